@@ -1,6 +1,12 @@
 /** The first timezone in the list, which will be the default value. */
 const FIRST_TIMEZONE = "Africa/Abidjan";
 
+/** How long to wait after typing before fetching suggestions, in milliseconds. */
+const SUGGEST_DELAY = 200;
+
+/** Pending timers to fetch suggestions, by kind. */
+const suggest_timers = new Map();
+
 function add_input(list, name, type, id, datalist) {
   var input = document.createElement("input");
   input.setAttribute("type", type);
@@ -52,6 +58,15 @@ function update_datetimes() {
   });
 }
 
+function update_repeats() {
+  let repeat = document.getElementById("repeat").value != "never";
+
+  Array.from(document.getElementsByClassName("repeats")).forEach((element) => {
+    element.style.display = repeat ? "grid" : "none";
+  });
+  document.getElementById("repeat_until").required = repeat;
+}
+
 function update_timezone() {
   let country = document.getElementById("country").value;
   let state = document.getElementById("state").value;
@@ -66,6 +81,39 @@ function update_timezone() {
   }
 }
 
+/**
+ * Fetches suggestions of the given kind for the value of the given input shortly, unless it changes
+ * again first.
+ */
+function update_suggestions(input, kind) {
+  clearTimeout(suggest_timers.get(kind));
+  suggest_timers.set(
+    kind,
+    setTimeout(() => fetch_suggestions(input, kind), SUGGEST_DELAY)
+  );
+}
+
+/** Fetches suggestions for the value of the given input, and puts them in its datalist. */
+async function fetch_suggestions(input, kind) {
+  let params = new URLSearchParams({ q: input.value });
+  if (kind == "cities") {
+    params.set("country", document.getElementById("country").value);
+    params.set("state", document.getElementById("state").value);
+  }
+  let response = await fetch("/api/suggest/" + kind + "?" + params);
+  if (!response.ok || input.list == null) {
+    return;
+  }
+  let suggestions = await response.json();
+  input.list.replaceChildren(
+    ...suggestions.map((suggestion) => {
+      let option = document.createElement("option");
+      option.value = suggestion.name;
+      return option;
+    })
+  );
+}
+
 function initialise() {
   document.getElementById("links_list").oninput = update_inputs;
   document.getElementById("bands_list").oninput = update_inputs;
@@ -74,6 +122,33 @@ function initialise() {
   document.getElementById("country").onchange = update_timezone;
   document.getElementById("state").onchange = update_timezone;
 
+  // Bands and callers inputs are added as needed, so listen on their lists.
+  document
+    .getElementById("bands_list")
+    .addEventListener("input", (event) =>
+      update_suggestions(event.target, "bands")
+    );
+  document
+    .getElementById("callers_list")
+    .addEventListener("input", (event) =>
+      update_suggestions(event.target, "callers")
+    );
+  let city = document.getElementById("city");
+  city.oninput = () => update_suggestions(city, "cities");
+  // Show the most common cities for the chosen country before anything is typed.
+  city.onfocus = () => fetch_suggestions(city, "cities");
+  let organisation = document.getElementById("organisation");
+  organisation.oninput = () =>
+    update_suggestions(organisation, "organisations");
+
+  // The repeat fields aren't shown when editing an existing event.
+  let repeat = document.getElementById("repeat");
+  if (repeat != null) {
+    document.getElementById("skip_dates_list").oninput = update_inputs;
+    repeat.onchange = update_repeats;
+    update_repeats();
+  }
+
   update_datetimes();
   update_timezone();
 }
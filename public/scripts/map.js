@@ -0,0 +1,41 @@
+/** The maximum radius of a city marker, in pixels. */
+const MAX_RADIUS = 20;
+
+function city_popup(properties) {
+  var link = document.createElement("a");
+  link.setAttribute("href", properties.link);
+  var events = properties.count == 1 ? " event" : " events";
+  link.textContent = properties.count + events + " in " + properties.city;
+  return link;
+}
+
+function show_cities(map, geojson) {
+  var layer = L.geoJSON(geojson, {
+    pointToLayer: function (feature, latlng) {
+      return L.circleMarker(latlng, {
+        radius: Math.min(MAX_RADIUS, 5 + 2 * Math.sqrt(feature.properties.count)),
+      });
+    },
+    onEachFeature: function (feature, layer) {
+      layer.bindPopup(city_popup(feature.properties));
+    },
+  }).addTo(map);
+  if (geojson.features.length > 0) {
+    map.fitBounds(layer.getBounds(), { maxZoom: 10, padding: [20, 20] });
+  }
+}
+
+function load_map() {
+  var element = document.getElementById("map");
+  var map = L.map(element).setView([30, 0], 2);
+  L.tileLayer("https://tile.openstreetmap.org/{z}/{x}/{y}.png", {
+    maxZoom: 19,
+    attribution:
+      '&copy; <a href="https://www.openstreetmap.org/copyright">OpenStreetMap</a> contributors',
+  }).addTo(map);
+  fetch(element.dataset.geojson)
+    .then((response) => response.json())
+    .then((geojson) => show_cities(map, geojson));
+}
+
+load_map();
@@ -0,0 +1,199 @@
+// Copyright 2024 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Links to add a single event to Google Calendar or Outlook.
+
+use crate::{
+    icalendar::{event_description, event_location, event_timezone},
+    model::event::{Event, EventTime},
+};
+use chrono::{Days, NaiveDate, Utc};
+use url::Url;
+
+const GOOGLE_CALENDAR_URL: &str = "https://calendar.google.com/calendar/render";
+const OUTLOOK_CALENDAR_URL: &str = "https://outlook.live.com/calendar/0/deeplink/compose";
+
+/// Returns a URL to add the given event to Google Calendar.
+pub fn google_calendar_url(event: &Event) -> String {
+    let mut params = vec![
+        ("action", "TEMPLATE".to_owned()),
+        ("text", event.name.clone()),
+        ("details", event_description(event)),
+        ("location", event_location(event)),
+    ];
+    match event.time {
+        EventTime::DateOnly {
+            start_date,
+            end_date,
+        } => {
+            // Google expects the end date of all-day events to be exclusive.
+            params.push((
+                "dates",
+                format!(
+                    "{}/{}",
+                    start_date.format("%Y%m%d"),
+                    exclusive_end_date(end_date).format("%Y%m%d")
+                ),
+            ));
+        }
+        EventTime::DateTime { start, end } => {
+            if let Some(timezone) = event_timezone(event) {
+                // Use local times in the event's timezone, so it shows the right zone.
+                params.push((
+                    "dates",
+                    format!(
+                        "{}/{}",
+                        start.format("%Y%m%dT%H%M%S"),
+                        end.format("%Y%m%dT%H%M%S")
+                    ),
+                ));
+                params.push(("ctz", timezone.name().to_owned()));
+            } else {
+                params.push((
+                    "dates",
+                    format!(
+                        "{}/{}",
+                        start.with_timezone(&Utc).format("%Y%m%dT%H%M%SZ"),
+                        end.with_timezone(&Utc).format("%Y%m%dT%H%M%SZ")
+                    ),
+                ));
+            }
+        }
+    }
+    Url::parse_with_params(GOOGLE_CALENDAR_URL, &params)
+        .unwrap()
+        .to_string()
+}
+
+/// Returns a URL to add the given event to Outlook.com.
+pub fn outlook_calendar_url(event: &Event) -> String {
+    let mut params = vec![
+        ("path", "/calendar/action/compose".to_owned()),
+        ("rru", "addevent".to_owned()),
+        ("subject", event.name.clone()),
+        ("body", event_description(event)),
+        ("location", event_location(event)),
+    ];
+    match event.time {
+        EventTime::DateOnly {
+            start_date,
+            end_date,
+        } => {
+            params.push(("startdt", start_date.to_string()));
+            // Outlook also expects the end date of all-day events to be exclusive.
+            params.push(("enddt", exclusive_end_date(end_date).to_string()));
+            params.push(("allday", "true".to_owned()));
+        }
+        EventTime::DateTime { start, end } => {
+            // Local times with the offset, so they are correct whatever timezone the user is in.
+            params.push(("startdt", start.to_rfc3339()));
+            params.push(("enddt", end.to_rfc3339()));
+        }
+    }
+    Url::parse_with_params(OUTLOOK_CALENDAR_URL, &params)
+        .unwrap()
+        .to_string()
+}
+
+/// Returns the day after the given inclusive end date.
+fn exclusive_end_date(end_date: NaiveDate) -> NaiveDate {
+    end_date.checked_add_days(Days::new(1)).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::dancestyle::DanceStyle;
+    use chrono::DateTime;
+
+    fn test_event(time: EventTime, country: &str) -> Event {
+        Event {
+            name: "Dance".to_string(),
+            details: None,
+            links: vec![],
+            time,
+            country: country.to_string(),
+            state: None,
+            city: "City".to_string(),
+            styles: vec![DanceStyle::Contra],
+            workshop: false,
+            social: true,
+            bands: vec![],
+            callers: vec![],
+            price: None,
+            organisation: None,
+            cancelled: false,
+            source: None,
+        }
+    }
+
+    fn param(url: &str, name: &str) -> Option<String> {
+        Url::parse(url)
+            .unwrap()
+            .query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    }
+
+    #[test]
+    fn date_only_links() {
+        let event = test_event(
+            EventTime::DateOnly {
+                start_date: NaiveDate::from_ymd_opt(2024, 1, 30).unwrap(),
+                end_date: NaiveDate::from_ymd_opt(2024, 1, 31).unwrap(),
+            },
+            "UK",
+        );
+
+        let google = google_calendar_url(&event);
+        assert_eq!(param(&google, "dates").unwrap(), "20240130/20240201");
+        assert_eq!(param(&google, "text").unwrap(), "Dance");
+        assert_eq!(param(&google, "location").unwrap(), "City, UK");
+
+        let outlook = outlook_calendar_url(&event);
+        assert_eq!(param(&outlook, "startdt").unwrap(), "2024-01-30");
+        assert_eq!(param(&outlook, "enddt").unwrap(), "2024-02-01");
+        assert_eq!(param(&outlook, "allday").unwrap(), "true");
+    }
+
+    #[test]
+    fn date_time_links() {
+        let time = EventTime::DateTime {
+            start: DateTime::parse_from_rfc3339("2024-07-01T19:30:00+01:00").unwrap(),
+            end: DateTime::parse_from_rfc3339("2024-07-01T22:00:00+01:00").unwrap(),
+        };
+
+        let google = google_calendar_url(&test_event(time.clone(), "UK"));
+        assert_eq!(
+            param(&google, "dates").unwrap(),
+            "20240701T193000/20240701T220000"
+        );
+        assert_eq!(param(&google, "ctz").unwrap(), "Europe/London");
+
+        // Without a known timezone, times are given in UTC.
+        let google = google_calendar_url(&test_event(time.clone(), "Nowhere"));
+        assert_eq!(
+            param(&google, "dates").unwrap(),
+            "20240701T183000Z/20240701T210000Z"
+        );
+        assert_eq!(param(&google, "ctz"), None);
+
+        let outlook = outlook_calendar_url(&test_event(time, "UK"));
+        assert_eq!(
+            param(&outlook, "startdt").unwrap(),
+            "2024-07-01T19:30:00+01:00"
+        );
+        assert_eq!(param(&outlook, "allday"), None);
+    }
+}
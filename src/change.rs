@@ -0,0 +1,182 @@
+// Copyright 2024 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Changes to events submitted by users, which may be proposed via a forge, committed to a local
+//! git repository or queued for moderation.
+
+use crate::{
+    forge::{add_events_message, cancel_message, edit_message, replace_event},
+    model::{event::Event, events::Events},
+};
+use eyre::{bail, Report};
+
+/// A change to the events in a single events file.
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Change {
+    /// Some new events to add to the file.
+    Add(Vec<Event>),
+    /// An edited version of an existing event.
+    Edit { original: Event, edited: Event },
+    /// An existing event to mark as cancelled, with the reason given by the user if any.
+    Cancel {
+        original: Event,
+        reason: Option<String>,
+    },
+}
+
+impl Change {
+    /// Returns the events as they will be after the change is made.
+    pub fn events(&self) -> Vec<Event> {
+        match self {
+            Self::Add(events) => events.clone(),
+            Self::Edit { edited, .. } => vec![edited.clone()],
+            Self::Cancel { original, .. } => vec![cancelled(original)],
+        }
+    }
+
+    /// Returns the existing event which the change replaces, if it isn't adding new events.
+    pub fn original(&self) -> Option<&Event> {
+        match self {
+            Self::Add(_) => None,
+            Self::Edit { original, .. } | Self::Cancel { original, .. } => Some(original),
+        }
+    }
+
+    /// Returns the action being taken, like "add", for use in branch names.
+    pub fn action(&self) -> &'static str {
+        match self {
+            Self::Add(_) => "add",
+            Self::Edit { .. } => "edit",
+            Self::Cancel { .. } => "cancel",
+        }
+    }
+
+    /// Returns the event which the change is about, for use in branch names.
+    ///
+    /// The events to add must not be empty.
+    pub fn subject(&self) -> &Event {
+        match self {
+            Self::Add(events) => &events[0],
+            Self::Edit { original, .. } | Self::Cancel { original, .. } => original,
+        }
+    }
+
+    /// Returns the commit message to use for the change.
+    ///
+    /// The events to add must not be empty.
+    pub fn message(&self) -> String {
+        match self {
+            Self::Add(events) => add_events_message(events),
+            Self::Edit { original, .. } => edit_message(original),
+            Self::Cancel { original, .. } => cancel_message(original),
+        }
+    }
+
+    /// Makes the change to the given events, and sorts them.
+    ///
+    /// Returns an error if there are no events to add, or the event to replace isn't there.
+    pub fn apply(&self, events: &mut Events) -> Result<(), Report> {
+        match self {
+            Self::Add(new_events) => {
+                if new_events.is_empty() {
+                    bail!("No events to add");
+                }
+                events.events.extend(new_events.iter().cloned());
+                events.sort();
+            }
+            Self::Edit { original, .. } | Self::Cancel { original, .. } => {
+                if !replace_event(events, original, self.events().remove(0)) {
+                    bail!("Event {} not found", original.name);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns a copy of the given event marked as cancelled.
+pub fn cancelled(event: &Event) -> Event {
+    Event {
+        cancelled: true,
+        source: None,
+        ..event.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{dancestyle::DanceStyle, event::EventTime};
+    use chrono::NaiveDate;
+
+    fn test_event(name: &str, day: u32) -> Event {
+        Event {
+            name: name.to_string(),
+            details: None,
+            links: vec![],
+            time: EventTime::DateOnly {
+                start_date: NaiveDate::from_ymd_opt(2030, 1, day).unwrap(),
+                end_date: NaiveDate::from_ymd_opt(2030, 1, day).unwrap(),
+            },
+            country: "UK".to_string(),
+            state: None,
+            city: "London".to_string(),
+            styles: vec![DanceStyle::Contra],
+            workshop: false,
+            social: true,
+            bands: vec![],
+            callers: vec![],
+            price: None,
+            organisation: None,
+            cancelled: false,
+            source: None,
+        }
+    }
+
+    #[test]
+    fn apply_changes() {
+        let existing = test_event("Existing", 2);
+        let mut events = Events {
+            events: vec![existing.clone()],
+        };
+
+        let new = test_event("New", 1);
+        Change::Add(vec![new.clone()]).apply(&mut events).unwrap();
+        assert_eq!(events.events, vec![new.clone(), existing.clone()]);
+
+        let edited = Event {
+            bands: vec!["Band".to_string()],
+            ..new.clone()
+        };
+        Change::Edit {
+            original: new.clone(),
+            edited: edited.clone(),
+        }
+        .apply(&mut events)
+        .unwrap();
+        assert_eq!(events.events, vec![edited.clone(), existing.clone()]);
+
+        let cancel = Change::Cancel {
+            original: existing.clone(),
+            reason: None,
+        };
+        cancel.apply(&mut events).unwrap();
+        assert_eq!(events.events, vec![edited, cancelled(&existing)]);
+
+        // The original event is no longer there to replace.
+        assert!(cancel.apply(&mut events).is_err());
+        assert!(Change::Add(vec![]).apply(&mut events).is_err());
+    }
+}
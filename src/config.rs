@@ -12,8 +12,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::model::filters::Filters;
 use eyre::{bail, Report, WrapErr};
-use serde::Deserialize;
+use serde::{de::Error, Deserialize, Deserializer};
 use std::{
     fs::read_to_string,
     net::SocketAddr,
@@ -35,8 +36,85 @@ pub struct Config {
     pub bind_address: SocketAddr,
     #[serde(default)]
     pub reload_token: String,
+    /// A GitHub repository to which to propose submitted events. At most one of `github`, `gitea`,
+    /// `gitlab` and `local_git` may be set.
     #[serde(default)]
     pub github: Option<GitHubConfig>,
+    /// A repository on a Gitea or Forgejo instance to which to propose submitted events.
+    #[serde(default)]
+    pub gitea: Option<GiteaConfig>,
+    /// A GitLab project to which to propose submitted events.
+    #[serde(default)]
+    pub gitlab: Option<GitLabConfig>,
+    /// A local git checkout of the events data repository to which to commit submitted events
+    /// directly, rather than proposing them on a forge.
+    #[serde(default)]
+    pub local_git: Option<LocalGitConfig>,
+    /// A local queue for submitted events to wait for a moderator to approve them. This is only
+    /// used if no forge (`github`, `gitea` or `gitlab`) or `local_git` is set.
+    #[serde(default)]
+    pub moderation: Option<ModerationConfig>,
+    /// Credentials for the `/admin/sources` page. If this is not set then the `moderation`
+    /// credentials are used instead.
+    #[serde(default)]
+    pub admin: Option<AdminConfig>,
+    /// Protection against spam submitted through the add form.
+    #[serde(default)]
+    pub spam: SpamConfig,
+    /// A JSON file in which to record when each event was first seen and last changed, so that
+    /// feeds can show recently added events and calendars can tell when events are updated. If this
+    /// is not set then the history is only kept in memory.
+    #[serde(default)]
+    pub history_file: Option<PathBuf>,
+    /// Profiles for sites served under particular hostnames.
+    #[serde(default)]
+    pub sites: Vec<SiteConfig>,
+    /// Named feeds with saved filters.
+    #[serde(default)]
+    pub feeds: Vec<FeedConfig>,
+}
+
+/// A profile for a community site served under a particular hostname.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SiteConfig {
+    /// The hostname for which to use this profile. Subdomains of it will also match.
+    pub host: String,
+    /// Filters to use for any filter which the request doesn't specify, in query string format.
+    #[serde(default, deserialize_with = "filters_from_query_string")]
+    pub default_filters: Filters,
+    /// The name of the site, shown at the top of each page.
+    #[serde(default)]
+    pub title: Option<String>,
+    /// The URL or path of a logo image to show next to the title.
+    #[serde(default)]
+    pub logo: Option<String>,
+    /// An email address to which people can send questions about the site.
+    #[serde(default)]
+    pub contact: Option<String>,
+}
+
+/// A named feed of events, served under `/feeds/`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FeedConfig {
+    /// The name of the feed, used in its URL such as `/feeds/{name}.ics`.
+    pub name: String,
+    /// The title of the feed. Defaults to a title generated from the filters.
+    #[serde(default)]
+    pub title: Option<String>,
+    /// The filters for events to include in the feed, in query string format.
+    #[serde(deserialize_with = "filters_from_query_string")]
+    pub filters: Filters,
+}
+
+impl FeedConfig {
+    /// Returns the title of the feed.
+    pub fn title(&self) -> String {
+        self.title
+            .clone()
+            .unwrap_or_else(|| self.filters.make_title())
+    }
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -50,6 +128,116 @@ pub struct GitHubConfig {
     pub private_key: PathBuf,
 }
 
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GiteaConfig {
+    /// The base URL of the Gitea or Forgejo instance, like `https://codeberg.org/`.
+    pub url: String,
+    pub owner: String,
+    pub repository: String,
+    #[serde(default = "default_main_branch")]
+    pub main_branch: String,
+    /// An access token with permission to write to the repository.
+    pub token: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GitLabConfig {
+    /// The base URL of the GitLab instance, like `https://gitlab.com/`.
+    pub url: String,
+    /// The path of the project, like `group/repository`.
+    pub project: String,
+    #[serde(default = "default_main_branch")]
+    pub main_branch: String,
+    /// An access token with permission to write to the project.
+    pub token: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LocalGitConfig {
+    /// The root of the git checkout. The `events` directory must be inside it.
+    pub repository: PathBuf,
+    /// The branch to which to commit events directly. This must be the branch checked out in
+    /// `repository`, and events are reloaded after each commit so they show up straight away.
+    ///
+    /// If this is not set then each submission is instead committed to a new branch from `HEAD`,
+    /// for someone to review and merge.
+    #[serde(default)]
+    pub branch: Option<String>,
+    /// The name to use as committer, and as author if the submitter doesn't give an email address.
+    #[serde(default = "default_committer_name")]
+    pub committer_name: String,
+    /// The email address to use as committer, and as author if the submitter doesn't give one.
+    #[serde(default = "default_committer_email")]
+    pub committer_email: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModerationConfig {
+    /// The directory in which to store submissions until they are approved or rejected.
+    pub queue_dir: PathBuf,
+    /// The username with which moderators log in to the `/admin` pages.
+    pub username: String,
+    /// The password with which moderators log in to the `/admin` pages.
+    pub password: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AdminConfig {
+    /// The username with which administrators log in to the `/admin/sources` page.
+    pub username: String,
+    /// The password with which administrators log in to the `/admin/sources` page.
+    pub password: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SpamConfig {
+    /// The secret key with which to sign the token included in the add form. If this is empty then
+    /// a random key is generated each time the server starts, so forms loaded before a restart
+    /// can't be submitted after it.
+    #[serde(default)]
+    pub secret: String,
+    /// The maximum number of submissions to accept from each IP address per hour, or 0 for no limit.
+    #[serde(default = "default_max_submissions_per_hour")]
+    pub max_submissions_per_hour: usize,
+    /// The minimum number of seconds between loading the add form and submitting it.
+    #[serde(default = "default_min_fill_seconds")]
+    pub min_fill_seconds: u64,
+    /// Domains which submitted events may not link to. Subdomains are also blocked.
+    #[serde(default)]
+    pub blocked_domains: Vec<String>,
+    /// Words or phrases which may not appear in submitted events, ignoring case.
+    #[serde(default)]
+    pub blocked_keywords: Vec<String>,
+    /// A file to which to append rejected submissions as JSON lines, for review. If this is not set
+    /// then they are only logged.
+    #[serde(default)]
+    pub rejected_log: Option<PathBuf>,
+    /// Whether to take the client's IP address from the `X-Forwarded-For` header, when the server is
+    /// behind a reverse proxy.
+    #[serde(default)]
+    pub trust_forwarded_for: bool,
+}
+
+impl Default for SpamConfig {
+    fn default() -> Self {
+        Self {
+            secret: String::new(),
+            max_submissions_per_hour: default_max_submissions_per_hour(),
+            min_fill_seconds: default_min_fill_seconds(),
+            blocked_domains: vec![],
+            blocked_keywords: vec![],
+            rejected_log: None,
+            trust_forwarded_for: false,
+        }
+    }
+}
+
 impl Config {
     pub fn from_file() -> Result<Config, Report> {
         for filename in &CONFIG_FILENAMES {
@@ -63,13 +251,62 @@ impl Config {
         );
     }
 
+    /// Returns the site profile for the given hostname, if there is one.
+    pub fn site_for_host(&self, hostname: &str) -> Option<&SiteConfig> {
+        self.sites.iter().find(|site| {
+            hostname == site.host
+                || hostname
+                    .strip_suffix(&site.host)
+                    .is_some_and(|prefix| prefix.ends_with('.'))
+        })
+    }
+
+    /// Returns the credentials for the admin pages, from the `admin` section if there is one or
+    /// else the `moderation` section.
+    pub fn admin_credentials(&self) -> Option<AdminConfig> {
+        self.admin.clone().or_else(|| {
+            self.moderation.as_ref().map(|moderation| AdminConfig {
+                username: moderation.username.clone(),
+                password: moderation.password.clone(),
+            })
+        })
+    }
+
+    /// Returns the feed with the given name, if there is one.
+    pub fn feed(&self, name: &str) -> Option<&FeedConfig> {
+        self.feeds.iter().find(|feed| feed.name == name)
+    }
+
     fn read(filename: &str) -> Result<Config, Report> {
         let config_file =
             read_to_string(filename).wrap_err_with(|| format!("Reading {}", filename))?;
-        Ok(toml::from_str(&config_file)?)
+        let config: Config = toml::from_str(&config_file)?;
+        config.check_forges()?;
+        Ok(config)
+    }
+
+    /// Checks that at most one forge or local git repository is configured.
+    fn check_forges(&self) -> Result<(), Report> {
+        let forges = [
+            self.github.is_some(),
+            self.gitea.is_some(),
+            self.gitlab.is_some(),
+            self.local_git.is_some(),
+        ];
+        if forges.into_iter().filter(|&configured| configured).count() > 1 {
+            bail!("At most one of github, gitea, gitlab and local_git may be configured");
+        }
+        Ok(())
     }
 }
 
+fn filters_from_query_string<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Filters, D::Error> {
+    let query_string = String::deserialize(deserializer)?;
+    serde_html_form::from_str(&query_string).map_err(D::Error::custom)
+}
+
 fn default_public_dir() -> PathBuf {
     Path::new("public").to_path_buf()
 }
@@ -86,9 +323,26 @@ fn default_main_branch() -> String {
     "main".to_string()
 }
 
+fn default_max_submissions_per_hour() -> usize {
+    5
+}
+
+fn default_min_fill_seconds() -> u64 {
+    5
+}
+
+fn default_committer_name() -> String {
+    "dancelist".to_string()
+}
+
+fn default_committer_email() -> String {
+    "dancelist@localhost".to_string()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::model::dancestyle::DanceStyle;
 
     /// Parsing the example config file should not give any errors.
     #[test]
@@ -101,4 +355,85 @@ mod tests {
     fn empty_config() {
         toml::from_str::<Config>("").unwrap();
     }
+
+    #[test]
+    fn multiple_forges() {
+        let config = toml::from_str::<Config>(
+            r#"
+            [gitea]
+            url = "https://codeberg.org/"
+            owner = "owner"
+            repository = "dancelist-data"
+            token = "token"
+
+            [gitlab]
+            url = "https://gitlab.com/"
+            project = "owner/dancelist-data"
+            token = "token"
+            "#,
+        )
+        .unwrap();
+        assert!(config.check_forges().is_err());
+    }
+
+    #[test]
+    fn site_for_host() {
+        let config = toml::from_str::<Config>(
+            r#"
+            [[sites]]
+            host = "balfolk.org"
+            default_filters = "styles=balfolk"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.site_for_host("balfolk.org").unwrap().default_filters,
+            Filters {
+                styles: [DanceStyle::Balfolk].into_iter().collect(),
+                ..Default::default()
+            }
+        );
+        assert!(config.site_for_host("www.balfolk.org").is_some());
+        assert!(config.site_for_host("notbalfolk.org").is_none());
+        assert!(config.site_for_host("folkdance.page").is_none());
+    }
+
+    #[test]
+    fn admin_credentials() {
+        let config = toml::from_str::<Config>("").unwrap();
+        assert_eq!(config.admin_credentials(), None);
+
+        let config = toml::from_str::<Config>(
+            r#"
+            [moderation]
+            queue_dir = "/tmp/queue"
+            username = "moderator"
+            password = "moderator password"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.admin_credentials(),
+            Some(AdminConfig {
+                username: "moderator".to_string(),
+                password: "moderator password".to_string(),
+            })
+        );
+
+        let config = toml::from_str::<Config>(
+            r#"
+            [admin]
+            username = "admin"
+            password = "admin password"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.admin_credentials(),
+            Some(AdminConfig {
+                username: "admin".to_string(),
+                password: "admin password".to_string(),
+            })
+        );
+    }
 }
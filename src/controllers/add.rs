@@ -12,32 +12,62 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::reload::reload_events;
 use crate::{
+    change::Change,
     config::Config,
     errors::InternalError,
-    github::{add_event_to_file, choose_file_for_event},
+    extractors::ClientIp,
+    forge::{self, choose_file_for_event, propose_change},
+    icalendar::event_timezone,
+    local_git::commit_change,
     model::{
         dancestyle::DanceStyle,
         event::{Event, EventTime},
-        events::{Band, Caller, Country, Events, Organisation},
+        events::{Country, Events},
         filters::Filters,
+        history::EventHistory,
     },
-    util::local_datetime_to_fixed_offset,
+    moderation::queue_change,
+    spam::{Rejection, SpamGuard},
+    util::{default_timezone, local_datetime_to_fixed_offset},
 };
 use askama::Template;
-use axum::{extract::State, response::Html};
+use axum::{
+    extract::{Path, State},
+    response::Html,
+};
 use axum_extra::extract::Form;
-use chrono::{NaiveDate, NaiveDateTime};
+use chrono::{Datelike, Days, Months, NaiveDate, NaiveDateTime, TimeDelta, Utc};
 use chrono_tz::Tz;
 use serde::{de::IntoDeserializer, Deserialize, Deserializer};
-use std::sync::Arc;
+use std::{
+    slice,
+    sync::{Arc, Mutex},
+};
 use url::Url;
 
-pub async fn add(events: Events) -> Result<Html<String>, InternalError> {
+/// The format used by `datetime-local` inputs.
+const DATETIME_LOCAL_FORMAT: &str = "%Y-%m-%dT%H:%M";
+
+/// The maximum number of events which may be added at once by repeating an event.
+const MAX_REPETITIONS: usize = 100;
+
+/// Events with times which are shorter than this get a warning in the preview.
+const SHORT_EVENT_MINUTES: i64 = 30;
+
+/// Events which last more than this many days get a warning in the preview.
+const LONG_EVENT_DAYS: i64 = 14;
+
+pub async fn add(
+    State(spam): State<Arc<SpamGuard>>,
+    events: Events,
+) -> Result<Html<String>, InternalError> {
     let template = AddTemplate::new(
         &events,
         AddForm {
             with_time: true,
+            form_token: spam.issue_token(Utc::now()),
             ..Default::default()
         },
         vec![],
@@ -45,71 +75,269 @@ pub async fn add(events: Events) -> Result<Html<String>, InternalError> {
     Ok(Html(template.render()?))
 }
 
+/// Shows the form pre-filled with the details of the existing event with the given UID.
+pub async fn edit(
+    State(spam): State<Arc<SpamGuard>>,
+    events: Events,
+    Path(uid): Path<String>,
+) -> Result<Html<String>, InternalError> {
+    let event = find_editable_event(&events, &uid)?;
+    let form = AddForm {
+        form_token: spam.issue_token(Utc::now()),
+        ..AddForm::from(event)
+    };
+    let template = AddTemplate {
+        editing: true,
+        ..AddTemplate::new(&events, form, vec![])
+    };
+    Ok(Html(template.render()?))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn submit_edit(
+    State(config): State<Arc<Config>>,
+    State(all_events): State<Arc<Mutex<Events>>>,
+    State(history): State<Arc<Mutex<EventHistory>>>,
+    State(spam): State<Arc<SpamGuard>>,
+    client: ClientIp,
+    events: Events,
+    Path(uid): Path<String>,
+    Form(form): Form<AddForm>,
+) -> Result<Html<String>, InternalError> {
+    let original = find_editable_event(&events, &uid)?;
+    let filename = original.source.as_deref().unwrap();
+    if form.action == FormAction::Edit {
+        let template = AddTemplate {
+            editing: true,
+            ..AddTemplate::new(&events, form, vec![])
+        };
+        return Ok(Html(template.render()?));
+    }
+    let edited = match Event::try_from(form.clone()) {
+        Ok(event) => Event {
+            // The form doesn't have a field for this, so keep it as it was.
+            cancelled: original.cancelled,
+            ..event
+        },
+        Err(errors) => {
+            let template = AddTemplate {
+                editing: true,
+                ..AddTemplate::new(&events, form, errors)
+            };
+            return Ok(Html(template.render()?));
+        }
+    };
+    if edited
+        == (Event {
+            source: None,
+            ..original.clone()
+        })
+    {
+        let template = AddTemplate {
+            editing: true,
+            ..AddTemplate::new(&events, form, vec!["No changes were made"])
+        };
+        return Ok(Html(template.render()?));
+    }
+    if form.action == FormAction::Preview {
+        let template = PreviewTemplate::new(&events, form, vec![edited], Some(original));
+        return Ok(Html(template.render()?));
+    }
+    check_spam(
+        &spam,
+        client,
+        &form.form_token,
+        &form.homepage,
+        form.email.as_deref(),
+        slice::from_ref(&edited),
+    )?;
+
+    let change = Change::Edit {
+        original: original.to_owned(),
+        edited: edited.clone(),
+    };
+    let submitted = submit_change(
+        change,
+        filename,
+        form.email.as_deref(),
+        &config,
+        &all_events,
+        &history,
+    )
+    .await?;
+    let template = SubmitTemplate {
+        submitted,
+        events: vec![edited],
+        edited: true,
+    };
+    Ok(Html(template.render()?))
+}
+
+/// Checks a submitted form with the given token and honeypot field value for spam. If it is
+/// rejected, logs it for review and returns an error saying why.
+pub fn check_spam(
+    spam: &SpamGuard,
+    ClientIp(client): ClientIp,
+    form_token: &str,
+    homepage: &str,
+    email: Option<&str>,
+    events: &[Event],
+) -> Result<(), InternalError> {
+    let Err(rejection) = spam.check(client, form_token, homepage, events, Utc::now()) else {
+        return Ok(());
+    };
+    spam.log_rejection(client, &rejection, email, events)
+        .map_err(InternalError::Internal)?;
+    Err(if rejection == Rejection::RateLimited {
+        InternalError::TooManyRequests
+    } else {
+        InternalError::BadRequest(rejection.to_string())
+    })
+}
+
+/// How a change submitted by a user was handled.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Submitted {
+    /// A PR was opened on the forge, with the given URL.
+    PullRequest(Url),
+    /// The change was committed to the given branch of the local git repository.
+    Committed(String),
+    /// The change was added to the local moderation queue.
+    Queued,
+    /// There is nowhere configured to submit changes to.
+    Failed,
+}
+
+/// Submits the given change to the given file, by opening a PR on the forge if one is configured,
+/// otherwise committing it to the local git repository, otherwise adding it to the moderation
+/// queue.
+///
+/// If the change is committed to the checked out branch of the local git repository then events
+/// are reloaded so that it shows up straight away.
+pub async fn submit_change(
+    change: Change,
+    filename: &str,
+    email: Option<&str>,
+    config: &Config,
+    events: &Mutex<Events>,
+    history: &Mutex<EventHistory>,
+) -> Result<Submitted, InternalError> {
+    if let Some(forge) = forge::connect(config).await? {
+        let pr = propose_change(change, filename, email, forge.as_ref()).await?;
+        Ok(Submitted::PullRequest(pr))
+    } else if let Some(local_git) = &config.local_git {
+        let branch = commit_change(&change, filename, email, &config.events, local_git)
+            .map_err(InternalError::Internal)?;
+        if local_git.branch.is_some() {
+            reload_events(events, history, config).await?;
+        }
+        Ok(Submitted::Committed(branch))
+    } else if let Some(moderation) = &config.moderation {
+        queue_change(change, filename, email, moderation).map_err(InternalError::Internal)?;
+        Ok(Submitted::Queued)
+    } else {
+        Ok(Submitted::Failed)
+    }
+}
+
+/// Finds the event with the given UID, as long as we know which file it came from so that it can
+/// be edited.
+pub fn find_editable_event<'a>(events: &'a Events, uid: &str) -> Result<&'a Event, InternalError> {
+    events
+        .events
+        .iter()
+        .find(|event| event.source.is_some() && event.uid() == uid)
+        .ok_or(InternalError::NotFound)
+}
+
 pub async fn submit(
     State(config): State<Arc<Config>>,
+    State(all_events): State<Arc<Mutex<Events>>>,
+    State(history): State<Arc<Mutex<EventHistory>>>,
+    State(spam): State<Arc<SpamGuard>>,
+    client: ClientIp,
     events: Events,
     Form(form): Form<AddForm>,
 ) -> Result<Html<String>, InternalError> {
-    match Event::try_from(form.clone()) {
-        Ok(event) => match choose_file_for_event(&events, &event) {
-            Ok(chosen_file) => {
-                let pr = if let Some(github) = &config.github {
-                    Some(
-                        add_event_to_file(
-                            event.clone(),
-                            &chosen_file,
-                            form.email.as_deref(),
-                            github,
-                        )
-                        .await?,
-                    )
-                } else {
-                    None
-                };
+    if form.action == FormAction::Edit {
+        let template = AddTemplate::new(&events, form, vec![]);
+        return Ok(Html(template.render()?));
+    }
+    let new_events = match Vec::<Event>::try_from(form.clone()) {
+        Ok(new_events) => new_events,
+        Err(errors) => {
+            let template = AddTemplate::new(&events, form, errors);
+            return Ok(Html(template.render()?));
+        }
+    };
+    if form.action == FormAction::Preview {
+        let template = PreviewTemplate::new(&events, form, new_events, None);
+        return Ok(Html(template.render()?));
+    }
+    check_spam(
+        &spam,
+        client,
+        &form.form_token,
+        &form.homepage,
+        form.email.as_deref(),
+        &new_events,
+    )?;
 
-                let template = SubmitTemplate { pr, event };
-                Ok(Html(template.render()?))
+    // All the events go in the same file as the first one, as they are a series.
+    let mut chosen_file = None;
+    for event in &new_events {
+        match choose_file_for_event(&events, event) {
+            Ok(file) => {
+                chosen_file.get_or_insert(file);
             }
             Err(duplicate) => {
                 let template = SubmitFailedTemplate {
-                    event: &event,
+                    event,
                     existing_event: &duplicate.existing,
                     merged: &duplicate.merged,
                 };
-                Ok(Html(template.render()?))
+                return Ok(Html(template.render()?));
             }
-        },
-        Err(errors) => {
-            let template = AddTemplate::new(&events, form, errors);
-            Ok(Html(template.render()?))
         }
     }
+    let chosen_file = chosen_file.unwrap();
+
+    let submitted = submit_change(
+        Change::Add(new_events.clone()),
+        &chosen_file,
+        form.email.as_deref(),
+        &config,
+        &all_events,
+        &history,
+    )
+    .await?;
+
+    let template = SubmitTemplate {
+        submitted,
+        events: new_events,
+        edited: false,
+    };
+    Ok(Html(template.render()?))
 }
 
 #[derive(Template)]
 #[template(path = "add.html")]
 struct AddTemplate {
     countries: Vec<Country>,
-    bands: Vec<Band>,
-    callers: Vec<Caller>,
-    organisations: Vec<Organisation>,
     form: AddForm,
     errors: Vec<&'static str>,
+    /// Whether the form is for editing an existing event rather than adding a new one.
+    editing: bool,
 }
 
 impl AddTemplate {
     fn new(events: &Events, form: AddForm, errors: Vec<&'static str>) -> Self {
         let countries = events.countries(&Filters::all());
-        let bands = events.bands();
-        let callers = events.callers();
-        let organisations = events.organisations();
         Self {
             countries,
-            bands,
-            callers,
-            organisations,
             form,
             errors,
+            editing: false,
         }
     }
 }
@@ -120,7 +348,7 @@ pub struct AddForm {
     name: String,
     #[serde(deserialize_with = "trim_non_empty")]
     details: Option<String>,
-    #[serde(deserialize_with = "trim_non_empty_vec")]
+    #[serde(default, deserialize_with = "trim_non_empty_vec")]
     links: Vec<String>,
     #[serde(default)]
     with_time: bool,
@@ -145,9 +373,9 @@ pub struct AddForm {
     workshop: bool,
     #[serde(default)]
     social: bool,
-    #[serde(deserialize_with = "trim_non_empty_vec")]
+    #[serde(default, deserialize_with = "trim_non_empty_vec")]
     bands: Vec<String>,
-    #[serde(deserialize_with = "trim_non_empty_vec")]
+    #[serde(default, deserialize_with = "trim_non_empty_vec")]
     callers: Vec<String>,
     #[serde(deserialize_with = "trim_non_empty")]
     price: Option<String>,
@@ -155,6 +383,24 @@ pub struct AddForm {
     organisation: Option<String>,
     #[serde(deserialize_with = "trim_non_empty")]
     email: Option<String>,
+    /// How often to repeat the event, if at all.
+    #[serde(default)]
+    repeat: Repeat,
+    /// The last date on which a repeated event may start.
+    #[serde(default, deserialize_with = "date_or_none")]
+    repeat_until: Option<NaiveDate>,
+    /// Dates on which a repeated event doesn't happen.
+    #[serde(default, deserialize_with = "dates_non_empty_vec")]
+    skip_dates: Vec<NaiveDate>,
+    /// The token issued by the server when the form was loaded.
+    #[serde(default)]
+    form_token: String,
+    /// A honeypot field hidden from people, which only bots fill in.
+    #[serde(default)]
+    homepage: String,
+    /// Which button was used to submit the form.
+    #[serde(default)]
+    action: FormAction,
 }
 
 impl AddForm {
@@ -188,7 +434,7 @@ impl AddForm {
 
     fn start_string(&self) -> String {
         if let Some(start) = self.start {
-            start.to_string()
+            start.format(DATETIME_LOCAL_FORMAT).to_string()
         } else {
             String::default()
         }
@@ -196,13 +442,208 @@ impl AddForm {
 
     fn end_string(&self) -> String {
         if let Some(end) = self.end {
-            end.to_string()
+            end.format(DATETIME_LOCAL_FORMAT).to_string()
         } else {
             String::default()
         }
     }
+
+    fn repeat_until_string(&self) -> String {
+        if let Some(repeat_until) = self.repeat_until {
+            repeat_until.to_string()
+        } else {
+            String::default()
+        }
+    }
+
+    /// Returns a copy of the form with the event's dates and times moved later by the given amount.
+    fn shifted(&self, delta: TimeDelta) -> Self {
+        Self {
+            start_date: self.start_date.map(|date| date + delta),
+            end_date: self.end_date.map(|date| date + delta),
+            start: self.start.map(|start| start + delta),
+            end: self.end.map(|end| end + delta),
+            ..self.clone()
+        }
+    }
+}
+
+/// What to do with a submitted form.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FormAction {
+    /// Show how the event will look, with any warnings, before submitting it.
+    #[default]
+    Preview,
+    /// Go back to the form from the preview to make changes.
+    Edit,
+    /// Submit the event after previewing it.
+    Submit,
+}
+
+/// How often an event repeats.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Repeat {
+    #[default]
+    Never,
+    /// Every week on the same day.
+    Weekly,
+    /// Every month on the same weekday of the same week, e.g. the second Friday.
+    MonthlyWeekday,
+    /// Every month on the last instance of the same weekday, e.g. the last Friday.
+    MonthlyLastWeekday,
+}
+
+impl Repeat {
+    pub const VALUES: [Self; 4] = [
+        Self::Never,
+        Self::Weekly,
+        Self::MonthlyWeekday,
+        Self::MonthlyLastWeekday,
+    ];
+
+    /// The value used in the form.
+    pub fn value(self) -> &'static str {
+        match self {
+            Self::Never => "never",
+            Self::Weekly => "weekly",
+            Self::MonthlyWeekday => "monthly_weekday",
+            Self::MonthlyLastWeekday => "monthly_last_weekday",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Never => "Don't repeat",
+            Self::Weekly => "Weekly",
+            Self::MonthlyWeekday => "Monthly on the same week and weekday",
+            Self::MonthlyLastWeekday => "Monthly on the last weekday",
+        }
+    }
+
+    /// Returns the dates matching the pattern which are between `start` and `until` inclusive, based
+    /// on the weekday and week of `start`.
+    ///
+    /// The first date will be `start` unless it doesn't match the pattern.
+    fn dates(self, start: NaiveDate, until: NaiveDate) -> Vec<NaiveDate> {
+        let weekday = start.weekday();
+        let mut dates = vec![];
+        match self {
+            Self::Never => dates.push(start),
+            Self::Weekly => {
+                dates.extend(start.iter_weeks().take_while(|date| *date <= until));
+            }
+            Self::MonthlyWeekday | Self::MonthlyLastWeekday => {
+                let week = (start.day0() / 7 + 1) as u8;
+                let mut month = start.with_day(1).unwrap();
+                while month <= until {
+                    let date = if self == Self::MonthlyWeekday {
+                        // Some months don't have a fifth instance of the weekday, so skip them.
+                        NaiveDate::from_weekday_of_month_opt(
+                            month.year(),
+                            month.month(),
+                            weekday,
+                            week,
+                        )
+                    } else {
+                        let last_day = month + Months::new(1) - Days::new(1);
+                        let days_back = last_day.weekday().days_since(weekday);
+                        Some(last_day - Days::new(days_back.into()))
+                    };
+                    dates.extend(date.filter(|date| *date >= start && *date <= until));
+                    month = month + Months::new(1);
+                }
+            }
+        }
+        dates
+    }
+}
+
+impl From<&Event> for AddForm {
+    fn from(event: &Event) -> Self {
+        let mut form = Self {
+            name: event.name.clone(),
+            details: event.details.clone(),
+            links: event.links.clone(),
+            country: event.country.clone(),
+            state: event.state.clone(),
+            city: event.city.clone(),
+            styles: event.styles.clone(),
+            workshop: event.workshop,
+            social: event.social,
+            bands: event.bands.clone(),
+            callers: event.callers.clone(),
+            price: event.price.clone(),
+            organisation: event.organisation.clone(),
+            ..Default::default()
+        };
+        match event.time {
+            EventTime::DateOnly {
+                start_date,
+                end_date,
+            } => {
+                form.start_date = Some(start_date);
+                form.end_date = Some(end_date);
+            }
+            EventTime::DateTime { start, end } => {
+                form.with_time = true;
+                // Use UTC if the event's times don't match the default timezone for its location.
+                let timezone = event_timezone(event).unwrap_or(Tz::UTC);
+                form.start = Some(start.with_timezone(&timezone).naive_local());
+                form.end = Some(end.with_timezone(&timezone).naive_local());
+                form.timezone = Some(timezone);
+            }
+        }
+        form
+    }
+}
+
+/// Converts the form to a list of events, repeating the event as requested.
+impl TryFrom<AddForm> for Vec<Event> {
+    type Error = Vec<&'static str>;
+
+    fn try_from(form: AddForm) -> Result<Self, Self::Error> {
+        let first = Event::try_from(form.clone())?;
+        if form.repeat == Repeat::Never {
+            return Ok(vec![first]);
+        }
+
+        let start_date = first.time.start_date();
+        let until = form
+            .repeat_until
+            .ok_or_else(|| vec!["Missing date to repeat until"])?;
+        if until < start_date {
+            return Err(vec!["Must repeat until a date after the start."]);
+        }
+        let dates = form.repeat.dates(start_date, until);
+        if dates.first() != Some(&start_date) {
+            return Err(vec!["Start date doesn't match the way the event repeats."]);
+        }
+        if dates.len() > MAX_REPETITIONS {
+            return Err(vec![
+                "Too many repetitions, please choose an earlier end date.",
+            ]);
+        }
+        if form.skip_dates.iter().any(|date| !dates.contains(date)) {
+            return Err(vec![
+                "Skipped dates must be dates on which the event repeats.",
+            ]);
+        }
+
+        let events = dates
+            .into_iter()
+            .filter(|date| !form.skip_dates.contains(date))
+            .map(|date| Event::try_from(form.shifted(date - start_date)))
+            .collect::<Result<Vec<_>, _>>()?;
+        if events.is_empty() {
+            return Err(vec!["All dates were skipped."]);
+        }
+        Ok(events)
+    }
 }
 
+/// Converts the form to a single event, ignoring any repetition.
 impl TryFrom<AddForm> for Event {
     type Error = Vec<&'static str>;
 
@@ -262,11 +703,104 @@ impl TryFrom<AddForm> for Event {
     }
 }
 
+#[derive(Template)]
+#[template(path = "add_preview.html")]
+struct PreviewTemplate {
+    form: AddForm,
+    events: Vec<Event>,
+    warnings: Vec<&'static str>,
+    /// Existing events which look like they might be the same as one being added.
+    duplicates: Vec<Event>,
+    /// Whether the form is for editing an existing event rather than adding a new one.
+    editing: bool,
+}
+
+impl PreviewTemplate {
+    /// Checks the given new events for warnings and likely duplicates. If an existing event is
+    /// being edited then it is passed as `original`, so it isn't counted as a duplicate.
+    fn new(
+        events: &Events,
+        form: AddForm,
+        new_events: Vec<Event>,
+        original: Option<&Event>,
+    ) -> Self {
+        // All events in a series are the same apart from their dates, so only check the first.
+        let warnings = preview_warnings(&new_events[0]);
+        let duplicates = events
+            .events
+            .iter()
+            .filter(|existing| {
+                Some(*existing) != original
+                    && new_events
+                        .iter()
+                        .any(|event| likely_duplicate(existing, event))
+            })
+            .cloned()
+            .collect();
+        Self {
+            form,
+            events: new_events,
+            warnings,
+            duplicates,
+            editing: original.is_some(),
+        }
+    }
+}
+
+/// Returns warnings about things which are allowed, but might be a mistake.
+fn preview_warnings(event: &Event) -> Vec<&'static str> {
+    let mut warnings = vec![];
+    if event.links.is_empty() {
+        warnings.push("There are no links to more details of the event.");
+    }
+    if event.price.is_none() {
+        warnings.push("No price is given.");
+    }
+    match event.time {
+        EventTime::DateOnly {
+            start_date,
+            end_date,
+        } => {
+            if (end_date - start_date).num_days() >= LONG_EVENT_DAYS {
+                warnings.push("The event lasts for more than two weeks.");
+            }
+        }
+        EventTime::DateTime { start, end } => {
+            let duration = end - start;
+            if duration.num_minutes() < SHORT_EVENT_MINUTES {
+                warnings.push("The event lasts for less than half an hour.");
+            } else if duration.num_days() >= LONG_EVENT_DAYS {
+                warnings.push("The event lasts for more than two weeks.");
+            }
+            if default_timezone(&event.country, event.state.as_deref()).is_some()
+                && event_timezone(event).is_none()
+            {
+                warnings.push("The times are not in the usual timezone for the location.");
+            }
+        }
+    }
+    warnings
+}
+
+/// Returns whether the existing event is in the same place as the new one on an overlapping day,
+/// so might be the same event.
+fn likely_duplicate(existing: &Event, event: &Event) -> bool {
+    let (start, end) = event.local_date_range();
+    let (existing_start, existing_end) = existing.local_date_range();
+    existing.country == event.country
+        && existing.state == event.state
+        && existing.city.eq_ignore_ascii_case(&event.city)
+        && existing_start <= end
+        && existing_end >= start
+}
+
 #[derive(Template)]
 #[template(path = "submit.html")]
 struct SubmitTemplate {
-    pr: Option<Url>,
-    event: Event,
+    submitted: Submitted,
+    events: Vec<Event>,
+    /// Whether an existing event was edited rather than a new one added.
+    edited: bool,
 }
 
 #[derive(Template)]
@@ -277,7 +811,7 @@ struct SubmitFailedTemplate<'a> {
     merged: &'a Event,
 }
 
-fn trim<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+pub fn trim<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
     Ok(String::deserialize(deserializer)?.trim().to_string())
 }
 
@@ -290,7 +824,9 @@ fn trimmed_non_empty(s: String) -> Option<String> {
     }
 }
 
-fn trim_non_empty<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+pub fn trim_non_empty<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
     let s = Option::<String>::deserialize(deserializer)?;
     Ok(s.and_then(trimmed_non_empty))
 }
@@ -300,6 +836,16 @@ fn trim_non_empty_vec<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<
     Ok(s.into_iter().filter_map(trimmed_non_empty).collect())
 }
 
+fn dates_non_empty_vec<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<NaiveDate>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .into_iter()
+        .filter_map(trimmed_non_empty)
+        .map(|date| NaiveDate::deserialize(date.into_deserializer()))
+        .collect()
+}
+
 fn date_or_none<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<NaiveDate>, D::Error> {
     if let Some(str) = Option::<String>::deserialize(deserializer)? {
         if str.is_empty() {
@@ -328,8 +874,274 @@ fn datetime_or_none<'de, D: Deserializer<'de>>(
     }
 }
 
-mod filters {
+pub mod filters {
     pub fn checked_if_true(value: bool) -> askama::Result<&'static str> {
         Ok(if value { "checked=\"checked\"" } else { "" })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+
+    #[test]
+    fn form_from_event_round_trip() {
+        let event = Event {
+            name: "Dance".to_string(),
+            details: Some("Details".to_string()),
+            links: vec!["https://example.com/".to_string()],
+            time: EventTime::DateTime {
+                start: DateTime::parse_from_rfc3339("2024-07-01T19:30:00+01:00").unwrap(),
+                end: DateTime::parse_from_rfc3339("2024-07-01T22:00:00+01:00").unwrap(),
+            },
+            country: "UK".to_string(),
+            state: None,
+            city: "London".to_string(),
+            styles: vec![DanceStyle::Contra],
+            workshop: true,
+            social: true,
+            bands: vec!["Band".to_string()],
+            callers: vec!["Caller".to_string()],
+            price: Some("£10".to_string()),
+            organisation: Some("Org".to_string()),
+            cancelled: false,
+            source: None,
+        };
+
+        let form = AddForm::from(&event);
+        assert_eq!(form.timezone, Some(Tz::Europe__London));
+        assert_eq!(form.start_string(), "2024-07-01T19:30");
+        assert_eq!(Event::try_from(form), Ok(event));
+    }
+
+    #[test]
+    fn preview_without_event_links() {
+        let event = Event {
+            name: "Dance".to_string(),
+            details: None,
+            links: vec![],
+            time: EventTime::DateOnly {
+                start_date: date(2030, 1, 4),
+                end_date: date(2030, 1, 4),
+            },
+            country: "UK".to_string(),
+            state: None,
+            city: "London".to_string(),
+            styles: vec![DanceStyle::Contra],
+            workshop: false,
+            social: true,
+            bands: vec![],
+            callers: vec![],
+            price: None,
+            organisation: None,
+            cancelled: false,
+            source: Some("events/uk/london.yaml".to_string()),
+        };
+        let existing = Event {
+            name: "Existing dance".to_string(),
+            ..event.clone()
+        };
+        let events = Events {
+            events: vec![existing.clone()],
+        };
+
+        let template = PreviewTemplate::new(&events, AddForm::default(), vec![event.clone()], None);
+        assert_eq!(template.duplicates, vec![existing.clone()]);
+        let html = template.render().unwrap();
+        // The previewed event doesn't exist yet, so there are no links to it.
+        assert!(!html.contains(&format!("/events/{}", event.uid())));
+        assert!(!html.contains(&format!("/edit/{}", event.uid())));
+        // The possible duplicate does exist.
+        assert!(html.contains(&format!("/events/{}.ics", existing.uid())));
+        assert!(html.contains(&format!("/edit/{}", existing.uid())));
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn repeat_dates() {
+        assert_eq!(
+            Repeat::Weekly.dates(date(2030, 1, 4), date(2030, 1, 25)),
+            vec![
+                date(2030, 1, 4),
+                date(2030, 1, 11),
+                date(2030, 1, 18),
+                date(2030, 1, 25)
+            ]
+        );
+        // The second Friday of each month.
+        assert_eq!(
+            Repeat::MonthlyWeekday.dates(date(2030, 1, 11), date(2030, 3, 8)),
+            vec![date(2030, 1, 11), date(2030, 2, 8), date(2030, 3, 8)]
+        );
+        // Months without a fifth Thursday are skipped.
+        assert_eq!(
+            Repeat::MonthlyWeekday.dates(date(2030, 1, 31), date(2030, 5, 31)),
+            vec![date(2030, 1, 31), date(2030, 5, 30)]
+        );
+        assert_eq!(
+            Repeat::MonthlyLastWeekday.dates(date(2030, 1, 25), date(2030, 3, 31)),
+            vec![date(2030, 1, 25), date(2030, 2, 22), date(2030, 3, 29)]
+        );
+        // The start date isn't the last Friday, so isn't included.
+        assert_eq!(
+            Repeat::MonthlyLastWeekday.dates(date(2030, 1, 18), date(2030, 1, 31)),
+            vec![date(2030, 1, 25)]
+        );
+    }
+
+    #[test]
+    fn repeat_form() {
+        let form = AddForm {
+            name: "Dance".to_string(),
+            links: vec!["https://example.com/".to_string()],
+            with_time: true,
+            start: Some(date(2030, 3, 22).and_hms_opt(19, 30, 0).unwrap()),
+            end: Some(date(2030, 3, 22).and_hms_opt(22, 0, 0).unwrap()),
+            timezone: Some(Tz::Europe__London),
+            country: "UK".to_string(),
+            city: "London".to_string(),
+            styles: vec![DanceStyle::Contra],
+            social: true,
+            repeat: Repeat::Weekly,
+            repeat_until: Some(date(2030, 4, 5)),
+            skip_dates: vec![date(2030, 3, 29)],
+            ..Default::default()
+        };
+
+        let events = Vec::<Event>::try_from(form.clone()).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events[0].time,
+            EventTime::DateTime {
+                start: DateTime::parse_from_rfc3339("2030-03-22T19:30:00Z").unwrap(),
+                end: DateTime::parse_from_rfc3339("2030-03-22T22:00:00Z").unwrap(),
+            }
+        );
+        // Summer time has started, but the local time stays the same.
+        assert_eq!(
+            events[1].time,
+            EventTime::DateTime {
+                start: DateTime::parse_from_rfc3339("2030-04-05T19:30:00+01:00").unwrap(),
+                end: DateTime::parse_from_rfc3339("2030-04-05T22:00:00+01:00").unwrap(),
+            }
+        );
+
+        assert_eq!(
+            Vec::<Event>::try_from(AddForm {
+                repeat_until: None,
+                ..form.clone()
+            }),
+            Err(vec!["Missing date to repeat until"])
+        );
+        assert_eq!(
+            Vec::<Event>::try_from(AddForm {
+                skip_dates: vec![date(2030, 3, 30)],
+                ..form
+            }),
+            Err(vec![
+                "Skipped dates must be dates on which the event repeats."
+            ])
+        );
+    }
+
+    fn timed_event(start: &str, end: &str) -> Event {
+        Event {
+            name: "Dance".to_string(),
+            details: None,
+            links: vec!["https://example.com/".to_string()],
+            time: EventTime::DateTime {
+                start: DateTime::parse_from_rfc3339(start).unwrap(),
+                end: DateTime::parse_from_rfc3339(end).unwrap(),
+            },
+            country: "UK".to_string(),
+            state: None,
+            city: "London".to_string(),
+            styles: vec![DanceStyle::Contra],
+            workshop: false,
+            social: true,
+            bands: vec![],
+            callers: vec![],
+            price: Some("£10".to_string()),
+            organisation: None,
+            cancelled: false,
+            source: None,
+        }
+    }
+
+    #[test]
+    fn warnings() {
+        let event = timed_event("2030-07-01T19:30:00+01:00", "2030-07-01T22:00:00+01:00");
+        assert_eq!(preview_warnings(&event), Vec::<&str>::new());
+
+        assert_eq!(
+            preview_warnings(&Event {
+                links: vec![],
+                price: None,
+                ..event.clone()
+            }),
+            vec![
+                "There are no links to more details of the event.",
+                "No price is given."
+            ]
+        );
+        assert_eq!(
+            preview_warnings(&timed_event(
+                "2030-07-01T19:30:00+01:00",
+                "2030-07-01T19:40:00+01:00"
+            )),
+            vec!["The event lasts for less than half an hour."]
+        );
+        assert_eq!(
+            preview_warnings(&timed_event(
+                "2030-07-01T19:30:00-04:00",
+                "2030-07-01T22:00:00-04:00"
+            )),
+            vec!["The times are not in the usual timezone for the location."]
+        );
+        assert_eq!(
+            preview_warnings(&Event {
+                time: EventTime::DateOnly {
+                    start_date: date(2030, 7, 1),
+                    end_date: date(2030, 7, 31),
+                },
+                ..event
+            }),
+            vec!["The event lasts for more than two weeks."]
+        );
+    }
+
+    #[test]
+    fn duplicates() {
+        let event = timed_event("2030-07-01T19:30:00+01:00", "2030-07-01T22:00:00+01:00");
+        let other = Event {
+            name: "Other dance".to_string(),
+            ..timed_event("2030-07-01T14:00:00+01:00", "2030-07-01T17:00:00+01:00")
+        };
+        assert!(likely_duplicate(&other, &event));
+        assert!(likely_duplicate(
+            &Event {
+                time: EventTime::DateOnly {
+                    start_date: date(2030, 6, 30),
+                    end_date: date(2030, 7, 2),
+                },
+                ..other.clone()
+            },
+            &event
+        ));
+        assert!(!likely_duplicate(
+            &Event {
+                city: "Oxford".to_string(),
+                ..other.clone()
+            },
+            &event
+        ));
+        assert!(!likely_duplicate(
+            &timed_event("2030-07-02T19:30:00+01:00", "2030-07-02T22:00:00+01:00"),
+            &event
+        ));
+    }
+}
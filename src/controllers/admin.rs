@@ -0,0 +1,85 @@
+// Copyright 2024 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Pages for moderators to approve or reject submissions in the local queue.
+
+use super::{add::trim_non_empty, reload::reload_events};
+use crate::{
+    config::Config,
+    errors::InternalError,
+    extractors::Moderator,
+    model::{events::Events, history::EventHistory},
+    moderation::{
+        approve_submission, get_submission, pending_submissions, reject_submission, Submission,
+    },
+};
+use askama::Template;
+use axum::{
+    extract::{Path, State},
+    response::{Html, Redirect},
+};
+use axum_extra::extract::Form;
+use serde::Deserialize;
+use std::sync::{Arc, Mutex};
+
+const SUBMISSIONS_PATH: &str = "/admin/submissions";
+
+pub async fn submissions(Moderator(moderation): Moderator) -> Result<Html<String>, InternalError> {
+    let submissions = pending_submissions(&moderation).map_err(InternalError::Internal)?;
+    let template = SubmissionsTemplate { submissions };
+    Ok(Html(template.render()?))
+}
+
+/// Adds the events of the submission to the appropriate file, then reloads events so they show up
+/// straight away.
+pub async fn approve(
+    Moderator(moderation): Moderator,
+    State(config): State<Arc<Config>>,
+    State(events): State<Arc<Mutex<Events>>>,
+    State(history): State<Arc<Mutex<EventHistory>>>,
+    Path(id): Path<String>,
+) -> Result<Redirect, InternalError> {
+    let submission = get_submission(&id, &moderation)
+        .map_err(InternalError::Internal)?
+        .ok_or(InternalError::NotFound)?;
+    approve_submission(&submission, &config.events, &moderation)
+        .map_err(InternalError::Internal)?;
+    reload_events(&events, &history, &config).await?;
+    Ok(Redirect::to(SUBMISSIONS_PATH))
+}
+
+pub async fn reject(
+    Moderator(moderation): Moderator,
+    Path(id): Path<String>,
+    Form(form): Form<RejectForm>,
+) -> Result<Redirect, InternalError> {
+    let submission = get_submission(&id, &moderation)
+        .map_err(InternalError::Internal)?
+        .ok_or(InternalError::NotFound)?;
+    reject_submission(submission, form.note, &moderation).map_err(InternalError::Internal)?;
+    Ok(Redirect::to(SUBMISSIONS_PATH))
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+pub struct RejectForm {
+    /// Why the submission was rejected.
+    #[serde(default, deserialize_with = "trim_non_empty")]
+    note: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "admin_submissions.html")]
+struct SubmissionsTemplate {
+    submissions: Vec<Submission>,
+}
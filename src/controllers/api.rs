@@ -0,0 +1,681 @@
+// Copyright 2024 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A versioned JSON API for apps, with pagination and facets.
+
+use crate::{
+    errors::InternalError,
+    model::{
+        dancestyle::DanceStyle, event::Event, events::Events, filters::Filters,
+        history::EventHistory,
+    },
+    syndication::{sort_events, FeedOrder},
+};
+use axum::{extract::State, http::header, response::IntoResponse};
+use axum_extra::extract::Query;
+use schemars::{gen::SchemaSettings, JsonSchema};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// The number of events to return per page if no limit is given.
+const DEFAULT_LIMIT: usize = 50;
+/// The maximum number of events which may be requested per page.
+const MAX_LIMIT: usize = 500;
+
+/// The query parameters for filtering events, with descriptions for the OpenAPI document. These
+/// must be kept in sync with the fields of `Filters`, which the `filter_parameters_complete` test
+/// checks.
+const FILTER_PARAMETERS: [(&str, &str); 26] = [
+    (
+        "date",
+        "Whether to include `future` (the default), `past` or `all` events.",
+    ),
+    (
+        "country",
+        "Only include events in any of the given countries.",
+    ),
+    (
+        "not_country",
+        "Exclude events in any of the given countries.",
+    ),
+    ("state", "Only include events in any of the given states."),
+    ("not_state", "Exclude events in any of the given states."),
+    ("city", "Only include events in any of the given cities."),
+    ("not_city", "Exclude events in any of the given cities."),
+    (
+        "near",
+        "Only include events near the given `latitude,longitude`.",
+    ),
+    (
+        "near_city",
+        "Only include events near the given city, like `Bristol` or `Portland, OR, USA`.",
+    ),
+    (
+        "radius",
+        "The radius in kilometres for `near` and `near_city`.",
+    ),
+    (
+        "styles",
+        "Only include events with any of the given dance styles, by tag.",
+    ),
+    (
+        "not_styles",
+        "Exclude events with any of the given dance styles, by tag.",
+    ),
+    (
+        "weekdays",
+        "Only include events which are on any of the given days of the week.",
+    ),
+    (
+        "start_after",
+        "Only include events which start at or after the given local time of day, as `HH:MM`.",
+    ),
+    (
+        "start_before",
+        "Only include events which start before the given local time of day, as `HH:MM`.",
+    ),
+    (
+        "multiday",
+        "Whether to include only multi-day or only single-day events.",
+    ),
+    (
+        "workshop",
+        "Whether to include only events with or without workshops.",
+    ),
+    (
+        "social",
+        "Whether to include only events with or without social dancing.",
+    ),
+    ("band", "Only include events with any of the given bands."),
+    ("not_band", "Exclude events with any of the given bands."),
+    (
+        "caller",
+        "Only include events with any of the given callers.",
+    ),
+    (
+        "not_caller",
+        "Exclude events with any of the given callers.",
+    ),
+    (
+        "organisation",
+        "Only include events run by any of the given organisations.",
+    ),
+    (
+        "not_organisation",
+        "Exclude events run by any of the given organisations.",
+    ),
+    (
+        "cancelled",
+        "Whether to include only cancelled or only non-cancelled events.",
+    ),
+    (
+        "source",
+        "Only include events from sources matching the given prefix or glob.",
+    ),
+];
+
+/// Options for which page of events to return, and in what order.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+pub struct PageOptions {
+    /// The `next_cursor` from the previous page, which is the offset of the first event on the next
+    /// page.
+    #[serde(default)]
+    cursor: Option<String>,
+    /// The maximum number of events to return.
+    #[serde(default)]
+    limit: Option<usize>,
+    #[serde(default)]
+    sort: FeedOrder,
+}
+
+impl PageOptions {
+    fn limit(&self) -> Result<usize, InternalError> {
+        match self.limit {
+            Some(0) => Err(InternalError::BadRequest(
+                "Limit must be at least 1".to_string(),
+            )),
+            limit => Ok(limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT)),
+        }
+    }
+}
+
+/// A page of events matching a query, along with facets for refining it.
+#[derive(Clone, Debug, JsonSchema, PartialEq, Serialize)]
+pub struct EventsResponse {
+    /// The total number of events matching the filters, across all pages.
+    pub total: usize,
+    /// The cursor to pass to get the next page, if there is one.
+    pub next_cursor: Option<String>,
+    pub events: Vec<ApiEvent>,
+    pub facets: Facets,
+}
+
+/// An event along with some fields computed from it.
+///
+/// The event isn't flattened into this, because its schema doesn't allow additional properties.
+#[derive(Clone, Debug, JsonSchema, PartialEq, Serialize)]
+pub struct ApiEvent {
+    /// A stable identifier for the event.
+    pub uid: String,
+    pub event: Event,
+    /// Whether the event lasts more than one day.
+    pub multiday: bool,
+    /// The event's main link, if it has one other than Facebook and similar.
+    pub main_link: Option<String>,
+}
+
+impl From<&Event> for ApiEvent {
+    fn from(event: &Event) -> Self {
+        Self {
+            uid: event.uid(),
+            event: event.to_owned(),
+            multiday: event.multiday(),
+            main_link: event.main_link().cloned(),
+        }
+    }
+}
+
+/// Counts of matching events for each possible value of some filters.
+///
+/// The counts for each filter ignore that filter itself, so they show how many events there would
+/// be if it were changed.
+#[derive(Clone, Debug, Default, JsonSchema, PartialEq, Serialize)]
+pub struct Facets {
+    pub countries: Vec<CountryFacet>,
+    pub styles: Vec<StyleFacet>,
+}
+
+#[derive(Clone, Debug, JsonSchema, PartialEq, Serialize)]
+pub struct CountryFacet {
+    pub name: String,
+    pub count: usize,
+    pub states: Vec<StateFacet>,
+    /// Cities which are not in any state.
+    pub cities: Vec<CityFacet>,
+}
+
+#[derive(Clone, Debug, JsonSchema, PartialEq, Serialize)]
+pub struct StateFacet {
+    pub name: String,
+    pub count: usize,
+    pub cities: Vec<CityFacet>,
+}
+
+#[derive(Clone, Debug, JsonSchema, PartialEq, Serialize)]
+pub struct CityFacet {
+    pub name: String,
+    pub count: usize,
+}
+
+#[derive(Clone, Debug, JsonSchema, PartialEq, Serialize)]
+pub struct StyleFacet {
+    pub style: DanceStyle,
+    pub count: usize,
+}
+
+/// Returns a page of events matching the given filters.
+pub async fn events(
+    State(history): State<Arc<Mutex<EventHistory>>>,
+    events: Events,
+    Query(filters): Query<Filters>,
+    Query(options): Query<PageOptions>,
+) -> Result<impl IntoResponse, InternalError> {
+    let response = {
+        let history = history.lock().unwrap();
+        events_page(&events, &filters, &options, &history)?
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/json"),
+            (header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"),
+        ],
+        serde_json::to_string(&response)?,
+    ))
+}
+
+/// Returns an OpenAPI document describing the API.
+pub async fn openapi() -> Result<impl IntoResponse, InternalError> {
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/json"),
+            (header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"),
+        ],
+        serde_json::to_string_pretty(&openapi_document())?,
+    ))
+}
+
+fn events_page(
+    events: &Events,
+    filters: &Filters,
+    options: &PageOptions,
+    history: &EventHistory,
+) -> Result<EventsResponse, InternalError> {
+    let mut matching = events.matching(filters);
+    sort_events(&mut matching, history, options.sort);
+
+    let limit = options.limit()?;
+    let start = if let Some(cursor) = &options.cursor {
+        cursor
+            .parse::<usize>()
+            .ok()
+            .filter(|start| *start <= matching.len())
+            .ok_or_else(|| InternalError::BadRequest(format!("Invalid cursor {:?}", cursor)))?
+    } else {
+        0
+    };
+    let end = (start + limit).min(matching.len());
+    let page = &matching[start..end];
+    let next_cursor = if end < matching.len() {
+        Some(end.to_string())
+    } else {
+        None
+    };
+
+    Ok(EventsResponse {
+        total: matching.len(),
+        next_cursor,
+        events: page.iter().map(|event| ApiEvent::from(*event)).collect(),
+        facets: facets(events, filters),
+    })
+}
+
+/// Counts events by location and style for the given filters.
+fn facets(events: &Events, filters: &Filters) -> Facets {
+    let location_filters = filters.with_country(None);
+    let mut country_counts = HashMap::<&str, usize>::new();
+    let mut state_counts = HashMap::<(&str, &str), usize>::new();
+    let mut city_counts = HashMap::<(&str, Option<&str>, &str), usize>::new();
+    for event in events.matching(&location_filters) {
+        *country_counts.entry(&event.country).or_default() += 1;
+        if let Some(state) = &event.state {
+            *state_counts.entry((&event.country, state)).or_default() += 1;
+        }
+        *city_counts
+            .entry((&event.country, event.state.as_deref(), &event.city))
+            .or_default() += 1;
+    }
+    let city_facets = |country: &str, state: Option<&str>, cities: Vec<String>| {
+        cities
+            .into_iter()
+            .map(|city| CityFacet {
+                count: city_counts
+                    .get(&(country, state, city.as_str()))
+                    .copied()
+                    .unwrap_or(0),
+                name: city,
+            })
+            .collect()
+    };
+    let countries = events
+        .countries(&location_filters)
+        .into_iter()
+        .map(|country| CountryFacet {
+            count: country_counts
+                .get(country.name.as_str())
+                .copied()
+                .unwrap_or(0),
+            states: country
+                .states
+                .into_iter()
+                .map(|state| StateFacet {
+                    count: state_counts
+                        .get(&(country.name.as_str(), state.name.as_str()))
+                        .copied()
+                        .unwrap_or(0),
+                    cities: city_facets(&country.name, Some(&state.name), state.cities),
+                    name: state.name,
+                })
+                .collect(),
+            cities: city_facets(&country.name, None, country.cities),
+            name: country.name,
+        })
+        .collect();
+
+    let style_filters = filters.with_style(None);
+    let style_events = events.matching(&style_filters);
+    let styles = events
+        .styles(&style_filters)
+        .into_iter()
+        .map(|style| StyleFacet {
+            style,
+            count: style_events
+                .iter()
+                .filter(|event| event.styles.contains(&style))
+                .count(),
+        })
+        .collect();
+
+    Facets { countries, styles }
+}
+
+/// Makes an OpenAPI 3.0 document describing the API, with schemas generated from the response
+/// types.
+fn openapi_document() -> Value {
+    let mut generator = SchemaSettings::openapi3().into_generator();
+    let response_schema = generator.subschema_for::<EventsResponse>();
+    let schemas = generator.take_definitions();
+
+    let mut parameters = FILTER_PARAMETERS
+        .iter()
+        .map(|(name, description)| query_parameter(name, description, json!({ "type": "string" })))
+        .collect::<Vec<_>>();
+    parameters.push(query_parameter(
+        "cursor",
+        "The `next_cursor` from the previous page.",
+        json!({ "type": "string" }),
+    ));
+    parameters.push(query_parameter(
+        "limit",
+        "The maximum number of events to return.",
+        json!({ "type": "integer", "minimum": 1, "maximum": MAX_LIMIT, "default": DEFAULT_LIMIT }),
+    ));
+    parameters.push(query_parameter(
+        "sort",
+        "The order in which to return events.",
+        json!({ "type": "string", "enum": ["start", "added", "location"], "default": "start" }),
+    ));
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Folk dance events",
+            "version": "1.0.0",
+        },
+        "paths": {
+            "/api/v1/events": {
+                "get": {
+                    "summary": "List events matching the given filters.",
+                    "parameters": parameters,
+                    "responses": {
+                        "200": {
+                            "description": "A page of matching events.",
+                            "content": {
+                                "application/json": {
+                                    "schema": response_schema,
+                                },
+                            },
+                        },
+                        "400": {
+                            "description": "The cursor or limit is invalid.",
+                        },
+                    },
+                },
+            },
+        },
+        "components": {
+            "schemas": Map::from_iter(
+                schemas
+                    .into_iter()
+                    .map(|(name, schema)| (name, serde_json::to_value(schema).unwrap()))
+            ),
+        },
+    })
+}
+
+fn query_parameter(name: &str, description: &str, schema: Value) -> Value {
+    json!({
+        "name": name,
+        "in": "query",
+        "description": description,
+        "required": false,
+        "schema": schema,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        geo::Coordinates,
+        model::{event::EventTime, filters::DateFilter},
+    };
+    use chrono::{NaiveDate, NaiveTime, Weekday};
+    use std::collections::BTreeSet;
+
+    fn test_event(name: &str, country: &str, city: &str, style: DanceStyle) -> Event {
+        Event {
+            name: name.to_string(),
+            details: None,
+            links: vec![],
+            time: EventTime::DateOnly {
+                start_date: NaiveDate::from_ymd_opt(2100, 1, 1).unwrap(),
+                end_date: NaiveDate::from_ymd_opt(2100, 1, 1).unwrap(),
+            },
+            country: country.to_string(),
+            state: None,
+            city: city.to_string(),
+            styles: vec![style],
+            workshop: false,
+            social: true,
+            bands: vec![],
+            callers: vec![],
+            price: None,
+            organisation: None,
+            cancelled: false,
+            source: None,
+        }
+    }
+
+    #[test]
+    fn paginate() {
+        let events = Events {
+            events: vec![
+                test_event("A", "UK", "London", DanceStyle::Contra),
+                test_event("B", "UK", "Bristol", DanceStyle::Contra),
+                test_event("C", "Belgium", "Gent", DanceStyle::Balfolk),
+            ],
+        };
+        let history = EventHistory::default();
+        let options = PageOptions {
+            limit: Some(2),
+            sort: FeedOrder::Location,
+            ..Default::default()
+        };
+
+        let first = events_page(&events, &Filters::default(), &options, &history).unwrap();
+        assert_eq!(first.total, 3);
+        assert_eq!(
+            first
+                .events
+                .iter()
+                .map(|event| event.event.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["C", "B"]
+        );
+        assert_eq!(first.next_cursor.as_deref(), Some("2"));
+
+        let second = events_page(
+            &events,
+            &Filters::default(),
+            &PageOptions {
+                cursor: first.next_cursor,
+                ..options.clone()
+            },
+            &history,
+        )
+        .unwrap();
+        assert_eq!(second.events.len(), 1);
+        assert_eq!(second.events[0].event.name, "A");
+        assert_eq!(second.next_cursor, None);
+
+        assert!(matches!(
+            events_page(
+                &events,
+                &Filters::default(),
+                &PageOptions {
+                    cursor: Some("unknown".to_string()),
+                    ..options.clone()
+                },
+                &history,
+            ),
+            Err(InternalError::BadRequest(_))
+        ));
+        assert!(matches!(
+            events_page(
+                &events,
+                &Filters::default(),
+                &PageOptions {
+                    limit: Some(0),
+                    ..options
+                },
+                &history,
+            ),
+            Err(InternalError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn paginate_same_uid() {
+        // These have the same UID, but a client following the cursor must still get both.
+        let event = test_event("A", "UK", "London", DanceStyle::Contra);
+        let events = Events {
+            events: vec![event.clone(), event.clone(), event],
+        };
+        let history = EventHistory::default();
+        let mut options = PageOptions {
+            limit: Some(2),
+            ..Default::default()
+        };
+        let mut count = 0;
+        loop {
+            let page = events_page(&events, &Filters::default(), &options, &history).unwrap();
+            count += page.events.len();
+            let Some(cursor) = page.next_cursor else {
+                break;
+            };
+            options.cursor = Some(cursor);
+        }
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn facet_counts() {
+        let events = Events {
+            events: vec![
+                test_event("A", "UK", "London", DanceStyle::Contra),
+                test_event("B", "UK", "London", DanceStyle::Balfolk),
+                test_event("C", "Belgium", "Gent", DanceStyle::Balfolk),
+            ],
+        };
+        let filters = Filters {
+            countries: vec!["UK".to_string()],
+            styles: [DanceStyle::Balfolk].into_iter().collect(),
+            ..Default::default()
+        };
+
+        let facets = facets(&events, &filters);
+        // Countries are counted ignoring the country filter, but with the style filter.
+        assert_eq!(
+            facets.countries,
+            vec![
+                CountryFacet {
+                    name: "Belgium".to_string(),
+                    count: 1,
+                    states: vec![],
+                    cities: vec![CityFacet {
+                        name: "Gent".to_string(),
+                        count: 1
+                    }],
+                },
+                CountryFacet {
+                    name: "UK".to_string(),
+                    count: 1,
+                    states: vec![],
+                    cities: vec![CityFacet {
+                        name: "London".to_string(),
+                        count: 1
+                    }],
+                },
+            ]
+        );
+        // Styles are counted ignoring the style filter, but with the country filter.
+        assert_eq!(
+            facets.styles,
+            vec![
+                StyleFacet {
+                    style: DanceStyle::Balfolk,
+                    count: 1
+                },
+                StyleFacet {
+                    style: DanceStyle::Contra,
+                    count: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn openapi_schemas() {
+        let document = openapi_document();
+        assert_eq!(
+            document["paths"]["/api/v1/events"]["get"]["responses"]["200"]["content"]
+                ["application/json"]["schema"]["$ref"],
+            "#/components/schemas/EventsResponse"
+        );
+        assert!(document["components"]["schemas"]["ApiEvent"].is_object());
+    }
+
+    /// Every filter which can be serialised should be documented, and nothing else.
+    #[test]
+    fn filter_parameters_complete() {
+        // Don't use `..Default::default()` here, so that adding a field to `Filters` means it must
+        // be added here too.
+        let filters = Filters {
+            date: DateFilter::All,
+            countries: vec!["UK".to_string()],
+            not_countries: vec!["USA".to_string()],
+            states: vec!["State".to_string()],
+            not_states: vec!["Other state".to_string()],
+            cities: vec!["London".to_string()],
+            not_cities: vec!["Bristol".to_string()],
+            near: Some(Coordinates {
+                latitude: 51.5,
+                longitude: -0.1,
+            }),
+            near_city: Some("London".to_string()),
+            radius: Some(10),
+            styles: [DanceStyle::Contra].into_iter().collect(),
+            not_styles: [DanceStyle::Balfolk].into_iter().collect(),
+            weekdays: [Weekday::Mon].into_iter().collect(),
+            start_after: Some(NaiveTime::from_hms_opt(18, 0, 0).unwrap()),
+            start_before: Some(NaiveTime::from_hms_opt(23, 0, 0).unwrap()),
+            multiday: Some(false),
+            workshop: Some(true),
+            social: Some(true),
+            bands: vec!["Band".to_string()],
+            not_bands: vec!["Other band".to_string()],
+            callers: vec!["Caller".to_string()],
+            not_callers: vec!["Other caller".to_string()],
+            organisations: vec!["Organisation".to_string()],
+            not_organisations: vec!["Other organisation".to_string()],
+            cancelled: Some(false),
+            source: Some("events/".to_string()),
+        };
+        let query_pairs: Vec<(String, String)> =
+            serde_html_form::from_str(&filters.to_query_string().unwrap()).unwrap();
+        let serialised_keys = query_pairs
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect::<BTreeSet<_>>();
+        let documented_keys = FILTER_PARAMETERS
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .collect::<BTreeSet<_>>();
+        assert_eq!(documented_keys, serialised_keys);
+    }
+}
@@ -0,0 +1,75 @@
+// Copyright 2024 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Detail pages for a band, caller or organisation, listing all their events.
+
+use super::index::{group_by_month, site_query, sort_and_group_by_month, Month};
+use crate::{
+    errors::InternalError,
+    extractors::Site,
+    model::{
+        events::{Appearances, Events},
+        filters::Filters,
+    },
+};
+use askama::Template;
+use axum::response::Html;
+
+/// Renders a page with all past and future events matching the given filters, which should pick
+/// out the band, caller or organisation with the given name.
+///
+/// `kind` is what sort of entity it is, like "Band".
+pub fn appearances_page(
+    site: Site,
+    events: &Events,
+    kind: &str,
+    name: String,
+    filters: Filters,
+) -> Result<Html<String>, InternalError> {
+    let appearances = events
+        .appearances(&filters)
+        .ok_or(InternalError::NotFound)?;
+    let upcoming_months = sort_and_group_by_month(appearances.upcoming.iter().collect());
+    // Past events are already sorted with the most recent first.
+    let past_months = group_by_month(appearances.past.iter().collect());
+    let template = AppearancesTemplate {
+        site,
+        kind: kind.to_owned(),
+        name,
+        filters,
+        appearances,
+        upcoming_months,
+        past_months,
+    };
+    Ok(Html(template.render()?))
+}
+
+#[derive(Template)]
+#[template(path = "appearances.html")]
+struct AppearancesTemplate {
+    site: Site,
+    kind: String,
+    name: String,
+    filters: Filters,
+    appearances: Appearances,
+    upcoming_months: Vec<Month>,
+    past_months: Vec<Month>,
+}
+
+impl AppearancesTemplate {
+    /// Returns the query string to link to the given filters on this site.
+    fn query(&self, filters: Filters) -> String {
+        site_query(&self.site, &filters)
+    }
+}
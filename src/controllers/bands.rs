@@ -12,21 +12,40 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::appearances::appearances_page;
 use crate::{
     errors::InternalError,
-    model::events::{Band, Events},
+    extractors::Site,
+    model::{
+        events::{Band, Events},
+        filters::Filters,
+    },
 };
 use askama::Template;
-use axum::response::Html;
+use axum::{extract::Path, response::Html};
 
-pub async fn bands(events: Events) -> Result<Html<String>, InternalError> {
+pub async fn bands(site: Site, events: Events) -> Result<Html<String>, InternalError> {
     let bands = events.bands();
-    let template = BandsTemplate { bands };
+    let template = BandsTemplate { site, bands };
     Ok(Html(template.render()?))
 }
 
+/// Shows all past and future events for the band with the given name.
+pub async fn band(
+    site: Site,
+    events: Events,
+    Path(name): Path<String>,
+) -> Result<Html<String>, InternalError> {
+    let filters = Filters {
+        bands: vec![name.clone()],
+        ..Default::default()
+    };
+    appearances_page(site, &events, "Band", name, filters)
+}
+
 #[derive(Template)]
 #[template(path = "bands.html")]
 struct BandsTemplate {
+    site: Site,
     bands: Vec<Band>,
 }
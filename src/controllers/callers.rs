@@ -12,21 +12,40 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::appearances::appearances_page;
 use crate::{
     errors::InternalError,
-    model::events::{Caller, Events},
+    extractors::Site,
+    model::{
+        events::{Caller, Events},
+        filters::Filters,
+    },
 };
 use askama::Template;
-use axum::response::Html;
+use axum::{extract::Path, response::Html};
 
-pub async fn callers(events: Events) -> Result<Html<String>, InternalError> {
+pub async fn callers(site: Site, events: Events) -> Result<Html<String>, InternalError> {
     let callers = events.callers();
-    let template = CallersTemplate { callers };
+    let template = CallersTemplate { site, callers };
     Ok(Html(template.render()?))
 }
 
+/// Shows all past and future events for the caller with the given name.
+pub async fn caller(
+    site: Site,
+    events: Events,
+    Path(name): Path<String>,
+) -> Result<Html<String>, InternalError> {
+    let filters = Filters {
+        callers: vec![name.clone()],
+        ..Default::default()
+    };
+    appearances_page(site, &events, "Caller", name, filters)
+}
+
 #[derive(Template)]
 #[template(path = "callers.html")]
 struct CallersTemplate {
+    site: Site,
     callers: Vec<Caller>,
 }
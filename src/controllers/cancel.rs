@@ -0,0 +1,120 @@
+// Copyright 2024 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A form for users to report that an event has been cancelled.
+
+use super::add::{check_spam, find_editable_event, submit_change, trim_non_empty, Submitted};
+use crate::{
+    change::Change,
+    config::Config,
+    errors::InternalError,
+    extractors::ClientIp,
+    model::{event::Event, events::Events, history::EventHistory},
+    spam::SpamGuard,
+};
+use askama::Template;
+use axum::{
+    extract::{Path, State},
+    response::Html,
+};
+use axum_extra::extract::Form;
+use chrono::Utc;
+use serde::Deserialize;
+use std::sync::{Arc, Mutex};
+
+pub async fn cancel(
+    State(spam): State<Arc<SpamGuard>>,
+    events: Events,
+    Path(uid): Path<String>,
+) -> Result<Html<String>, InternalError> {
+    let event = find_editable_event(&events, &uid)?.to_owned();
+    let template = CancelTemplate {
+        event,
+        form_token: spam.issue_token(Utc::now()),
+    };
+    Ok(Html(template.render()?))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn submit(
+    State(config): State<Arc<Config>>,
+    State(all_events): State<Arc<Mutex<Events>>>,
+    State(history): State<Arc<Mutex<EventHistory>>>,
+    State(spam): State<Arc<SpamGuard>>,
+    client: ClientIp,
+    events: Events,
+    Path(uid): Path<String>,
+    Form(form): Form<CancelForm>,
+) -> Result<Html<String>, InternalError> {
+    let event = find_editable_event(&events, &uid)?;
+    if event.cancelled {
+        return Err(InternalError::BadRequest(
+            "Event is already cancelled".to_string(),
+        ));
+    }
+    let change = Change::Cancel {
+        original: event.to_owned(),
+        reason: form.reason,
+    };
+    check_spam(
+        &spam,
+        client,
+        &form.form_token,
+        &form.homepage,
+        form.email.as_deref(),
+        &change.events(),
+    )?;
+    let submitted = submit_change(
+        change,
+        event.source.as_deref().unwrap(),
+        form.email.as_deref(),
+        &config,
+        &all_events,
+        &history,
+    )
+    .await?;
+    let template = CancelSubmittedTemplate {
+        submitted,
+        event: event.to_owned(),
+    };
+    Ok(Html(template.render()?))
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+pub struct CancelForm {
+    #[serde(default, deserialize_with = "trim_non_empty")]
+    reason: Option<String>,
+    #[serde(default, deserialize_with = "trim_non_empty")]
+    email: Option<String>,
+    /// The token issued by the server when the form was loaded.
+    #[serde(default)]
+    form_token: String,
+    /// A honeypot field hidden from people, which only bots fill in.
+    #[serde(default)]
+    homepage: String,
+}
+
+#[derive(Template)]
+#[template(path = "cancel.html")]
+struct CancelTemplate {
+    event: Event,
+    form_token: String,
+}
+
+#[derive(Template)]
+#[template(path = "cancel_submitted.html")]
+struct CancelSubmittedTemplate {
+    submitted: Submitted,
+    event: Event,
+}
@@ -14,6 +14,7 @@
 
 use crate::{
     errors::InternalError,
+    extractors::Site,
     model::{
         events::{Country, Events},
         filters::Filters,
@@ -22,14 +23,15 @@ use crate::{
 use askama::Template;
 use axum::response::Html;
 
-pub async fn cities(events: Events) -> Result<Html<String>, InternalError> {
+pub async fn cities(site: Site, events: Events) -> Result<Html<String>, InternalError> {
     let countries = events.countries(&Filters::all());
-    let template = CitiesTemplate { countries };
+    let template = CitiesTemplate { site, countries };
     Ok(Html(template.render()?))
 }
 
 #[derive(Template)]
 #[template(path = "cities.html")]
 struct CitiesTemplate {
+    site: Site,
     countries: Vec<Country>,
 }
@@ -0,0 +1,167 @@
+// Copyright 2024 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A widget which other sites can embed to show a list of events.
+
+use super::index::{hide_cancelled_by_default, site_filters, site_query, sorted_matching};
+use crate::{
+    errors::InternalError,
+    extractors::Site,
+    model::{event::Event, events::Events, filters::Filters},
+};
+use askama::Template;
+use axum::{
+    extract::RawQuery,
+    http::header,
+    response::{Html, IntoResponse},
+};
+use axum_extra::{extract::Query, headers::Host, TypedHeader};
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+/// The number of events to show if no limit is given.
+const DEFAULT_LIMIT: usize = 10;
+/// The maximum number of events which may be requested.
+const MAX_LIMIT: usize = 100;
+
+/// Options for how to show the embedded events.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+pub struct EmbedOptions {
+    /// The maximum number of events to show.
+    #[serde(default)]
+    limit: Option<usize>,
+    #[serde(default)]
+    layout: Layout,
+}
+
+impl EmbedOptions {
+    fn limit(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Layout {
+    /// Just the time, name, location and styles of each event.
+    #[default]
+    Compact,
+    /// Also the bands, callers and price.
+    Full,
+}
+
+/// Renders a minimal HTML page without styles or scripts, suitable for use in an iframe.
+pub async fn embed(
+    site: Site,
+    events: Events,
+    RawQuery(query): RawQuery,
+    Query(options): Query<EmbedOptions>,
+    TypedHeader(host): TypedHeader<Host>,
+) -> Result<Html<String>, InternalError> {
+    let filters = site_filters(&site, query.as_deref())?;
+    let days = group_by_day(embedded_events(&events, filters.clone(), &options));
+    let template = EmbedTemplate {
+        site,
+        host: host.to_string(),
+        title: filters.make_title(),
+        filters,
+        days,
+        full: options.layout == Layout::Full,
+    };
+    Ok(Html(template.render()?))
+}
+
+/// Returns the same events as JSON, with CORS headers so that other sites can fetch them from
+/// scripts and render them however they like.
+pub async fn embed_json(
+    site: Site,
+    events: Events,
+    RawQuery(query): RawQuery,
+    Query(options): Query<EmbedOptions>,
+) -> Result<impl IntoResponse, InternalError> {
+    let filters = site_filters(&site, query.as_deref())?;
+    let events = Events::cloned(embedded_events(&events, filters, &options));
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/json"),
+            (header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"),
+        ],
+        serde_json::to_string(&events)?,
+    ))
+}
+
+/// Returns the first events matching the given filters, up to the limit.
+fn embedded_events<'a>(
+    events: &'a Events,
+    mut filters: Filters,
+    options: &EmbedOptions,
+) -> Vec<&'a Event> {
+    hide_cancelled_by_default(&mut filters);
+    let mut events = sorted_matching(events, &filters);
+    events.truncate(options.limit());
+    events
+}
+
+/// Groups the given events, which must already be sorted, by their start date.
+fn group_by_day(events: Vec<&Event>) -> Vec<Day> {
+    let mut days: Vec<Day> = vec![];
+    for event in events {
+        let date = event.time.start_date();
+        match days.last_mut() {
+            Some(day) if day.date == date => day.events.push(event.to_owned()),
+            _ => days.push(Day {
+                date,
+                events: vec![event.to_owned()],
+            }),
+        }
+    }
+    days
+}
+
+#[derive(Template)]
+#[template(path = "embed.html")]
+struct EmbedTemplate {
+    site: Site,
+    host: String,
+    title: String,
+    filters: Filters,
+    days: Vec<Day>,
+    full: bool,
+}
+
+impl EmbedTemplate {
+    /// Returns the query string to link to the given filters on this site.
+    fn query(&self, filters: Filters) -> String {
+        site_query(&self.site, &filters)
+    }
+}
+
+struct Day {
+    date: NaiveDate,
+    events: Vec<Event>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_options() {
+        let options: EmbedOptions =
+            serde_html_form::from_str("country=UK&limit=500&layout=full").unwrap();
+        assert_eq!(options.layout, Layout::Full);
+        assert_eq!(options.limit(), MAX_LIMIT);
+        assert_eq!(EmbedOptions::default().limit(), DEFAULT_LIMIT);
+    }
+}
@@ -13,57 +13,74 @@
 // limitations under the License.
 
 use crate::{
+    calendar_links::{google_calendar_url, outlook_calendar_url},
+    config::Config,
     errors::InternalError,
+    extractors::Site,
+    geojson::{cities_to_geojson, events_to_geojson, Cluster, GeoJson, GeoJsonOptions},
     icalendar::{events_to_calendar, Ics},
     model::{
         dancestyle::DanceStyle,
         event::Event,
         events::{Country, Events},
         filters::Filters,
+        history::EventHistory,
     },
+    syndication::{events_to_atom, events_to_rss, sort_events, Atom, FeedOptions, Rss},
 };
 use askama::Template;
-use axum::{extract::Query, response::Html};
-use axum_extra::{headers::Host, TypedHeader};
-use chrono::{Datelike, Months, NaiveDate};
+use axum::{
+    extract::{Path, RawQuery, State},
+    http::header,
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use axum_extra::{extract::Query, headers::Host, TypedHeader};
+use chrono::{Datelike, Months, NaiveDate, Utc};
+use std::sync::{Arc, Mutex};
 
 pub async fn index(
+    site: Site,
     events: Events,
-    Query(filters): Query<Filters>,
+    RawQuery(query): RawQuery,
     TypedHeader(host): TypedHeader<Host>,
 ) -> Result<Html<String>, InternalError> {
-    index_html(events, filters, host, false).await
+    index_html(site, events, query, host, false).await
 }
 
 pub async fn calendar(
+    site: Site,
     events: Events,
-    Query(filters): Query<Filters>,
+    RawQuery(query): RawQuery,
     TypedHeader(host): TypedHeader<Host>,
 ) -> Result<Html<String>, InternalError> {
-    index_html(events, filters, host, true).await
+    index_html(site, events, query, host, true).await
+}
+
+/// Shows the cities with events matching the filters on a map.
+pub async fn map(site: Site, RawQuery(query): RawQuery) -> Result<Html<String>, InternalError> {
+    let filters = site_filters(&site, query.as_deref())?;
+    let template = MapTemplate { site, filters };
+    Ok(Html(template.render()?))
 }
 
 pub async fn index_html(
+    site: Site,
     events: Events,
-    mut filters: Filters,
+    query: Option<String>,
     host: Host,
     calendar: bool,
 ) -> Result<Html<String>, InternalError> {
-    let has_filters = filters.has_some();
-
-    if host.hostname().contains("balfolk.org") && filters.styles.is_empty() {
-        // Default to only showing Balfolk events.
-        filters.styles = [DanceStyle::Balfolk].into_iter().collect();
-    }
+    let has_filters = site_filters(&Site::default(), query.as_deref())?.has_some();
+    let filters = site_filters(&site, query.as_deref())?;
 
     let countries = events.countries(&filters.with_country(None));
-    let states = if filters.country.is_some() {
+    let states = if !filters.countries.is_empty() {
         events.states(&filters.with_state(None))
     } else {
         vec![]
     };
     let styles = events.styles(&filters.with_style(None));
-    let cities = if filters.country.is_some() {
+    let cities = if !filters.countries.is_empty() {
         events.cities(&filters.with_city(None))
     } else {
         vec![]
@@ -71,6 +88,8 @@ pub async fn index_html(
     let events = events.matching(&filters);
     let months = sort_and_group_by_month(events);
     let template = IndexTemplate {
+        site,
+        host: host.to_string(),
         filters,
         months,
         has_filters,
@@ -83,54 +102,316 @@ pub async fn index_html(
     Ok(Html(template.render()?))
 }
 
+/// Parses the filters from the given query string, with the site's default filters for any which
+/// it doesn't include.
+pub fn site_filters(site: &Site, query: Option<&str>) -> Result<Filters, InternalError> {
+    Filters::from_query_string_with_defaults(query.unwrap_or_default(), &site.default_filters())
+        .map_err(|e| InternalError::BadRequest(format!("Invalid filters: {}", e)))
+}
+
 pub async fn index_json(
+    site: Site,
     events: Events,
-    Query(filters): Query<Filters>,
+    RawQuery(query): RawQuery,
 ) -> Result<String, InternalError> {
-    let mut events = events.matching(&filters);
-    events.sort_by_key(|event| event.time.start_time_sort_key());
-    let events = Events::cloned(events);
-    Ok(serde_json::to_string(&events)?)
+    let filters = site_filters(&site, query.as_deref())?;
+    events_json(&events, &filters)
 }
 
 pub async fn index_toml(
+    site: Site,
     events: Events,
-    Query(filters): Query<Filters>,
+    RawQuery(query): RawQuery,
 ) -> Result<String, InternalError> {
-    let mut events = events.matching(&filters);
-    events.sort_by_key(|event| event.time.start_time_sort_key());
-    let events = Events::cloned(events);
-    Ok(toml::to_string(&events)?)
+    let filters = site_filters(&site, query.as_deref())?;
+    events_toml(&events, &filters)
 }
 
 pub async fn index_yaml(
+    site: Site,
     events: Events,
-    Query(filters): Query<Filters>,
+    RawQuery(query): RawQuery,
 ) -> Result<String, InternalError> {
-    let mut events = events.matching(&filters);
-    events.sort_by_key(|event| event.time.start_time_sort_key());
-    let events = Events::cloned(events);
-    Ok(serde_yaml::to_string(&events)?)
+    let filters = site_filters(&site, query.as_deref())?;
+    events_yaml(&events, &filters)
+}
+
+pub async fn index_csv(
+    site: Site,
+    events: Events,
+    RawQuery(query): RawQuery,
+) -> Result<Response, InternalError> {
+    let filters = site_filters(&site, query.as_deref())?;
+    Ok(events_csv(&events, &filters)?.into_response())
+}
+
+pub async fn index_geojson(
+    site: Site,
+    events: Events,
+    RawQuery(query): RawQuery,
+    Query(options): Query<GeoJsonOptions>,
+) -> Result<GeoJson, InternalError> {
+    let filters = site_filters(&site, query.as_deref())?;
+    events_geojson(&events, filters, &options, &site)
 }
 
 pub async fn index_ics(
+    site: Site,
+    State(history): State<Arc<Mutex<EventHistory>>>,
     events: Events,
-    Query(mut filters): Query<Filters>,
+    RawQuery(query): RawQuery,
 ) -> Result<Ics, InternalError> {
-    // Default to hiding cancelled events unless the filter explicitly asks for them.
+    let filters = site_filters(&site, query.as_deref())?;
+    let history = history.lock().unwrap();
+    Ok(events_ics(&events, &history, filters, None))
+}
+
+pub async fn index_rss(
+    site: Site,
+    State(history): State<Arc<Mutex<EventHistory>>>,
+    events: Events,
+    RawQuery(query): RawQuery,
+    Query(options): Query<FeedOptions>,
+    TypedHeader(host): TypedHeader<Host>,
+) -> Result<Rss, InternalError> {
+    let filters = site_filters(&site, query.as_deref())?;
+    let history = history.lock().unwrap();
+    events_rss(&events, &history, filters, &options, None, &site, &host)
+}
+
+pub async fn index_atom(
+    site: Site,
+    State(history): State<Arc<Mutex<EventHistory>>>,
+    events: Events,
+    RawQuery(query): RawQuery,
+    Query(options): Query<FeedOptions>,
+    TypedHeader(host): TypedHeader<Host>,
+) -> Result<Atom, InternalError> {
+    let filters = site_filters(&site, query.as_deref())?;
+    let history = history.lock().unwrap();
+    events_atom(&events, &history, filters, &options, None, &site, &host)
+}
+
+/// Serves a single event with the given UID as an iCalendar file to download.
+pub async fn event_ics(
+    State(history): State<Arc<Mutex<EventHistory>>>,
+    events: Events,
+    Path(filename): Path<String>,
+) -> Result<impl IntoResponse, InternalError> {
+    let uid = filename
+        .strip_suffix(".ics")
+        .ok_or(InternalError::NotFound)?;
+    let event = find_event(&events, uid)?;
+    let history = history.lock().unwrap();
+    let calendar = events_to_calendar(&[event], &event.name, &history, Utc::now());
+    Ok((
+        [(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}.ics\"", uid),
+        )],
+        Ics(calendar),
+    ))
+}
+
+/// Redirects to the given calendar service to add the event with the given UID to it.
+pub async fn event_calendar_link(
+    events: Events,
+    Path((uid, service)): Path<(String, String)>,
+) -> Result<Redirect, InternalError> {
+    let event = find_event(&events, &uid)?;
+    let url = match service.as_str() {
+        "google" => google_calendar_url(event),
+        "outlook" => outlook_calendar_url(event),
+        _ => return Err(InternalError::NotFound),
+    };
+    Ok(Redirect::to(&url))
+}
+
+/// Returns the event with the given UID.
+fn find_event<'a>(events: &'a Events, uid: &str) -> Result<&'a Event, InternalError> {
+    events
+        .events
+        .iter()
+        .find(|event| event.uid() == uid)
+        .ok_or(InternalError::NotFound)
+}
+
+/// Serves one of the named feeds from the config file, in the format given by the extension.
+#[allow(clippy::too_many_arguments)]
+pub async fn feed(
+    site: Site,
+    State(config): State<Arc<Config>>,
+    State(history): State<Arc<Mutex<EventHistory>>>,
+    events: Events,
+    Path(filename): Path<String>,
+    Query(options): Query<FeedOptions>,
+    Query(geojson_options): Query<GeoJsonOptions>,
+    TypedHeader(host): TypedHeader<Host>,
+) -> Result<Response, InternalError> {
+    let (name, extension) = filename.rsplit_once('.').ok_or(InternalError::NotFound)?;
+    let feed = config.feed(name).ok_or(InternalError::NotFound)?;
+    let feed_query = feed
+        .filters
+        .to_query_string()
+        .map_err(InternalError::Internal)?;
+    let filters = site_filters(&site, Some(&feed_query))?;
+    let history = history.lock().unwrap();
+    Ok(match extension {
+        "ics" => events_ics(&events, &history, filters, Some(feed.title())).into_response(),
+        "rss" => events_rss(
+            &events,
+            &history,
+            filters,
+            &options,
+            Some(feed.title()),
+            &site,
+            &host,
+        )?
+        .into_response(),
+        "atom" => events_atom(
+            &events,
+            &history,
+            filters,
+            &options,
+            Some(feed.title()),
+            &site,
+            &host,
+        )?
+        .into_response(),
+        "csv" => events_csv(&events, &filters)?.into_response(),
+        "geojson" => events_geojson(&events, filters, &geojson_options, &site)?.into_response(),
+        "json" => events_json(&events, &filters)?.into_response(),
+        "toml" => events_toml(&events, &filters)?.into_response(),
+        "yaml" => events_yaml(&events, &filters)?.into_response(),
+        _ => return Err(InternalError::NotFound),
+    })
+}
+
+/// Returns the events matching the given filters, sorted by start time.
+pub fn sorted_matching<'a>(events: &'a Events, filters: &Filters) -> Vec<&'a Event> {
+    let mut events = events.matching(filters);
+    events.sort_by_key(|event| event.time.start_time_sort_key());
+    events
+}
+
+fn events_json(events: &Events, filters: &Filters) -> Result<String, InternalError> {
+    let events = Events::cloned(sorted_matching(events, filters));
+    Ok(serde_json::to_string(&events)?)
+}
+
+fn events_toml(events: &Events, filters: &Filters) -> Result<String, InternalError> {
+    let events = Events::cloned(sorted_matching(events, filters));
+    Ok(toml::to_string(&events)?)
+}
+
+fn events_csv(events: &Events, filters: &Filters) -> Result<impl IntoResponse, InternalError> {
+    let events = Events::cloned(sorted_matching(events, filters));
+    Ok((
+        [(header::CONTENT_TYPE, "text/csv; charset=utf-8")],
+        events.to_csv_string().map_err(InternalError::Internal)?,
+    ))
+}
+
+fn events_yaml(events: &Events, filters: &Filters) -> Result<String, InternalError> {
+    let events = Events::cloned(sorted_matching(events, filters));
+    Ok(serde_yaml::to_string(&events)?)
+}
+
+fn events_geojson(
+    events: &Events,
+    mut filters: Filters,
+    options: &GeoJsonOptions,
+    site: &Site,
+) -> Result<GeoJson, InternalError> {
+    let link_filters = filters.clone();
+    hide_cancelled_by_default(&mut filters);
+    let geojson = match options.cluster {
+        Cluster::Event => events_to_geojson(&sorted_matching(events, &filters)),
+        Cluster::City => {
+            cities_to_geojson(events, &filters, &link_filters, &site.default_filters())
+                .map_err(InternalError::Internal)?
+        }
+    };
+    Ok(GeoJson(serde_json::to_string(&geojson)?))
+}
+
+fn events_ics(
+    events: &Events,
+    history: &EventHistory,
+    mut filters: Filters,
+    title: Option<String>,
+) -> Ics {
+    hide_cancelled_by_default(&mut filters);
+    let events = sorted_matching(events, &filters);
+    let title = title.unwrap_or_else(|| filters.make_title());
+    Ics(events_to_calendar(&events, &title, history, Utc::now()))
+}
+
+fn events_rss(
+    events: &Events,
+    history: &EventHistory,
+    mut filters: Filters,
+    options: &FeedOptions,
+    title: Option<String>,
+    site: &Site,
+    host: &Host,
+) -> Result<Rss, InternalError> {
+    let link = html_link(&filters, site, host);
+    hide_cancelled_by_default(&mut filters);
+    let mut events = events.matching(&filters);
+    sort_events(&mut events, history, options.order);
+    let title = title.unwrap_or_else(|| filters.make_title());
+    Ok(Rss(events_to_rss(&events, history, &title, &link)))
+}
+
+fn events_atom(
+    events: &Events,
+    history: &EventHistory,
+    mut filters: Filters,
+    options: &FeedOptions,
+    title: Option<String>,
+    site: &Site,
+    host: &Host,
+) -> Result<Atom, InternalError> {
+    let link = html_link(&filters, site, host);
+    hide_cancelled_by_default(&mut filters);
+    let mut events = events.matching(&filters);
+    sort_events(&mut events, history, options.order);
+    let title = title.unwrap_or_else(|| filters.make_title());
+    Ok(Atom(events_to_atom(
+        &events,
+        history,
+        &title,
+        &link,
+        &site.name(host),
+        Utc::now(),
+    )))
+}
+
+/// Default to hiding cancelled events from calendars and feeds unless the filter explicitly asks
+/// for them.
+pub fn hide_cancelled_by_default(filters: &mut Filters) {
     if filters.cancelled.is_none() {
         filters.cancelled = Some(false);
     }
+}
 
-    let mut events = events.matching(&filters);
-    events.sort_by_key(|event| event.time.start_time_sort_key());
-    let calendar = events_to_calendar(&events, &filters.make_title());
-    Ok(Ics(calendar))
+/// Returns the absolute URL of the HTML page on the given site showing events matching the given
+/// filters.
+fn html_link(filters: &Filters, site: &Site, host: &Host) -> String {
+    let query = site_query(site, filters);
+    if query.is_empty() {
+        format!("https://{}/", host)
+    } else {
+        format!("https://{}/?{}", host, query)
+    }
 }
 
 #[derive(Template)]
 #[template(path = "index.html")]
 struct IndexTemplate {
+    site: Site,
+    host: String,
     filters: Filters,
     months: Vec<Month>,
     has_filters: bool,
@@ -141,10 +422,39 @@ struct IndexTemplate {
     calendar: bool,
 }
 
-struct Month {
+impl IndexTemplate {
+    /// Returns the query string to link to the given filters on this site.
+    fn query(&self, filters: Filters) -> String {
+        site_query(&self.site, &filters)
+    }
+}
+
+#[derive(Template)]
+#[template(path = "map.html")]
+struct MapTemplate {
+    site: Site,
+    filters: Filters,
+}
+
+impl MapTemplate {
+    /// Returns the query string to link to the given filters on this site.
+    fn query(&self, filters: Filters) -> String {
+        site_query(&self.site, &filters)
+    }
+}
+
+/// Returns the query string for a link to a page showing the given filters on the given site, so
+/// that any of the site's default filters which they don't use are cleared explicitly.
+pub fn site_query(site: &Site, filters: &Filters) -> String {
+    filters
+        .to_query_string_with_defaults(&site.default_filters())
+        .unwrap()
+}
+
+pub struct Month {
     /// The first day of the month.
-    start: NaiveDate,
-    events: Vec<Event>,
+    pub start: NaiveDate,
+    pub events: Vec<Event>,
 }
 
 impl Month {
@@ -172,15 +482,20 @@ impl Month {
 }
 
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
-struct Day {
+pub struct Day {
     day_of_month: Option<u32>,
     events: Vec<Event>,
 }
 
 /// Given a list of events in arbitrary order, sort them in ascending order of start date, then group them by starting month.
-fn sort_and_group_by_month(mut events: Vec<&Event>) -> Vec<Month> {
+pub fn sort_and_group_by_month(mut events: Vec<&Event>) -> Vec<Month> {
     events.sort_by_key(|event| event.time.start_time_sort_key());
+    group_by_month(events)
+}
 
+/// Given a list of events which are already sorted by start date, group them by starting month,
+/// keeping the same order.
+pub fn group_by_month(events: Vec<&Event>) -> Vec<Month> {
     let mut months = vec![];
     let mut month = Month {
         start: NaiveDate::MIN,
@@ -205,3 +520,90 @@ fn sort_and_group_by_month(mut events: Vec<&Event>) -> Vec<Month> {
 
     months
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::SiteConfig, model::event::EventTime};
+
+    fn test_event(name: &str, country: &str) -> Event {
+        Event {
+            name: name.to_string(),
+            details: None,
+            links: vec![],
+            time: EventTime::DateOnly {
+                start_date: NaiveDate::from_ymd_opt(2100, 1, 1).unwrap(),
+                end_date: NaiveDate::from_ymd_opt(2100, 1, 1).unwrap(),
+            },
+            country: country.to_string(),
+            state: None,
+            city: "City".to_string(),
+            styles: vec![DanceStyle::Contra],
+            workshop: false,
+            social: true,
+            bands: vec![],
+            callers: vec![],
+            price: None,
+            organisation: None,
+            cancelled: false,
+            source: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn exports_use_site_defaults() {
+        let events = Events {
+            events: vec![test_event("UK dance", "UK"), test_event("US dance", "USA")],
+        };
+        let site = Site(Some(SiteConfig {
+            host: "uk.example.com".to_string(),
+            default_filters: Filters {
+                countries: vec!["UK".to_string()],
+                ..Default::default()
+            },
+            title: None,
+            logo: None,
+            contact: None,
+        }));
+        let host: Host = "uk.example.com"
+            .parse::<axum::http::uri::Authority>()
+            .unwrap()
+            .into();
+
+        for (query, expected_names) in [
+            (None, vec!["UK dance"]),
+            (Some("country="), vec!["UK dance", "US dance"]),
+            (Some("country=USA"), vec!["US dance"]),
+        ] {
+            let query = query.map(ToOwned::to_owned);
+            let Html(html) = index_html(
+                site.clone(),
+                events.clone(),
+                query.clone(),
+                host.clone(),
+                false,
+            )
+            .await
+            .unwrap();
+            let json = index_json(site.clone(), events.clone(), RawQuery(query.clone()))
+                .await
+                .unwrap();
+            let exported: Events = serde_json::from_str(&json).unwrap();
+            let exported_names: Vec<_> = exported
+                .events
+                .iter()
+                .map(|event| event.name.as_str())
+                .collect();
+            assert_eq!(exported_names, expected_names, "query {:?}", query);
+            for event in &events.events {
+                assert_eq!(
+                    html.contains(&event.name),
+                    expected_names.contains(&event.name.as_str()),
+                    "{} for query {:?}",
+                    event.name,
+                    query
+                );
+            }
+        }
+    }
+}
@@ -13,9 +13,18 @@
 // limitations under the License.
 
 pub mod add;
+pub mod admin;
+pub mod api;
+pub mod appearances;
 pub mod bands;
 pub mod callers;
+pub mod cancel;
 pub mod cities;
+pub mod embed;
 pub mod index;
 pub mod organisations;
 pub mod reload;
+pub mod sources;
+pub mod stats;
+pub mod suggest;
+pub mod upload;
@@ -12,21 +12,43 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::appearances::appearances_page;
 use crate::{
     errors::InternalError,
-    model::events::{Events, Organisation},
+    extractors::Site,
+    model::{
+        events::{Events, Organisation},
+        filters::Filters,
+    },
 };
 use askama::Template;
-use axum::response::Html;
+use axum::{extract::Path, response::Html};
 
-pub async fn organisations(events: Events) -> Result<Html<String>, InternalError> {
+pub async fn organisations(site: Site, events: Events) -> Result<Html<String>, InternalError> {
     let organisations = events.organisations();
-    let template = OrganisationsTemplate { organisations };
+    let template = OrganisationsTemplate {
+        site,
+        organisations,
+    };
     Ok(Html(template.render()?))
 }
 
+/// Shows all past and future events for the organisation with the given name.
+pub async fn organisation(
+    site: Site,
+    events: Events,
+    Path(name): Path<String>,
+) -> Result<Html<String>, InternalError> {
+    let filters = Filters {
+        organisations: vec![name.clone()],
+        ..Default::default()
+    };
+    appearances_page(site, &events, "Organisation", name, filters)
+}
+
 #[derive(Template)]
 #[template(path = "organisations.html")]
 struct OrganisationsTemplate {
+    site: Site,
     organisations: Vec<Organisation>,
 }
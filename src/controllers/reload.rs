@@ -12,7 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::{config::Config, errors::InternalError, model::events::Events};
+use crate::{
+    config::Config,
+    errors::InternalError,
+    model::{events::Events, history::EventHistory},
+};
 use axum::extract::{Form, State};
 use log::info;
 use serde::{Deserialize, Serialize};
@@ -20,6 +24,7 @@ use std::sync::{Arc, Mutex};
 
 pub async fn reload(
     State(events): State<Arc<Mutex<Events>>>,
+    State(history): State<Arc<Mutex<EventHistory>>>,
     State(config): State<Arc<Config>>,
     Form(request): Form<ReloadRequest>,
 ) -> Result<String, InternalError> {
@@ -27,9 +32,26 @@ pub async fn reload(
         return Err(InternalError::Unauthorised);
     }
 
+    let count = reload_events(&events, &history, &config).await?;
+    Ok(format!("Reloaded {} events.\n", count))
+}
+
+/// Reloads events from the configured source and records their history.
+///
+/// Returns the number of events loaded.
+pub async fn reload_events(
+    events: &Mutex<Events>,
+    history: &Mutex<EventHistory>,
+    config: &Config,
+) -> Result<usize, InternalError> {
     let new_events = Events::load_events(&config.events)
         .await
         .map_err(InternalError::Internal)?;
+    history
+        .lock()
+        .unwrap()
+        .record_and_save(&new_events, config.history_file.as_deref())
+        .map_err(InternalError::Internal)?;
 
     let mut events = events.lock().unwrap();
     *events = new_events;
@@ -40,7 +62,7 @@ pub async fn reload(
         config.events,
     );
 
-    Ok(format!("Reloaded {} events.\n", events.events.len()))
+    Ok(events.events.len())
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -0,0 +1,37 @@
+// Copyright 2024 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{
+    errors::InternalError,
+    extractors::Admin,
+    model::events::{Events, Source},
+};
+use askama::Template;
+use axum::response::Html;
+use chrono::{NaiveDate, Utc};
+
+/// Shows the source files and importers of events, for administrators to spot stale ones.
+pub async fn sources(_admin: Admin, events: Events) -> Result<Html<String>, InternalError> {
+    let sources = events.sources();
+    let today = Utc::now().date_naive();
+    let template = SourcesTemplate { sources, today };
+    Ok(Html(template.render()?))
+}
+
+#[derive(Template)]
+#[template(path = "sources.html")]
+struct SourcesTemplate {
+    sources: Vec<Source>,
+    today: NaiveDate,
+}
@@ -0,0 +1,80 @@
+// Copyright 2024 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::index::site_query;
+use crate::{
+    errors::InternalError,
+    extractors::Site,
+    model::{
+        events::Events,
+        filters::{DateFilter, Filters},
+    },
+    stats::Stats,
+};
+use askama::Template;
+use axum::{extract::RawQuery, response::Html};
+
+/// Shows statistics about the events matching the filters in the query string.
+///
+/// Unlike the event lists, this includes past events unless the query string says otherwise, to
+/// match the `stats` command.
+pub async fn stats(
+    site: Site,
+    events: Events,
+    RawQuery(query): RawQuery,
+) -> Result<Html<String>, InternalError> {
+    let filters = Filters::from_query_string_with_defaults(
+        query.as_deref().unwrap_or_default(),
+        &stats_defaults(&site),
+    )
+    .map_err(|e| InternalError::BadRequest(format!("Invalid filters: {}", e)))?;
+    let stats = Stats::new(&events.matching(&filters));
+    let template = StatsTemplate {
+        site,
+        filters,
+        stats,
+    };
+    Ok(Html(template.render()?))
+}
+
+/// Returns the default filters for statistics on the given site, which are the site's defaults but
+/// including past events.
+fn stats_defaults(site: &Site) -> Filters {
+    Filters {
+        date: DateFilter::All,
+        ..site.default_filters()
+    }
+}
+
+#[derive(Template)]
+#[template(path = "stats.html")]
+struct StatsTemplate {
+    site: Site,
+    filters: Filters,
+    stats: Stats,
+}
+
+impl StatsTemplate {
+    /// Returns the query string to link to statistics for the given filters on this site.
+    fn query(&self, filters: Filters) -> String {
+        filters
+            .to_query_string_with_defaults(&stats_defaults(&self.site))
+            .unwrap()
+    }
+
+    /// Returns the query string to link to the list of events matching the filters on this site.
+    fn list_query(&self) -> String {
+        site_query(&self.site, &self.filters)
+    }
+}
@@ -0,0 +1,210 @@
+// Copyright 2024 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Autocomplete suggestions for the fields of the add form.
+
+use crate::{
+    errors::InternalError,
+    model::{events::Events, filters::Filters},
+};
+use axum::{http::header, response::IntoResponse};
+use axum_extra::extract::Query;
+use serde::{Deserialize, Serialize};
+use std::{cmp::Reverse, collections::HashMap};
+
+/// The maximum number of suggestions to return.
+const MAX_SUGGESTIONS: usize = 10;
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct SuggestQuery {
+    /// The text entered so far.
+    #[serde(default)]
+    q: String,
+    /// For cities, the country to which suggestions are limited.
+    country: Option<String>,
+    /// For cities, the state to which suggestions are limited.
+    state: Option<String>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Suggestion {
+    name: String,
+    event_count: usize,
+}
+
+pub async fn bands(
+    events: Events,
+    Query(query): Query<SuggestQuery>,
+) -> Result<impl IntoResponse, InternalError> {
+    let candidates = events
+        .bands()
+        .into_iter()
+        .map(|band| (band.name, band.event_count));
+    suggestions_response(suggest(candidates, &query.q))
+}
+
+pub async fn callers(
+    events: Events,
+    Query(query): Query<SuggestQuery>,
+) -> Result<impl IntoResponse, InternalError> {
+    let candidates = events
+        .callers()
+        .into_iter()
+        .map(|caller| (caller.name, caller.event_count));
+    suggestions_response(suggest(candidates, &query.q))
+}
+
+pub async fn organisations(
+    events: Events,
+    Query(query): Query<SuggestQuery>,
+) -> Result<impl IntoResponse, InternalError> {
+    let candidates = events
+        .organisations()
+        .into_iter()
+        .map(|organisation| (organisation.name, organisation.event_count));
+    suggestions_response(suggest(candidates, &query.q))
+}
+
+/// Suggests cities, limited to the given country and state if any.
+pub async fn cities(
+    events: Events,
+    Query(query): Query<SuggestQuery>,
+) -> Result<impl IntoResponse, InternalError> {
+    let filters = Filters {
+        countries: query
+            .country
+            .into_iter()
+            .filter(|c| !c.is_empty())
+            .collect(),
+        states: query.state.into_iter().filter(|s| !s.is_empty()).collect(),
+        ..Filters::all()
+    };
+    let mut counts = HashMap::<String, usize>::new();
+    for event in events.matching(&filters) {
+        *counts.entry(event.city.clone()).or_default() += 1;
+    }
+    suggestions_response(suggest(counts, &query.q))
+}
+
+fn suggestions_response(suggestions: Vec<Suggestion>) -> Result<impl IntoResponse, InternalError> {
+    Ok((
+        [(header::CONTENT_TYPE, "application/json")],
+        serde_json::to_string(&suggestions)?,
+    ))
+}
+
+/// Returns the names matching the given text, best matches first, with names with more events
+/// ranked above others which match equally well.
+fn suggest(candidates: impl IntoIterator<Item = (String, usize)>, text: &str) -> Vec<Suggestion> {
+    let text = text.trim().to_lowercase();
+    let mut matches = candidates
+        .into_iter()
+        .filter_map(|(name, event_count)| {
+            let rank = match_rank(&name.to_lowercase(), &text)?;
+            Some((rank, Reverse(event_count), name))
+        })
+        .collect::<Vec<_>>();
+    matches.sort();
+    matches
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, Reverse(event_count), name)| Suggestion { name, event_count })
+        .collect()
+}
+
+/// Returns how well the given name matches the text, lower being better, or `None` if it doesn't
+/// match at all. Both must already be lowercase.
+///
+/// A prefix of the whole name is the best match, followed by a prefix of any word in the name,
+/// then text anywhere in the name, then the characters of the text appearing in order.
+fn match_rank(name: &str, text: &str) -> Option<u8> {
+    if name.starts_with(text) {
+        Some(0)
+    } else if name
+        .split(|c: char| !c.is_alphanumeric())
+        .any(|word| word.starts_with(text))
+    {
+        Some(1)
+    } else if name.contains(text) {
+        Some(2)
+    } else {
+        let mut name_chars = name.chars();
+        if text
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .all(|c| name_chars.any(|n| n == c))
+        {
+            Some(3)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidates() -> Vec<(String, usize)> {
+        [
+            ("Contra Club", 3),
+            ("The Contrarians", 5),
+            ("Big Band", 10),
+            ("Cobbled Band", 1),
+            ("Band of Contrast", 2),
+        ]
+        .into_iter()
+        .map(|(name, count)| (name.to_string(), count))
+        .collect()
+    }
+
+    fn names(suggestions: Vec<Suggestion>) -> Vec<String> {
+        suggestions
+            .into_iter()
+            .map(|suggestion| suggestion.name)
+            .collect()
+    }
+
+    #[test]
+    fn rank_matches() {
+        assert_eq!(
+            names(suggest(candidates(), "contra")),
+            vec!["Contra Club", "The Contrarians", "Band of Contrast"]
+        );
+        assert_eq!(
+            names(suggest(candidates(), "band")),
+            vec!["Band of Contrast", "Big Band", "Cobbled Band"]
+        );
+        // The characters only need to appear in order.
+        assert_eq!(names(suggest(candidates(), "cbd")), vec!["Cobbled Band"]);
+        assert_eq!(names(suggest(candidates(), "xyz")), Vec::<String>::new());
+    }
+
+    #[test]
+    fn empty_text_ranks_by_event_count() {
+        assert_eq!(
+            suggest(candidates(), " ")[..2],
+            [
+                Suggestion {
+                    name: "Big Band".to_string(),
+                    event_count: 10,
+                },
+                Suggestion {
+                    name: "The Contrarians".to_string(),
+                    event_count: 5,
+                },
+            ]
+        );
+    }
+}
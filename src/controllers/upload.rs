@@ -0,0 +1,508 @@
+// Copyright 2024 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bulk submission of events by uploading an iCalendar or CSV file.
+
+use super::add::{check_spam, filters, submit_change, trim, trim_non_empty, Submitted};
+use crate::{
+    change::Change,
+    config::Config,
+    errors::InternalError,
+    extractors::ClientIp,
+    forge::choose_file_for_event,
+    importers::icalendar::{convert_without_location, parse_calendar},
+    model::{
+        dancestyle::DanceStyle,
+        event::Event,
+        events::{Country, Events, Organisation},
+        filters::Filters,
+        history::EventHistory,
+    },
+    spam::SpamGuard,
+};
+use askama::Template;
+use axum::{
+    extract::{Multipart, State},
+    response::Html,
+};
+use axum_extra::extract::Form;
+use chrono::Utc;
+use eyre::Report;
+use serde::Deserialize;
+use std::sync::{Arc, Mutex};
+
+/// The maximum number of events which may be submitted from a single file.
+const MAX_UPLOAD_EVENTS: usize = 200;
+
+pub async fn upload(events: Events) -> Result<Html<String>, InternalError> {
+    let template = UploadTemplate::new(&events, UploadDefaults::default(), vec![]);
+    Ok(Html(template.render()?))
+}
+
+/// Parses the uploaded file and shows a preview of the events in it, with any problems.
+pub async fn preview(
+    State(spam): State<Arc<SpamGuard>>,
+    events: Events,
+    mut multipart: Multipart,
+) -> Result<Html<String>, InternalError> {
+    let mut file = None;
+    let mut fields = vec![];
+    while let Some(field) = multipart.next_field().await.map_err(bad_request)? {
+        let name = field.name().unwrap_or_default().to_owned();
+        if name == "file" {
+            let filename = field.file_name().unwrap_or_default().to_owned();
+            let contents = field.text().await.map_err(bad_request)?;
+            file = Some((filename, contents));
+        } else {
+            fields.push((name, field.text().await.map_err(bad_request)?));
+        }
+    }
+    let defaults: UploadDefaults =
+        serde_html_form::from_str(&serde_html_form::to_string(&fields).map_err(bad_request)?)
+            .map_err(bad_request)?;
+
+    let Some((filename, contents)) = file.filter(|(_, contents)| !contents.is_empty()) else {
+        let template = UploadTemplate::new(&events, defaults, vec!["Please choose a file."]);
+        return Ok(Html(template.render()?));
+    };
+    let rows = match parse_upload(&filename, &contents, &defaults) {
+        Ok(rows) => rows,
+        Err(e) => {
+            let template = UploadTemplate {
+                file_error: Some(format!("{:#}", e)),
+                ..UploadTemplate::new(&events, defaults, vec![])
+            };
+            return Ok(Html(template.render()?));
+        }
+    };
+    if rows.len() > MAX_UPLOAD_EVENTS {
+        let template = UploadTemplate::new(
+            &events,
+            defaults,
+            vec!["Too many events in file, please split it up."],
+        );
+        return Ok(Html(template.render()?));
+    }
+
+    let rows = check_rows(&events, rows);
+    let accepted = rows
+        .iter()
+        .filter_map(UploadRow::accepted)
+        .cloned()
+        .collect::<Vec<_>>();
+    let chosen_file = accepted
+        .first()
+        .and_then(|event| choose_file_for_event(&events, event).ok());
+    let template = PreviewTemplate {
+        accepted_json: serde_json::to_string(&accepted)?,
+        accepted_count: accepted.len(),
+        rows,
+        chosen_file,
+        form_token: spam.issue_token(Utc::now()),
+    };
+    Ok(Html(template.render()?))
+}
+
+/// Opens a single PR to add all the accepted events from the preview.
+pub async fn submit(
+    State(config): State<Arc<Config>>,
+    State(all_events): State<Arc<Mutex<Events>>>,
+    State(history): State<Arc<Mutex<EventHistory>>>,
+    State(spam): State<Arc<SpamGuard>>,
+    client: ClientIp,
+    events: Events,
+    Form(form): Form<UploadSubmitForm>,
+) -> Result<Html<String>, InternalError> {
+    let uploaded: Vec<Event> = serde_json::from_str(&form.events).map_err(bad_request)?;
+    if uploaded.is_empty() {
+        return Err(InternalError::BadRequest("No events to add".to_string()));
+    }
+    if uploaded.len() > MAX_UPLOAD_EVENTS {
+        return Err(InternalError::BadRequest("Too many events".to_string()));
+    }
+    // Check again, in case events have been added since the preview or the form was modified.
+    let rows = check_rows(&events, uploaded.into_iter().map(Ok).collect());
+    if let Some(row) = rows.iter().find(|row| row.accepted().is_none()) {
+        return Err(InternalError::BadRequest(format!(
+            "Event {} is not valid: {}",
+            row.number,
+            row.problems.join(" ")
+        )));
+    }
+    let accepted = rows
+        .into_iter()
+        .filter_map(|row| row.event)
+        .collect::<Vec<_>>();
+    check_spam(
+        &spam,
+        client,
+        &form.form_token,
+        &form.homepage,
+        form.email.as_deref(),
+        &accepted,
+    )?;
+    let chosen_file = choose_file_for_event(&events, &accepted[0])
+        .map_err(|_| InternalError::BadRequest("Duplicate event".to_string()))?;
+
+    let submitted = submit_change(
+        Change::Add(accepted.clone()),
+        &chosen_file,
+        form.email.as_deref(),
+        &config,
+        &all_events,
+        &history,
+    )
+    .await?;
+    let template = UploadSubmittedTemplate {
+        submitted,
+        events: accepted,
+        chosen_file,
+    };
+    Ok(Html(template.render()?))
+}
+
+fn bad_request(e: impl ToString) -> InternalError {
+    InternalError::BadRequest(e.to_string())
+}
+
+/// Parses the given uploaded file as iCalendar or CSV depending on its name and contents, and
+/// fills in any missing fields from the defaults.
+///
+/// Returns an error if the file as a whole can't be parsed, or a result for each event if it can.
+fn parse_upload(
+    filename: &str,
+    contents: &str,
+    defaults: &UploadDefaults,
+) -> Result<Vec<Result<Event, Report>>, Report> {
+    let is_calendar = filename.to_lowercase().ends_with(".ics")
+        || contents.trim_start().starts_with("BEGIN:VCALENDAR");
+    let events = if is_calendar {
+        parse_calendar(contents, defaults.timezone.as_deref())?
+            .into_iter()
+            .map(convert_without_location)
+            .collect()
+    } else {
+        Events::parse_csv_rows(contents)
+    };
+    Ok(events
+        .into_iter()
+        .map(|event| {
+            event.map(|mut event| {
+                defaults.apply(&mut event, is_calendar);
+                event
+            })
+        })
+        .collect())
+}
+
+/// Validates the given events, and checks whether they are duplicates of existing events or of
+/// each other.
+fn check_rows(events: &Events, uploaded: Vec<Result<Event, Report>>) -> Vec<UploadRow> {
+    let mut rows: Vec<UploadRow> = vec![];
+    for (i, event) in uploaded.into_iter().enumerate() {
+        let row = match event {
+            Ok(event) => {
+                let mut problems = event
+                    .validate()
+                    .into_iter()
+                    .map(ToOwned::to_owned)
+                    .collect::<Vec<_>>();
+                let duplicate = choose_file_for_event(events, &event)
+                    .err()
+                    .map(|duplicate| {
+                        problems.push("Duplicate of an existing event.".to_owned());
+                        duplicate.existing
+                    });
+                if rows
+                    .iter()
+                    .filter_map(UploadRow::accepted)
+                    .any(|earlier| earlier.merge(&event).is_some())
+                {
+                    problems.push("Duplicate of an earlier event in the file.".to_owned());
+                }
+                UploadRow {
+                    number: i + 1,
+                    event: Some(event),
+                    problems,
+                    duplicate,
+                }
+            }
+            Err(e) => UploadRow {
+                number: i + 1,
+                event: None,
+                problems: vec![format!("{:#}", e)],
+                duplicate: None,
+            },
+        };
+        rows.push(row);
+    }
+    rows
+}
+
+/// Values to use for fields which the uploaded file doesn't include.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+pub struct UploadDefaults {
+    #[serde(default, deserialize_with = "trim")]
+    country: String,
+    #[serde(default, deserialize_with = "trim_non_empty")]
+    state: Option<String>,
+    #[serde(default, deserialize_with = "trim")]
+    city: String,
+    #[serde(default)]
+    styles: Vec<DanceStyle>,
+    #[serde(default)]
+    workshop: bool,
+    #[serde(default)]
+    social: bool,
+    #[serde(default, deserialize_with = "trim_non_empty")]
+    organisation: Option<String>,
+    /// The timezone to use for iCalendar files which don't specify one.
+    #[serde(default, deserialize_with = "trim_non_empty")]
+    timezone: Option<String>,
+}
+
+impl UploadDefaults {
+    /// Fills in any empty fields of the event from the defaults.
+    ///
+    /// Workshop and social can't be empty, so they are only set for events from iCalendar files,
+    /// which don't have them.
+    fn apply(&self, event: &mut Event, from_calendar: bool) {
+        if event.country.is_empty() {
+            event.country = self.country.clone();
+            if event.state.is_none() {
+                event.state = self.state.clone();
+            }
+        }
+        if event.city.is_empty() {
+            event.city = self.city.clone();
+        }
+        if event.styles.is_empty() {
+            event.styles = self.styles.clone();
+        }
+        if event.organisation.is_none() {
+            event.organisation = self.organisation.clone();
+        }
+        if from_calendar {
+            event.workshop = self.workshop;
+            event.social = self.social;
+        }
+    }
+
+    fn workshop(&self) -> bool {
+        self.workshop
+    }
+
+    fn social(&self) -> bool {
+        self.social
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+pub struct UploadSubmitForm {
+    /// The accepted events from the preview, as JSON.
+    events: String,
+    #[serde(default, deserialize_with = "trim_non_empty")]
+    email: Option<String>,
+    /// The token issued by the server when the preview was shown.
+    #[serde(default)]
+    form_token: String,
+    /// A honeypot field hidden from people, which only bots fill in.
+    #[serde(default)]
+    homepage: String,
+}
+
+/// An event from the uploaded file, and whether it can be added.
+#[derive(Clone, Debug, PartialEq)]
+struct UploadRow {
+    /// The 1-based position of the event in the file.
+    number: usize,
+    /// The event, if it could be parsed.
+    event: Option<Event>,
+    /// Any problems which prevent the event from being added.
+    problems: Vec<String>,
+    /// The existing event which this is a duplicate of, if any.
+    duplicate: Option<Event>,
+}
+
+impl UploadRow {
+    /// Returns the event if it can be added.
+    fn accepted(&self) -> Option<&Event> {
+        if self.problems.is_empty() {
+            self.event.as_ref()
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "upload.html")]
+struct UploadTemplate {
+    countries: Vec<Country>,
+    organisations: Vec<Organisation>,
+    defaults: UploadDefaults,
+    errors: Vec<&'static str>,
+    /// An error parsing the uploaded file.
+    file_error: Option<String>,
+}
+
+impl UploadTemplate {
+    fn new(events: &Events, defaults: UploadDefaults, errors: Vec<&'static str>) -> Self {
+        Self {
+            countries: events.countries(&Filters::all()),
+            organisations: events.organisations(),
+            defaults,
+            errors,
+            file_error: None,
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "upload_preview.html")]
+struct PreviewTemplate {
+    rows: Vec<UploadRow>,
+    accepted_count: usize,
+    /// The accepted events, as JSON to submit.
+    accepted_json: String,
+    /// The file to which the accepted events will be added.
+    chosen_file: Option<String>,
+    form_token: String,
+}
+
+#[derive(Template)]
+#[template(path = "upload_submitted.html")]
+struct UploadSubmittedTemplate {
+    submitted: Submitted,
+    events: Vec<Event>,
+    chosen_file: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::event::EventTime;
+    use chrono::NaiveDate;
+
+    const CALENDAR: &str = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+BEGIN:VEVENT\r
+UID:1\r
+SUMMARY:Spring dance\r
+DESCRIPTION:Tickets £12\r
+DTSTART;VALUE=DATE:20300301\r
+DTEND;VALUE=DATE:20300302\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:2\r
+SUMMARY:Summer dance\r
+DTSTART;VALUE=DATE:20300601\r
+DTEND;VALUE=DATE:20300602\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+    fn defaults() -> UploadDefaults {
+        UploadDefaults {
+            country: "UK".to_string(),
+            city: "London".to_string(),
+            styles: vec![DanceStyle::Contra],
+            social: true,
+            organisation: Some("Org".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parse_calendar_with_defaults() {
+        let events = parse_upload("season.ics", CALENDAR, &defaults())
+            .unwrap()
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].name, "Spring dance");
+        assert_eq!(events[0].price.as_deref(), Some("£12"));
+        assert_eq!(
+            events[0].time,
+            EventTime::DateOnly {
+                start_date: NaiveDate::from_ymd_opt(2030, 3, 1).unwrap(),
+                end_date: NaiveDate::from_ymd_opt(2030, 3, 1).unwrap(),
+            }
+        );
+        assert_eq!(events[1].city, "London");
+        assert_eq!(events[1].organisation.as_deref(), Some("Org"));
+        assert!(events[1].social);
+        assert!(!events[1].workshop);
+    }
+
+    #[test]
+    fn parse_csv_keeps_columns() {
+        let rows = parse_upload(
+            "season.csv",
+            "name,start_date,end_date,start_time,end_time,country,state,city,styles,workshop,social\n\
+            Dance,2030-01-05,2030-01-05,,,,,,,true,false\n\
+            Other,2030-01-06,2030-01-06,,,USA,MA,Boston,balfolk,false,true\n",
+            &defaults(),
+        )
+        .unwrap();
+        let first = rows[0].as_ref().unwrap();
+        assert_eq!(first.city, "London");
+        assert_eq!(first.styles, vec![DanceStyle::Contra]);
+        assert!(first.workshop);
+        assert!(!first.social);
+        let second = rows[1].as_ref().unwrap();
+        assert_eq!(second.city, "Boston");
+        assert_eq!(second.state.as_deref(), Some("MA"));
+        assert_eq!(second.styles, vec![DanceStyle::Balfolk]);
+    }
+
+    #[test]
+    fn check_duplicates_and_problems() {
+        let rows = parse_upload("season.ics", CALENDAR, &defaults()).unwrap();
+        let existing = Events {
+            events: vec![Event {
+                source: Some("events/uk/london.yaml".to_string()),
+                ..rows[1].as_ref().unwrap().clone()
+            }],
+        };
+        let mut uploaded = rows;
+        // The same event again, and an event with no styles.
+        uploaded.push(Ok(uploaded[0].as_ref().unwrap().clone()));
+        uploaded.push(Ok(Event {
+            styles: vec![],
+            time: EventTime::DateOnly {
+                start_date: NaiveDate::from_ymd_opt(2030, 9, 1).unwrap(),
+                end_date: NaiveDate::from_ymd_opt(2030, 9, 1).unwrap(),
+            },
+            ..uploaded[0].as_ref().unwrap().clone()
+        }));
+        uploaded.push(Err(eyre::eyre!("Bad row")));
+
+        let rows = check_rows(&existing, uploaded);
+        assert_eq!(rows.len(), 5);
+        assert!(rows[0].accepted().is_some());
+        assert_eq!(rows[1].duplicate, Some(existing.events[0].clone()));
+        assert_eq!(
+            rows[2].problems,
+            vec!["Duplicate of an earlier event in the file."]
+        );
+        assert_eq!(
+            rows[3].problems,
+            vec!["Must include at least one style of dance."]
+        );
+        assert_eq!(rows[4].number, 5);
+        assert_eq!(rows[4].problems, vec!["Bad row"]);
+    }
+}
@@ -15,6 +15,8 @@
 use crate::model::event::Event;
 use askama::Template;
 use eyre::Report;
+use serde_json::{Map, Value};
+use std::collections::BTreeSet;
 
 /// Returns a Markdown diff between the two sets of events.
 pub fn diff_markdown(events_a: Vec<Event>, events_b: Vec<Event>) -> Result<String, Report> {
@@ -25,6 +27,55 @@ pub fn diff_markdown(events_a: Vec<Event>, events_b: Vec<Event>) -> Result<Strin
     Ok(html)
 }
 
+/// Returns a Markdown table of the fields which differ between the two versions of an event.
+///
+/// The source of the events is ignored.
+pub fn event_changes_markdown(old: &Event, new: &Event) -> String {
+    let old = event_fields(old);
+    let new = event_fields(new);
+    let fields = old.keys().chain(new.keys()).collect::<BTreeSet<_>>();
+
+    let mut markdown = "| Field | Old | New |\n| --- | --- | --- |\n".to_string();
+    for field in fields {
+        let old_value = old.get(field).unwrap_or(&Value::Null);
+        let new_value = new.get(field).unwrap_or(&Value::Null);
+        if old_value != new_value {
+            markdown += &format!(
+                "| {} | {} | {} |\n",
+                field,
+                markdown_value(old_value),
+                markdown_value(new_value)
+            );
+        }
+    }
+    markdown
+}
+
+/// Returns the fields of the event, other than its source, as JSON values.
+fn event_fields(event: &Event) -> Map<String, Value> {
+    let mut fields = match serde_json::to_value(event).unwrap() {
+        Value::Object(fields) => fields,
+        _ => unreachable!("Events always serialise to objects."),
+    };
+    fields.remove("source");
+    fields
+}
+
+/// Formats the given field value to go in a Markdown table cell.
+fn markdown_value(value: &Value) -> String {
+    value_text(value).replace('|', "\\|").replace('\n', " ")
+}
+
+/// Formats the given field value as plain text.
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.to_owned(),
+        Value::Array(values) => values.iter().map(value_text).collect::<Vec<_>>().join(", "),
+        other => other.to_string(),
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 struct DiffResult {
     /// The boolean is false if the event is only in the first list, true if it is only in the
@@ -43,24 +94,28 @@ fn find_diff(mut events_a: Vec<Event>, mut events_b: Vec<Event>) -> DiffResult {
     let mut same = 0;
     let mut a = 0;
     let mut b = 0;
-    while a < events_a.len() || b < events_b.len() {
-        let event_a = events_a.get(a);
-        let event_b = events_b.get(b);
-        if event_a == event_b {
-            a += 1;
-            b += 1;
-            same += 1;
-        } else if event_a.is_none() {
-            different.push((event_b.unwrap().to_owned(), true));
-            b += 1;
-        } else if event_b.is_none()
-            || event_a.unwrap().date_location_sort_key() < event_b.unwrap().date_location_sort_key()
-        {
-            different.push((event_a.unwrap().to_owned(), false));
-            a += 1;
-        } else {
-            different.push((event_b.unwrap().to_owned(), true));
-            b += 1;
+    loop {
+        match (events_a.get(a), events_b.get(b)) {
+            (None, None) => break,
+            (event_a, event_b) if event_a == event_b => {
+                a += 1;
+                b += 1;
+                same += 1;
+            }
+            (Some(event_a), None) => {
+                different.push((event_a.to_owned(), false));
+                a += 1;
+            }
+            (Some(event_a), Some(event_b))
+                if event_a.date_location_sort_key() < event_b.date_location_sort_key() =>
+            {
+                different.push((event_a.to_owned(), false));
+                a += 1;
+            }
+            (_, Some(event_b)) => {
+                different.push((event_b.to_owned(), true));
+                b += 1;
+            }
         }
     }
 
@@ -103,6 +158,26 @@ mod tests {
         }
     }
 
+    #[test]
+    fn field_changes() {
+        let old = test_event("Event", NaiveDate::from_ymd_opt(2024, 1, 1).unwrap());
+        let new = Event {
+            name: "Event | renamed".to_string(),
+            bands: vec!["A".to_string(), "B".to_string()],
+            price: Some("£5".to_string()),
+            source: Some("events/test.yaml".to_string()),
+            ..old.clone()
+        };
+        assert_eq!(
+            event_changes_markdown(&old, &new),
+            "| Field | Old | New |\n\
+             | --- | --- | --- |\n\
+             | bands |  | A, B |\n\
+             | name | Event | Event \\| renamed |\n\
+             | price |  | £5 |\n"
+        );
+    }
+
     #[test]
     fn diff_all_same() {
         let event_1 = test_event("Event 1", NaiveDate::from_ymd_opt(2024, 1, 1).unwrap());
@@ -13,7 +13,7 @@
 // limitations under the License.
 
 use axum::{
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
 };
 use eyre::Report;
@@ -23,7 +23,12 @@ use std::{error::Error, fmt::Debug};
 #[derive(Debug)]
 pub enum InternalError {
     Internal(Report),
+    /// The request was invalid, for the given reason.
+    BadRequest(String),
+    NotFound,
     Unauthorised,
+    /// The client has made too many requests recently.
+    TooManyRequests,
 }
 
 impl<E: Error + Send + Sync + 'static> From<E> for InternalError {
@@ -36,7 +41,18 @@ impl IntoResponse for InternalError {
     fn into_response(self) -> Response {
         match self {
             Self::Internal(report) => internal_error_response(report),
-            Self::Unauthorised => StatusCode::UNAUTHORIZED.into_response(),
+            Self::BadRequest(reason) => (StatusCode::BAD_REQUEST, reason).into_response(),
+            Self::NotFound => StatusCode::NOT_FOUND.into_response(),
+            Self::Unauthorised => (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Basic realm=\"dancelist\"")],
+            )
+                .into_response(),
+            Self::TooManyRequests => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "Too many submissions. Please wait for a while and try again.",
+            )
+                .into_response(),
         }
     }
 }
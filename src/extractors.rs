@@ -12,8 +12,28 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::{errors::InternalError, model::events::Events, AppState};
-use axum::{async_trait, extract::FromRequestParts, http::request::Parts};
+use crate::{
+    config::{ModerationConfig, SiteConfig},
+    errors::InternalError,
+    model::{events::Events, filters::Filters},
+    AppState,
+};
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts},
+    http::request::Parts,
+};
+use axum_extra::{
+    headers::{authorization::Basic, Authorization, Host},
+    TypedHeader,
+};
+use eyre::eyre;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+};
 
 #[async_trait]
 impl FromRequestParts<AppState> for Events {
@@ -26,3 +46,204 @@ impl FromRequestParts<AppState> for Events {
         Ok(state.events.lock().unwrap().clone())
     }
 }
+
+/// The profile of the site for the hostname to which the request was made, if there is one.
+#[derive(Clone, Debug, Default)]
+pub struct Site(pub Option<SiteConfig>);
+
+impl Site {
+    /// Returns the title for a page on the site, including the site's name if it has one.
+    pub fn title(&self, page_title: impl AsRef<str>) -> String {
+        let page_title = page_title.as_ref();
+        match self.0.as_ref().and_then(|site| site.title.as_deref()) {
+            Some(site_title) => format!("{} - {}", page_title, site_title),
+            None => page_title.to_owned(),
+        }
+    }
+
+    /// Returns the name of the site, or the given hostname if the site profile doesn't have a title.
+    pub fn name(&self, host: &Host) -> String {
+        self.0
+            .as_ref()
+            .and_then(|site| site.title.clone())
+            .unwrap_or_else(|| host.hostname().to_owned())
+    }
+
+    /// Returns the default filters for the site, or no filters if there is no site profile.
+    pub fn default_filters(&self) -> Filters {
+        self.0
+            .as_ref()
+            .map(|site| site.default_filters.clone())
+            .unwrap_or_default()
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for Site {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let site = TypedHeader::<Host>::from_request_parts(parts, state)
+            .await
+            .ok()
+            .and_then(|TypedHeader(host)| state.config.site_for_host(host.hostname()).cloned());
+        Ok(Self(site))
+    }
+}
+
+/// A moderator who has logged in with HTTP basic authentication, with the moderation config.
+///
+/// Rejects the request as not found if moderation isn't configured, or unauthorised if the
+/// credentials are missing or wrong.
+#[derive(Clone, Debug)]
+pub struct Moderator(pub ModerationConfig);
+
+#[async_trait]
+impl FromRequestParts<AppState> for Moderator {
+    type Rejection = InternalError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let moderation = state
+            .config
+            .moderation
+            .as_ref()
+            .ok_or(InternalError::NotFound)?;
+        check_credentials(parts, state, &moderation.username, &moderation.password).await?;
+        Ok(Self(moderation.to_owned()))
+    }
+}
+
+/// An administrator who has logged in with HTTP basic authentication.
+///
+/// The credentials are from the `admin` config section, or the `moderation` section if there is no
+/// `admin` section. Rejects the request as not found if neither is configured, or unauthorised if
+/// the credentials are missing or wrong.
+#[derive(Clone, Debug)]
+pub struct Admin;
+
+#[async_trait]
+impl FromRequestParts<AppState> for Admin {
+    type Rejection = InternalError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let admin = state
+            .config
+            .admin_credentials()
+            .ok_or(InternalError::NotFound)?;
+        check_credentials(parts, state, &admin.username, &admin.password).await?;
+        Ok(Self)
+    }
+}
+
+/// Checks that the request has HTTP basic authentication with the given username and password.
+async fn check_credentials(
+    parts: &mut Parts,
+    state: &AppState,
+    username: &str,
+    password: &str,
+) -> Result<(), InternalError> {
+    let TypedHeader(Authorization(credentials)) =
+        TypedHeader::<Authorization<Basic>>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| InternalError::Unauthorised)?;
+    // Check both so that the time taken doesn't reveal which was wrong.
+    let username_matches = constant_time_eq(credentials.username(), username);
+    let password_matches = constant_time_eq(credentials.password(), password);
+    if username_matches && password_matches {
+        Ok(())
+    } else {
+        Err(InternalError::Unauthorised)
+    }
+}
+
+/// Compares the given strings in time which doesn't depend on where they differ.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    // Compare MACs rather than the strings themselves, so the comparison doesn't depend on their
+    // lengths either.
+    let mac = |value: &str| {
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"dancelist credentials").unwrap();
+        mac.update(value.as_bytes());
+        mac
+    };
+    mac(a).verify_slice(&mac(b).finalize().into_bytes()).is_ok()
+}
+
+/// The IP address of the client which made the request.
+///
+/// This is taken from the last address in the `X-Forwarded-For` header if the spam config says to
+/// trust it, as that is the one added by the reverse proxy, otherwise from the connection.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ClientIp(pub IpAddr);
+
+#[async_trait]
+impl FromRequestParts<AppState> for ClientIp {
+    type Rejection = InternalError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        if state.config.spam.trust_forwarded_for {
+            if let Some(forwarded_for) = parts
+                .headers
+                .get_all("X-Forwarded-For")
+                .iter()
+                .next_back()
+                .and_then(|value| value.to_str().ok())
+            {
+                return forwarded_client(forwarded_for).map(Self);
+            }
+        }
+        let ConnectInfo(address) = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .ok_or_else(|| InternalError::Internal(eyre!("Missing connection info")))?;
+        Ok(Self(address.ip()))
+    }
+}
+
+/// Returns the client address from the given `X-Forwarded-For` header value.
+///
+/// Earlier entries may have been sent by the client, so only the last one, added by the reverse
+/// proxy, can be trusted.
+fn forwarded_client(forwarded_for: &str) -> Result<IpAddr, InternalError> {
+    let last = forwarded_for.rsplit(',').next().unwrap_or_default().trim();
+    last.parse()
+        .map_err(|_| InternalError::BadRequest(format!("Invalid X-Forwarded-For {:?}", last)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forwarded_for_last_hop() {
+        assert_eq!(
+            forwarded_client("192.0.2.1").unwrap(),
+            IpAddr::from([192, 0, 2, 1])
+        );
+        // A client can't choose its address by sending a spoofed leading hop.
+        assert_eq!(
+            forwarded_client("203.0.113.7, 192.0.2.1").unwrap(),
+            IpAddr::from([192, 0, 2, 1])
+        );
+        assert!(forwarded_client("192.0.2.1, nonsense").is_err());
+    }
+
+    #[test]
+    fn compare_credentials() {
+        assert!(constant_time_eq("password", "password"));
+        assert!(!constant_time_eq("password", "passwore"));
+        assert!(!constant_time_eq("password", "pass"));
+        assert!(!constant_time_eq("", "password"));
+    }
+}
@@ -0,0 +1,355 @@
+// Copyright 2024 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Forge backend for Gitea or Forgejo, using the REST API with an access token.
+
+use super::{decode_base64_content, encode_base64_content, CommitAuthor, FileContents, Forge};
+use crate::{config::GiteaConfig, errors::InternalError};
+use axum::async_trait;
+use eyre::eyre;
+use log::trace;
+use reqwest::{header::AUTHORIZATION, Client, Method, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+pub struct Gitea {
+    client: Client,
+    /// The API URL of the repository, like `https://codeberg.org/api/v1/repos/owner/repository`.
+    repo_url: Url,
+    main_branch: String,
+    token: String,
+}
+
+impl Gitea {
+    pub fn new(config: &GiteaConfig) -> Result<Self, InternalError> {
+        let mut repo_url = Url::parse(&config.url)?;
+        repo_url
+            .path_segments_mut()
+            .map_err(|()| InternalError::Internal(eyre!("Invalid Gitea URL {}", config.url)))?
+            .pop_if_empty()
+            .extend(["api", "v1", "repos", &config.owner, &config.repository]);
+        Ok(Self {
+            client: Client::new(),
+            repo_url,
+            main_branch: config.main_branch.clone(),
+            token: config.token.clone(),
+        })
+    }
+
+    /// Returns a request to the given path under the repository API URL.
+    fn request<'a>(
+        &self,
+        method: Method,
+        path: impl IntoIterator<Item = &'a str>,
+    ) -> RequestBuilder {
+        let mut url = self.repo_url.clone();
+        url.path_segments_mut().unwrap().extend(path);
+        self.client
+            .request(method, url)
+            .header(AUTHORIZATION, format!("token {}", self.token))
+    }
+
+    fn contents_request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.request(method, ["contents"].into_iter().chain(path.split('/')))
+    }
+}
+
+#[async_trait]
+impl Forge for Gitea {
+    fn main_branch(&self) -> &str {
+        &self.main_branch
+    }
+
+    async fn create_branch(&self, name: &str, from_branch: &str) -> Result<bool, InternalError> {
+        let response = self
+            .request(Method::POST, ["branches"])
+            .json(&CreateBranch {
+                new_branch_name: name,
+                old_branch_name: from_branch,
+            })
+            .send()
+            .await?;
+        if response.status() == StatusCode::CONFLICT {
+            return Ok(false);
+        }
+        response.error_for_status()?;
+        Ok(true)
+    }
+
+    async fn get_file(
+        &self,
+        path: &str,
+        branch: &str,
+    ) -> Result<Option<FileContents>, InternalError> {
+        let response = self
+            .contents_request(Method::GET, path)
+            .query(&[("ref", branch)])
+            .send()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let contents = response.error_for_status()?.json::<Contents>().await?;
+        let content = contents
+            .content
+            .ok_or_else(|| InternalError::Internal(eyre!("File {} has no content", path)))?;
+        Ok(Some(FileContents {
+            content: decode_base64_content(&content)?,
+            version: contents.sha,
+        }))
+    }
+
+    async fn create_file(
+        &self,
+        path: &str,
+        branch: &str,
+        content: &str,
+        message: &str,
+        author: Option<&CommitAuthor>,
+    ) -> Result<(), InternalError> {
+        let response = self
+            .contents_request(Method::POST, path)
+            .json(&ChangeFile {
+                content: encode_base64_content(content),
+                message,
+                branch,
+                sha: None,
+                author: author.map(Into::into),
+            })
+            .send()
+            .await?
+            .error_for_status()?;
+        trace!("Create: {:?}", response);
+        Ok(())
+    }
+
+    async fn update_file(
+        &self,
+        path: &str,
+        branch: &str,
+        existing: &FileContents,
+        content: &str,
+        message: &str,
+        author: Option<&CommitAuthor>,
+    ) -> Result<(), InternalError> {
+        let response = self
+            .contents_request(Method::PUT, path)
+            .json(&ChangeFile {
+                content: encode_base64_content(content),
+                message,
+                branch,
+                sha: Some(&existing.version),
+                author: author.map(Into::into),
+            })
+            .send()
+            .await?
+            .error_for_status()?;
+        trace!("Update: {:?}", response);
+        Ok(())
+    }
+
+    async fn create_pull_request(
+        &self,
+        branch: &str,
+        title: &str,
+        body: &str,
+    ) -> Result<Url, InternalError> {
+        let pr = self
+            .request(Method::POST, ["pulls"])
+            .json(&CreatePullRequest {
+                head: branch,
+                base: &self.main_branch,
+                title,
+                body,
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<PullRequest>()
+            .await?;
+        trace!("Made PR {:?}", pr);
+        Ok(Url::parse(&pr.html_url)?)
+    }
+}
+
+#[derive(Serialize)]
+struct CreateBranch<'a> {
+    new_branch_name: &'a str,
+    old_branch_name: &'a str,
+}
+
+#[derive(Deserialize)]
+struct Contents {
+    sha: String,
+    /// The base64 encoded content of the file.
+    content: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ChangeFile<'a> {
+    /// The new content of the file, base64 encoded.
+    content: String,
+    message: &'a str,
+    branch: &'a str,
+    /// The SHA of the file being replaced, if it already exists.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author: Option<Identity<'a>>,
+}
+
+#[derive(Serialize)]
+struct Identity<'a> {
+    name: &'a str,
+    email: &'a str,
+}
+
+impl<'a> From<&'a CommitAuthor> for Identity<'a> {
+    fn from(author: &'a CommitAuthor) -> Self {
+        Self {
+            name: &author.name,
+            email: &author.email,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct CreatePullRequest<'a> {
+    head: &'a str,
+    base: &'a str,
+    title: &'a str,
+    body: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct PullRequest {
+    html_url: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::forge::mock::{check_forge, serve, MockRepo, SharedRepo};
+    use axum::{
+        extract::{Path, Query, State},
+        http::StatusCode,
+        routing::{get, post},
+        Json, Router,
+    };
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+
+    #[derive(Deserialize)]
+    struct MockChangeFile {
+        content: String,
+        message: String,
+        branch: String,
+        sha: Option<String>,
+        author: Option<MockIdentity>,
+    }
+
+    #[derive(Deserialize)]
+    struct MockIdentity {
+        email: String,
+    }
+
+    async fn create_branch(State(repo): State<SharedRepo>, Json(body): Json<Value>) -> StatusCode {
+        match repo.lock().unwrap().create_branch(
+            body["new_branch_name"].as_str().unwrap(),
+            body["old_branch_name"].as_str().unwrap(),
+        ) {
+            Some(true) => StatusCode::CREATED,
+            Some(false) => StatusCode::CONFLICT,
+            None => StatusCode::NOT_FOUND,
+        }
+    }
+
+    async fn get_contents(
+        State(repo): State<SharedRepo>,
+        Path((_, _, path)): Path<(String, String, String)>,
+        Query(query): Query<HashMap<String, String>>,
+    ) -> Result<Json<Value>, StatusCode> {
+        let repo = repo.lock().unwrap();
+        let file = repo
+            .get_file(&query["ref"], &path)
+            .ok_or(StatusCode::NOT_FOUND)?;
+        Ok(Json(json!({
+            "type": "file",
+            "path": path,
+            "sha": file.version,
+            "encoding": "base64",
+            "content": STANDARD.encode(&file.content),
+        })))
+    }
+
+    async fn change_contents(
+        State(repo): State<SharedRepo>,
+        Path((_, _, path)): Path<(String, String, String)>,
+        Json(body): Json<MockChangeFile>,
+    ) -> Result<Json<Value>, StatusCode> {
+        let content = String::from_utf8(STANDARD.decode(&body.content).unwrap()).unwrap();
+        let version = repo
+            .lock()
+            .unwrap()
+            .commit(
+                &body.branch,
+                &path,
+                &content,
+                &body.message,
+                body.author.map(|author| author.email),
+                body.sha.as_deref(),
+            )
+            .ok_or(StatusCode::UNPROCESSABLE_ENTITY)?;
+        Ok(Json(json!({ "content": { "sha": version } })))
+    }
+
+    async fn create_pull(
+        State(repo): State<SharedRepo>,
+        Json(body): Json<Value>,
+    ) -> (StatusCode, Json<Value>) {
+        let url = repo.lock().unwrap().create_pull(
+            body["head"].as_str().unwrap(),
+            body["base"].as_str().unwrap(),
+            body["title"].as_str().unwrap(),
+            body["body"].as_str().unwrap(),
+            |number| format!("https://codeberg.org/owner/repo/pulls/{}", number),
+        );
+        (StatusCode::CREATED, Json(json!({ "html_url": url })))
+    }
+
+    #[tokio::test]
+    async fn add_and_edit_events() {
+        let repo = SharedRepo::new(MockRepo::new("main").into());
+        let router = Router::new()
+            .route("/api/v1/repos/:owner/:repo/branches", post(create_branch))
+            .route(
+                "/api/v1/repos/:owner/:repo/contents/*path",
+                get(get_contents).post(change_contents).put(change_contents),
+            )
+            .route("/api/v1/repos/:owner/:repo/pulls", post(create_pull))
+            .with_state(repo.clone());
+        let url = serve(router).await;
+
+        let gitea = Gitea::new(&GiteaConfig {
+            url,
+            owner: "owner".to_string(),
+            repository: "repo".to_string(),
+            main_branch: "main".to_string(),
+            token: "token".to_string(),
+        })
+        .unwrap();
+        check_forge(&gitea, &repo).await;
+    }
+}
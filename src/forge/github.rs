@@ -0,0 +1,388 @@
+// Copyright 2024 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Forge backend for GitHub, authenticating as a GitHub App.
+
+use super::{decode_base64_content, CommitAuthor, FileContents, Forge};
+use crate::{config::GitHubConfig, errors::InternalError};
+use axum::async_trait;
+use eyre::eyre;
+use jsonwebtoken::EncodingKey;
+use log::trace;
+use octocrab::{
+    models::repos::{self, Object},
+    params::repos::Reference,
+    Octocrab, OctocrabBuilder,
+};
+use std::fs;
+use url::Url;
+
+pub struct GitHub {
+    octocrab: Octocrab,
+    owner: String,
+    repository: String,
+    main_branch: String,
+}
+
+impl GitHub {
+    /// Authenticates as the configured GitHub App, for the installation on the configured
+    /// repository.
+    pub async fn connect(config: &GitHubConfig) -> Result<Self, InternalError> {
+        let file_contents = fs::read(&config.private_key)?;
+        let key = EncodingKey::from_rsa_pem(&file_contents)?;
+        let octocrab = OctocrabBuilder::new()
+            .app(config.app_id.into(), key)
+            .build()?;
+
+        // Get the installation for the repository we care about.
+        let installation = octocrab
+            .apps()
+            .get_repository_installation(&config.owner, &config.repository)
+            .await?;
+
+        // Make an Octocrab for that installation.
+        Ok(Self::new(
+            octocrab.installation(installation.id)?,
+            &config.owner,
+            &config.repository,
+            &config.main_branch,
+        ))
+    }
+
+    pub fn new(octocrab: Octocrab, owner: &str, repository: &str, main_branch: &str) -> Self {
+        Self {
+            octocrab,
+            owner: owner.to_owned(),
+            repository: repository.to_owned(),
+            main_branch: main_branch.to_owned(),
+        }
+    }
+
+    /// Returns the SHA for the current head of the given branch.
+    async fn sha_for_branch(&self, branch_name: &str) -> Result<String, InternalError> {
+        let head = self
+            .octocrab
+            .repos(&self.owner, &self.repository)
+            .get_ref(&Reference::Branch(branch_name.to_owned()))
+            .await?;
+        if let Object::Commit { sha, .. } = head.object {
+            Ok(sha)
+        } else {
+            Err(InternalError::Internal(eyre!(
+                "Ref {} was not a commit.",
+                branch_name
+            )))
+        }
+    }
+}
+
+#[async_trait]
+impl Forge for GitHub {
+    fn main_branch(&self) -> &str {
+        &self.main_branch
+    }
+
+    async fn create_branch(&self, name: &str, from_branch: &str) -> Result<bool, InternalError> {
+        let head_sha = self.sha_for_branch(from_branch).await?;
+        match self
+            .octocrab
+            .repos(&self.owner, &self.repository)
+            .create_ref(&Reference::Branch(name.to_owned()), head_sha)
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(octocrab::Error::GitHub { source, .. })
+                if source.message == "Reference already exists" =>
+            {
+                Ok(false)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn get_file(
+        &self,
+        path: &str,
+        branch: &str,
+    ) -> Result<Option<FileContents>, InternalError> {
+        let contents = match self
+            .octocrab
+            .repos(&self.owner, &self.repository)
+            .get_content()
+            .path(path)
+            .r#ref(branch)
+            .send()
+            .await
+        {
+            Ok(contents) => contents,
+            Err(octocrab::Error::GitHub { source, .. }) if source.status_code.as_u16() == 404 => {
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let Some(file) = contents.items.into_iter().next() else {
+            return Ok(None);
+        };
+        let content = file
+            .content
+            .ok_or_else(|| InternalError::Internal(eyre!("File {} has no content", path)))?;
+        Ok(Some(FileContents {
+            content: decode_base64_content(&content)?,
+            version: file.sha,
+        }))
+    }
+
+    async fn create_file(
+        &self,
+        path: &str,
+        branch: &str,
+        content: &str,
+        message: &str,
+        author: Option<&CommitAuthor>,
+    ) -> Result<(), InternalError> {
+        let repo = self.octocrab.repos(&self.owner, &self.repository);
+        let mut create = repo.create_file(path, message, content).branch(branch);
+        if let Some(author) = author {
+            create = create.author(commit_author(author));
+        }
+        let create = create.send().await?;
+        trace!("Create: {:?}", create);
+        Ok(())
+    }
+
+    async fn update_file(
+        &self,
+        path: &str,
+        branch: &str,
+        existing: &FileContents,
+        content: &str,
+        message: &str,
+        author: Option<&CommitAuthor>,
+    ) -> Result<(), InternalError> {
+        let repo = self.octocrab.repos(&self.owner, &self.repository);
+        let mut update = repo
+            .update_file(path, message, content, &existing.version)
+            .branch(branch);
+        if let Some(author) = author {
+            update = update.author(commit_author(author));
+        }
+        let update = update.send().await?;
+        trace!("Update: {:?}", update);
+        Ok(())
+    }
+
+    async fn create_pull_request(
+        &self,
+        branch: &str,
+        title: &str,
+        body: &str,
+    ) -> Result<Url, InternalError> {
+        let pr = self
+            .octocrab
+            .pulls(&self.owner, &self.repository)
+            .create(title, branch, &self.main_branch)
+            .body(body)
+            .send()
+            .await?;
+        trace!("Made PR {:?}", pr);
+        pr.html_url
+            .ok_or_else(|| InternalError::Internal(eyre!("PR missing html_url")))
+    }
+}
+
+fn commit_author(author: &CommitAuthor) -> repos::CommitAuthor {
+    repos::CommitAuthor {
+        name: author.name.clone(),
+        email: author.email.clone(),
+        date: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::forge::mock::{check_forge, serve, MockRepo, SharedRepo};
+    use axum::{
+        extract::{Path, Query, State},
+        http::StatusCode,
+        routing::{get, post},
+        Json, Router,
+    };
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::Deserialize;
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+
+    #[derive(Deserialize)]
+    struct MockChangeFile {
+        message: String,
+        content: String,
+        sha: Option<String>,
+        branch: String,
+        author: Option<repos::CommitAuthor>,
+    }
+
+    fn error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+        (status, Json(json!({ "message": message })))
+    }
+
+    fn git_ref(branch: &str, sha: &str) -> Json<Value> {
+        Json(json!({
+            "ref": format!("refs/heads/{}", branch),
+            "node_id": "node",
+            "url": format!("https://api.github.com/repos/owner/repo/git/refs/heads/{}", branch),
+            "object": {
+                "type": "commit",
+                "sha": sha,
+                "url": format!("https://api.github.com/repos/owner/repo/git/commits/{}", sha),
+            },
+        }))
+    }
+
+    fn content(path: &str, sha: &str, content: Option<&str>) -> Value {
+        let url = format!("https://api.github.com/repos/owner/repo/contents/{}", path);
+        json!({
+            "name": path.rsplit('/').next().unwrap(),
+            "path": path,
+            "sha": sha,
+            "size": content.map(str::len).unwrap_or_default(),
+            "url": url,
+            "type": "file",
+            "encoding": "base64",
+            "content": content.map(|content| STANDARD.encode(content)),
+            "_links": { "self": url },
+        })
+    }
+
+    async fn get_ref(
+        State(repo): State<SharedRepo>,
+        Path((_, _, branch)): Path<(String, String, String)>,
+    ) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+        let repo = repo.lock().unwrap();
+        let head = repo
+            .branch_head(&branch)
+            .ok_or_else(|| error(StatusCode::NOT_FOUND, "Not Found"))?;
+        Ok(git_ref(&branch, head))
+    }
+
+    async fn create_ref(
+        State(repo): State<SharedRepo>,
+        Json(body): Json<Value>,
+    ) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+        let mut repo = repo.lock().unwrap();
+        let branch = body["ref"]
+            .as_str()
+            .unwrap()
+            .strip_prefix("refs/heads/")
+            .unwrap();
+        let sha = body["sha"].as_str().unwrap();
+        let from = repo
+            .branch_with_head(sha)
+            .ok_or_else(|| error(StatusCode::UNPROCESSABLE_ENTITY, "Object does not exist"))?;
+        if repo.create_branch(branch, &from) == Some(true) {
+            Ok((StatusCode::CREATED, git_ref(branch, sha)))
+        } else {
+            Err(error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Reference already exists",
+            ))
+        }
+    }
+
+    async fn get_contents(
+        State(repo): State<SharedRepo>,
+        Path((_, _, path)): Path<(String, String, String)>,
+        Query(query): Query<HashMap<String, String>>,
+    ) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+        let repo = repo.lock().unwrap();
+        let file = repo
+            .get_file(&query["ref"], &path)
+            .ok_or_else(|| error(StatusCode::NOT_FOUND, "Not Found"))?;
+        Ok(Json(content(&path, &file.version, Some(&file.content))))
+    }
+
+    async fn put_contents(
+        State(repo): State<SharedRepo>,
+        Path((_, _, path)): Path<(String, String, String)>,
+        Json(body): Json<MockChangeFile>,
+    ) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+        let content_string = String::from_utf8(STANDARD.decode(&body.content).unwrap()).unwrap();
+        let sha = repo
+            .lock()
+            .unwrap()
+            .commit(
+                &body.branch,
+                &path,
+                &content_string,
+                &body.message,
+                body.author.map(|author| author.email),
+                body.sha.as_deref(),
+            )
+            .ok_or_else(|| error(StatusCode::CONFLICT, "sha does not match"))?;
+        Ok(Json(json!({
+            "content": content(&path, &sha, None),
+            "commit": { "sha": sha },
+        })))
+    }
+
+    async fn create_pull(
+        State(repo): State<SharedRepo>,
+        Json(body): Json<Value>,
+    ) -> (StatusCode, Json<Value>) {
+        let mut repo = repo.lock().unwrap();
+        let number = repo.pulls.len() + 1;
+        let url = repo.create_pull(
+            body["head"].as_str().unwrap(),
+            body["base"].as_str().unwrap(),
+            body["title"].as_str().unwrap(),
+            body["body"].as_str().unwrap(),
+            |number| format!("https://github.com/owner/repo/pull/{}", number),
+        );
+        (
+            StatusCode::CREATED,
+            Json(json!({
+                "url": format!("https://api.github.com/repos/owner/repo/pulls/{}", number),
+                "id": number,
+                "number": number,
+                "html_url": url,
+                "head": { "ref": body["head"], "sha": "head" },
+                "base": { "ref": body["base"], "sha": "base" },
+            })),
+        )
+    }
+
+    #[tokio::test]
+    async fn add_and_edit_events() {
+        let repo = SharedRepo::new(MockRepo::new("main").into());
+        let router = Router::new()
+            .route("/repos/:owner/:repo/git/ref/heads/*branch", get(get_ref))
+            .route("/repos/:owner/:repo/git/refs", post(create_ref))
+            .route(
+                "/repos/:owner/:repo/contents/*path",
+                get(get_contents).put(put_contents),
+            )
+            .route("/repos/:owner/:repo/pulls", post(create_pull))
+            .with_state(repo.clone());
+        let url = serve(router).await;
+
+        let octocrab = OctocrabBuilder::new()
+            .base_uri(url)
+            .unwrap()
+            .personal_token("token".to_string())
+            .build()
+            .unwrap();
+        let github = GitHub::new(octocrab, "owner", "repo", "main");
+        check_forge(&github, &repo).await;
+    }
+}
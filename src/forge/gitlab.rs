@@ -0,0 +1,377 @@
+// Copyright 2024 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Forge backend for GitLab, using the REST API with an access token.
+
+use super::{decode_base64_content, CommitAuthor, FileContents, Forge};
+use crate::{config::GitLabConfig, errors::InternalError};
+use axum::async_trait;
+use eyre::eyre;
+use log::trace;
+use reqwest::{Client, Method, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+pub struct GitLab {
+    client: Client,
+    /// The API URL of the project, like `https://gitlab.com/api/v4/projects/group%2Frepository`.
+    project_url: Url,
+    main_branch: String,
+    token: String,
+}
+
+impl GitLab {
+    pub fn new(config: &GitLabConfig) -> Result<Self, InternalError> {
+        let mut project_url = Url::parse(&config.url)?;
+        // The project path is a single segment, so any slashes in it will be percent-encoded.
+        project_url
+            .path_segments_mut()
+            .map_err(|()| InternalError::Internal(eyre!("Invalid GitLab URL {}", config.url)))?
+            .pop_if_empty()
+            .extend(["api", "v4", "projects", &config.project]);
+        Ok(Self {
+            client: Client::new(),
+            project_url,
+            main_branch: config.main_branch.clone(),
+            token: config.token.clone(),
+        })
+    }
+
+    /// Returns a request to the given path under the project API URL.
+    fn request<'a>(
+        &self,
+        method: Method,
+        path: impl IntoIterator<Item = &'a str>,
+    ) -> RequestBuilder {
+        let mut url = self.project_url.clone();
+        url.path_segments_mut().unwrap().extend(path);
+        self.client
+            .request(method, url)
+            .header("PRIVATE-TOKEN", &self.token)
+    }
+
+    /// Returns a request for the file with the given path. GitLab expects the path as a single
+    /// segment, so any slashes are percent-encoded.
+    fn file_request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.request(method, ["repository", "files", path])
+    }
+}
+
+#[async_trait]
+impl Forge for GitLab {
+    fn main_branch(&self) -> &str {
+        &self.main_branch
+    }
+
+    async fn create_branch(&self, name: &str, from_branch: &str) -> Result<bool, InternalError> {
+        let response = self
+            .request(Method::POST, ["repository", "branches"])
+            .query(&[("branch", name), ("ref", from_branch)])
+            .send()
+            .await?;
+        if response.status() == StatusCode::BAD_REQUEST {
+            let error = response.json::<ErrorMessage>().await?;
+            return if error.message == "Branch already exists" {
+                Ok(false)
+            } else {
+                Err(InternalError::Internal(eyre!(
+                    "Failed to create branch {}: {}",
+                    name,
+                    error.message
+                )))
+            };
+        }
+        response.error_for_status()?;
+        Ok(true)
+    }
+
+    async fn get_file(
+        &self,
+        path: &str,
+        branch: &str,
+    ) -> Result<Option<FileContents>, InternalError> {
+        let response = self
+            .file_request(Method::GET, path)
+            .query(&[("ref", branch)])
+            .send()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let file = response.error_for_status()?.json::<File>().await?;
+        Ok(Some(FileContents {
+            content: decode_base64_content(&file.content)?,
+            version: file.last_commit_id,
+        }))
+    }
+
+    async fn create_file(
+        &self,
+        path: &str,
+        branch: &str,
+        content: &str,
+        message: &str,
+        author: Option<&CommitAuthor>,
+    ) -> Result<(), InternalError> {
+        let response = self
+            .file_request(Method::POST, path)
+            .json(&ChangeFile {
+                branch,
+                content,
+                commit_message: message,
+                last_commit_id: None,
+                author_name: author.map(|author| author.name.as_str()),
+                author_email: author.map(|author| author.email.as_str()),
+            })
+            .send()
+            .await?
+            .error_for_status()?;
+        trace!("Create: {:?}", response);
+        Ok(())
+    }
+
+    async fn update_file(
+        &self,
+        path: &str,
+        branch: &str,
+        existing: &FileContents,
+        content: &str,
+        message: &str,
+        author: Option<&CommitAuthor>,
+    ) -> Result<(), InternalError> {
+        let response = self
+            .file_request(Method::PUT, path)
+            .json(&ChangeFile {
+                branch,
+                content,
+                commit_message: message,
+                last_commit_id: Some(&existing.version),
+                author_name: author.map(|author| author.name.as_str()),
+                author_email: author.map(|author| author.email.as_str()),
+            })
+            .send()
+            .await?
+            .error_for_status()?;
+        trace!("Update: {:?}", response);
+        Ok(())
+    }
+
+    async fn create_pull_request(
+        &self,
+        branch: &str,
+        title: &str,
+        body: &str,
+    ) -> Result<Url, InternalError> {
+        let mr = self
+            .request(Method::POST, ["merge_requests"])
+            .json(&CreateMergeRequest {
+                source_branch: branch,
+                target_branch: &self.main_branch,
+                title,
+                description: body,
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<MergeRequest>()
+            .await?;
+        trace!("Made MR {:?}", mr);
+        Ok(Url::parse(&mr.web_url)?)
+    }
+}
+
+#[derive(Deserialize)]
+struct ErrorMessage {
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct File {
+    /// The base64 encoded content of the file.
+    content: String,
+    last_commit_id: String,
+}
+
+#[derive(Serialize)]
+struct ChangeFile<'a> {
+    branch: &'a str,
+    content: &'a str,
+    commit_message: &'a str,
+    /// The ID of the last commit to change the file, if it already exists.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_commit_id: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author_name: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author_email: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct CreateMergeRequest<'a> {
+    source_branch: &'a str,
+    target_branch: &'a str,
+    title: &'a str,
+    description: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct MergeRequest {
+    web_url: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::forge::mock::{check_forge, serve, MockRepo, SharedRepo};
+    use axum::{
+        extract::{Path, Query, State},
+        http::{HeaderMap, StatusCode},
+        routing::{get, post},
+        Json, Router,
+    };
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+
+    const PROJECT: &str = "group/repo";
+
+    #[derive(Deserialize)]
+    struct MockChangeFile {
+        branch: String,
+        content: String,
+        commit_message: String,
+        last_commit_id: Option<String>,
+        author_email: Option<String>,
+    }
+
+    fn check_request(project: &str, headers: &HeaderMap) -> Result<(), StatusCode> {
+        if project != PROJECT {
+            Err(StatusCode::NOT_FOUND)
+        } else if headers["PRIVATE-TOKEN"] != "token" {
+            Err(StatusCode::UNAUTHORIZED)
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn create_branch(
+        State(repo): State<SharedRepo>,
+        Path(project): Path<String>,
+        headers: HeaderMap,
+        Query(query): Query<HashMap<String, String>>,
+    ) -> Result<(StatusCode, Json<Value>), StatusCode> {
+        check_request(&project, &headers)?;
+        match repo
+            .lock()
+            .unwrap()
+            .create_branch(&query["branch"], &query["ref"])
+        {
+            Some(true) => Ok((
+                StatusCode::CREATED,
+                Json(json!({ "name": query["branch"] })),
+            )),
+            Some(false) => Ok((
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "message": "Branch already exists" })),
+            )),
+            None => Err(StatusCode::NOT_FOUND),
+        }
+    }
+
+    async fn get_file(
+        State(repo): State<SharedRepo>,
+        Path((project, path)): Path<(String, String)>,
+        headers: HeaderMap,
+        Query(query): Query<HashMap<String, String>>,
+    ) -> Result<Json<Value>, StatusCode> {
+        check_request(&project, &headers)?;
+        let repo = repo.lock().unwrap();
+        let file = repo
+            .get_file(&query["ref"], &path)
+            .ok_or(StatusCode::NOT_FOUND)?;
+        Ok(Json(json!({
+            "file_path": path,
+            "encoding": "base64",
+            "content": STANDARD.encode(&file.content),
+            "last_commit_id": file.version,
+        })))
+    }
+
+    async fn change_file(
+        State(repo): State<SharedRepo>,
+        Path((project, path)): Path<(String, String)>,
+        headers: HeaderMap,
+        Json(body): Json<MockChangeFile>,
+    ) -> Result<Json<Value>, StatusCode> {
+        check_request(&project, &headers)?;
+        repo.lock()
+            .unwrap()
+            .commit(
+                &body.branch,
+                &path,
+                &body.content,
+                &body.commit_message,
+                body.author_email,
+                body.last_commit_id.as_deref(),
+            )
+            .ok_or(StatusCode::BAD_REQUEST)?;
+        Ok(Json(json!({ "file_path": path, "branch": body.branch })))
+    }
+
+    async fn create_merge_request(
+        State(repo): State<SharedRepo>,
+        Path(project): Path<String>,
+        headers: HeaderMap,
+        Json(body): Json<Value>,
+    ) -> Result<(StatusCode, Json<Value>), StatusCode> {
+        check_request(&project, &headers)?;
+        let url = repo.lock().unwrap().create_pull(
+            body["source_branch"].as_str().unwrap(),
+            body["target_branch"].as_str().unwrap(),
+            body["title"].as_str().unwrap(),
+            body["description"].as_str().unwrap(),
+            |number| format!("https://gitlab.com/group/repo/-/merge_requests/{}", number),
+        );
+        Ok((StatusCode::CREATED, Json(json!({ "web_url": url }))))
+    }
+
+    #[tokio::test]
+    async fn add_and_edit_events() {
+        let repo = SharedRepo::new(MockRepo::new("main").into());
+        let router = Router::new()
+            .route(
+                "/api/v4/projects/:project/repository/branches",
+                post(create_branch),
+            )
+            .route(
+                "/api/v4/projects/:project/repository/files/:path",
+                get(get_file).post(change_file).put(change_file),
+            )
+            .route(
+                "/api/v4/projects/:project/merge_requests",
+                post(create_merge_request),
+            )
+            .with_state(repo.clone());
+        let url = serve(router).await;
+
+        let gitlab = GitLab::new(&GitLabConfig {
+            url,
+            project: PROJECT.to_string(),
+            main_branch: "main".to_string(),
+            token: "token".to_string(),
+        })
+        .unwrap();
+        check_forge(&gitlab, &repo).await;
+    }
+}
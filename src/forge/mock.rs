@@ -0,0 +1,321 @@
+// Copyright 2024 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An in-memory repository for the mock forge servers used to test each backend.
+
+use super::{add_event_to_file, add_events_to_file, edit_event_in_file, Forge};
+use crate::{
+    errors::InternalError,
+    model::{
+        dancestyle::DanceStyle,
+        event::{Event, EventTime},
+        events::Events,
+    },
+};
+use axum::Router;
+use chrono::NaiveDate;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+use tokio::net::TcpListener;
+
+pub type SharedRepo = Arc<Mutex<MockRepo>>;
+
+#[derive(Clone, Debug, Default)]
+pub struct MockRepo {
+    branches: BTreeMap<String, MockBranch>,
+    pub commits: Vec<MockCommit>,
+    pub pulls: Vec<MockPull>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct MockBranch {
+    /// The ID of the last commit on the branch.
+    head: String,
+    files: BTreeMap<String, MockFile>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MockFile {
+    pub content: String,
+    /// The ID of the last commit which changed the file.
+    pub version: String,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MockCommit {
+    pub branch: String,
+    pub path: String,
+    pub message: String,
+    pub author_email: Option<String>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MockPull {
+    pub head: String,
+    pub base: String,
+    pub title: String,
+    pub body: String,
+    pub url: String,
+}
+
+impl MockRepo {
+    /// Creates a new repository with a single empty branch.
+    pub fn new(main_branch: &str) -> Self {
+        let mut repo = Self::default();
+        repo.branches.insert(
+            main_branch.to_owned(),
+            MockBranch {
+                head: "initial".to_string(),
+                files: BTreeMap::new(),
+            },
+        );
+        repo
+    }
+
+    /// Returns the ID of the last commit on the given branch.
+    pub fn branch_head(&self, branch: &str) -> Option<&str> {
+        Some(&self.branches.get(branch)?.head)
+    }
+
+    /// Returns the name of a branch whose last commit has the given ID.
+    pub fn branch_with_head(&self, head: &str) -> Option<String> {
+        self.branches
+            .iter()
+            .find(|(_, branch)| branch.head == head)
+            .map(|(name, _)| name.to_owned())
+    }
+
+    /// Creates a new branch from the given existing branch.
+    ///
+    /// Returns `None` if `from` doesn't exist, or `Some(false)` if `name` already exists.
+    pub fn create_branch(&mut self, name: &str, from: &str) -> Option<bool> {
+        let from = self.branches.get(from)?.clone();
+        if self.branches.contains_key(name) {
+            return Some(false);
+        }
+        self.branches.insert(name.to_owned(), from);
+        Some(true)
+    }
+
+    pub fn get_file(&self, branch: &str, path: &str) -> Option<&MockFile> {
+        self.branches.get(branch)?.files.get(path)
+    }
+
+    /// Commits the given content to the file on the given branch, and returns the new commit ID.
+    ///
+    /// `version` must be the current version of the file if it exists, or `None` to create a new
+    /// file. Returns `None` if it doesn't match or the branch doesn't exist.
+    pub fn commit(
+        &mut self,
+        branch: &str,
+        path: &str,
+        content: &str,
+        message: &str,
+        author_email: Option<String>,
+        version: Option<&str>,
+    ) -> Option<String> {
+        let id = format!("commit{}", self.commits.len());
+        let mock_branch = self.branches.get_mut(branch)?;
+        if mock_branch
+            .files
+            .get(path)
+            .map(|file| file.version.as_str())
+            != version
+        {
+            return None;
+        }
+        mock_branch.head = id.clone();
+        mock_branch.files.insert(
+            path.to_owned(),
+            MockFile {
+                content: content.to_owned(),
+                version: id.clone(),
+            },
+        );
+        self.commits.push(MockCommit {
+            branch: branch.to_owned(),
+            path: path.to_owned(),
+            message: message.to_owned(),
+            author_email,
+        });
+        Some(id)
+    }
+
+    /// Records a new pull request, and returns its URL as constructed by `url` from its number.
+    pub fn create_pull(
+        &mut self,
+        head: &str,
+        base: &str,
+        title: &str,
+        body: &str,
+        url: impl FnOnce(usize) -> String,
+    ) -> String {
+        let url = url(self.pulls.len() + 1);
+        self.pulls.push(MockPull {
+            head: head.to_owned(),
+            base: base.to_owned(),
+            title: title.to_owned(),
+            body: body.to_owned(),
+            url: url.clone(),
+        });
+        url
+    }
+
+    /// Parses the events in the given file on the given branch.
+    fn events(&self, branch: &str, path: &str) -> Vec<Event> {
+        let file = self.get_file(branch, path).unwrap();
+        serde_yaml::from_str::<Events>(&file.content)
+            .unwrap()
+            .events
+    }
+}
+
+/// Starts serving the given router on a free local port, and returns its base URL.
+pub async fn serve(router: Router) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    format!("http://{}/", address)
+}
+
+fn test_event(name: &str, country: &str, city: &str, day: u32) -> Event {
+    Event {
+        name: name.to_string(),
+        details: None,
+        links: vec!["https://example.com/".to_string()],
+        time: EventTime::DateOnly {
+            start_date: NaiveDate::from_ymd_opt(2030, 1, day).unwrap(),
+            end_date: NaiveDate::from_ymd_opt(2030, 1, day).unwrap(),
+        },
+        country: country.to_string(),
+        state: None,
+        city: city.to_string(),
+        styles: vec![DanceStyle::Contra],
+        workshop: false,
+        social: true,
+        bands: vec![],
+        callers: vec![],
+        price: None,
+        organisation: None,
+        cancelled: false,
+        source: None,
+    }
+}
+
+/// Proposes some changes via the given forge, and checks that they end up in the mock repository
+/// which it is connected to.
+pub async fn check_forge(forge: &dyn Forge, repo: &SharedRepo) {
+    let london = "events/uk/london.yaml";
+    let existing = test_event("Existing dance", "UK", "London", 1);
+    {
+        let mut repo = repo.lock().unwrap();
+        let content = Events {
+            events: vec![existing.clone()],
+        }
+        .to_yaml_string()
+        .unwrap();
+        repo.commit("main", london, &content, "Initial", None, None)
+            .unwrap();
+        // Take the first choice of branch name, so a suffix must be added.
+        repo.create_branch("add-uk-london-new_dance", "main")
+            .unwrap();
+    }
+
+    // Add an event to an existing file.
+    let new = test_event("New dance", "UK", "London", 2);
+    let url = add_event_to_file(new.clone(), london, Some("user@example.com"), forge)
+        .await
+        .unwrap();
+    {
+        let repo = repo.lock().unwrap();
+        let pull = repo.pulls.last().unwrap();
+        assert_eq!(url.as_str(), pull.url);
+        assert_eq!(pull.head, "add-uk-london-new_dance1");
+        assert_eq!(pull.base, "main");
+        assert_eq!(pull.title, "Add New dance in London");
+        assert_eq!(pull.body, "Added from web form.");
+        assert_eq!(
+            repo.commits.last().unwrap(),
+            &MockCommit {
+                branch: "add-uk-london-new_dance1".to_string(),
+                path: london.to_string(),
+                message: "Add New dance in London".to_string(),
+                author_email: Some("user@example.com".to_string()),
+            }
+        );
+        assert_eq!(
+            repo.events("add-uk-london-new_dance1", london),
+            vec![existing.clone(), new]
+        );
+        assert_eq!(repo.events("main", london), vec![existing.clone()]);
+    }
+
+    // Add several events to a new file.
+    let paris = "events/france/paris.yaml";
+    let bals = vec![
+        test_event("Bal", "France", "Paris", 4),
+        test_event("Bal", "France", "Paris", 3),
+    ];
+    let url = add_events_to_file(bals.clone(), paris, None, forge)
+        .await
+        .unwrap();
+    {
+        let repo = repo.lock().unwrap();
+        let pull = repo.pulls.last().unwrap();
+        assert_eq!(url.as_str(), pull.url);
+        assert_eq!(pull.head, "add-france-paris-bal");
+        assert_eq!(pull.title, "Add 2 events for Bal in Paris");
+        assert_eq!(repo.commits.last().unwrap().author_email, None);
+        assert_eq!(
+            repo.events("add-france-paris-bal", paris),
+            vec![bals[1].clone(), bals[0].clone()]
+        );
+    }
+
+    // Edit an existing event.
+    let edited = Event {
+        bands: vec!["Band".to_string()],
+        ..existing.clone()
+    };
+    let url = edit_event_in_file(&existing, edited.clone(), london, None, forge)
+        .await
+        .unwrap();
+    {
+        let repo = repo.lock().unwrap();
+        let pull = repo.pulls.last().unwrap();
+        assert_eq!(url.as_str(), pull.url);
+        assert_eq!(pull.head, "edit-uk-london-existing_dance");
+        assert_eq!(pull.title, "Update Existing dance in London");
+        assert!(pull.body.starts_with("Edited from web form."));
+        assert_eq!(
+            repo.events("edit-uk-london-existing_dance", london),
+            vec![edited]
+        );
+    }
+
+    // Try to edit an event which isn't in the file.
+    let missing = test_event("Missing dance", "UK", "London", 5);
+    let result = edit_event_in_file(&missing, missing.clone(), london, None, forge).await;
+    assert!(matches!(result, Err(InternalError::NotFound)));
+    assert_eq!(
+        repo.lock()
+            .unwrap()
+            .branch_head("edit-uk-london-missing_dance"),
+        None
+    );
+
+    assert_eq!(repo.lock().unwrap().pulls.len(), 3);
+}
@@ -0,0 +1,606 @@
+//! Proposing changes to the events data repository on a code forge such as GitHub, Gitea or
+//! GitLab.
+
+mod gitea;
+mod github;
+mod gitlab;
+#[cfg(test)]
+mod mock;
+
+use crate::{
+    change::{cancelled, Change},
+    config::Config,
+    diff::event_changes_markdown,
+    errors::InternalError,
+    model::{event::Event, events::Events},
+};
+use axum::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use eyre::eyre;
+use log::{trace, warn};
+use std::collections::HashSet;
+use url::Url;
+
+/// The higher suffix number to add to a branch name.
+const MAX_SUFFIX: u32 = 9;
+
+/// The operations on a repository hosted on a code forge which are needed to propose changes to
+/// events.
+#[async_trait]
+pub trait Forge: Send + Sync {
+    /// Returns the name of the branch to which changes should be proposed.
+    fn main_branch(&self) -> &str;
+
+    /// Creates a new branch with the given name from the head of `from_branch`.
+    ///
+    /// Returns `Ok(false)` if there is already a branch with the name.
+    async fn create_branch(&self, name: &str, from_branch: &str) -> Result<bool, InternalError>;
+
+    /// Returns the contents of the file at the given path on the given branch, or `None` if there is
+    /// no such file.
+    async fn get_file(
+        &self,
+        path: &str,
+        branch: &str,
+    ) -> Result<Option<FileContents>, InternalError>;
+
+    /// Commits a new file with the given contents to the given branch.
+    async fn create_file(
+        &self,
+        path: &str,
+        branch: &str,
+        content: &str,
+        message: &str,
+        author: Option<&CommitAuthor>,
+    ) -> Result<(), InternalError>;
+
+    /// Commits new contents for an existing file to the given branch. `existing` is the file as
+    /// returned by [`Forge::get_file`].
+    async fn update_file(
+        &self,
+        path: &str,
+        branch: &str,
+        existing: &FileContents,
+        content: &str,
+        message: &str,
+        author: Option<&CommitAuthor>,
+    ) -> Result<(), InternalError>;
+
+    /// Opens a pull request (or merge request) to merge the given branch into the main branch, and
+    /// returns its URL.
+    async fn create_pull_request(
+        &self,
+        branch: &str,
+        title: &str,
+        body: &str,
+    ) -> Result<Url, InternalError>;
+}
+
+/// The contents of a file in a forge repository.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FileContents {
+    pub content: String,
+    /// An identifier for this version of the file, which some forges need in order to update it.
+    pub version: String,
+}
+
+/// The author to record for a commit.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CommitAuthor {
+    pub name: String,
+    pub email: String,
+}
+
+/// Connects to whichever forge is configured, if any.
+pub async fn connect(config: &Config) -> Result<Option<Box<dyn Forge>>, InternalError> {
+    Ok(if let Some(github) = &config.github {
+        Some(Box::new(github::GitHub::connect(github).await?))
+    } else if let Some(gitea) = &config.gitea {
+        Some(Box::new(gitea::Gitea::new(gitea)?))
+    } else if let Some(gitlab) = &config.gitlab {
+        Some(Box::new(gitlab::GitLab::new(gitlab)?))
+    } else {
+        None
+    })
+}
+
+/// Creates a branch for the PR to add or edit the given event, and returns its name.
+///
+/// `prefix` is the action being taken, like "add".
+async fn create_branch(
+    forge: &dyn Forge,
+    prefix: &str,
+    event: &Event,
+) -> Result<String, InternalError> {
+    // Create the branch, retrying with different suffixes if it already exists.
+    let pr_branch_base = branch_name_base(prefix, event);
+
+    for suffix in 0..=MAX_SUFFIX {
+        let branch_name = if suffix == 0 {
+            pr_branch_base.clone()
+        } else {
+            format!("{}{}", pr_branch_base, suffix)
+        };
+        trace!("Creating branch \"{}\"", branch_name);
+        if forge
+            .create_branch(&branch_name, forge.main_branch())
+            .await?
+        {
+            return Ok(branch_name);
+        }
+        // The branch already exists, let the loop try a different suffix.
+    }
+
+    warn!(
+        "Failed to create PR branch {} after trying all suffixes",
+        pr_branch_base
+    );
+    Err(InternalError::Internal(eyre!(
+        "Failed to create branch for event PR."
+    )))
+}
+
+/// Returns the name to use for a branch to add or edit the given event, before any suffix is added.
+///
+/// `prefix` is the action being taken, like "add".
+pub fn branch_name_base(prefix: &str, event: &Event) -> String {
+    format!(
+        "{}-{}-{}-{}",
+        prefix,
+        to_safe_filename(&event.country),
+        to_safe_filename(&event.city),
+        to_safe_filename(&event.name),
+    )
+}
+
+/// Returns the commit message to use for adding the given new events.
+///
+/// The events must not be empty.
+pub fn add_events_message(events: &[Event]) -> String {
+    if let [event] = events {
+        format!("Add {} in {}", event.name, event.city)
+    } else {
+        let first = &events[0];
+        format!(
+            "Add {} events for {} in {}",
+            events.len(),
+            first.organisation.as_deref().unwrap_or(&first.name),
+            first.city
+        )
+    }
+}
+
+/// Returns the commit message to use for editing the given event.
+pub fn edit_message(original: &Event) -> String {
+    format!("Update {} in {}", original.name, original.city)
+}
+
+/// Returns the commit message to use for marking the given event as cancelled.
+pub fn cancel_message(original: &Event) -> String {
+    format!("Cancel {} in {}", original.name, original.city)
+}
+
+/// Creates a PR to make the given change to the given file.
+///
+/// Returns the URL of the new PR.
+pub async fn propose_change(
+    change: Change,
+    filename: &str,
+    email: Option<&str>,
+    forge: &dyn Forge,
+) -> Result<Url, InternalError> {
+    match change {
+        Change::Add(events) => {
+            if let [event] = events.as_slice() {
+                add_event_to_file(event.clone(), filename, email, forge).await
+            } else {
+                add_events_to_file(events, filename, email, forge).await
+            }
+        }
+        Change::Edit { original, edited } => {
+            edit_event_in_file(&original, edited, filename, email, forge).await
+        }
+        Change::Cancel { original, reason } => {
+            cancel_event_in_file(&original, reason.as_deref(), filename, email, forge).await
+        }
+    }
+}
+
+/// Creates a PR to add the given event to the given file.
+///
+/// Returns the URL of the new PR.
+pub async fn add_event_to_file(
+    event: Event,
+    filename: &str,
+    email: Option<&str>,
+    forge: &dyn Forge,
+) -> Result<Url, InternalError> {
+    let events = vec![event];
+    let commit_message = add_events_message(&events);
+    add_to_file(
+        events,
+        filename,
+        email,
+        forge,
+        &commit_message,
+        "Added from web form.",
+    )
+    .await
+}
+
+/// Creates a single PR to add all the given events to the given file.
+///
+/// Returns the URL of the new PR.
+pub async fn add_events_to_file(
+    events: Vec<Event>,
+    filename: &str,
+    email: Option<&str>,
+    forge: &dyn Forge,
+) -> Result<Url, InternalError> {
+    if events.is_empty() {
+        return Err(InternalError::BadRequest("No events to add".to_string()));
+    }
+    let commit_message = add_events_message(&events);
+    add_to_file(
+        events,
+        filename,
+        email,
+        forge,
+        &commit_message,
+        "Added from uploaded file.",
+    )
+    .await
+}
+
+/// Creates a PR with a commit adding the given events to the given file, creating it if it doesn't
+/// already exist.
+///
+/// The events must not be empty. Returns the URL of the new PR.
+async fn add_to_file(
+    new_events: Vec<Event>,
+    filename: &str,
+    email: Option<&str>,
+    forge: &dyn Forge,
+    commit_message: &str,
+    body: &str,
+) -> Result<Url, InternalError> {
+    let pr_branch = create_branch(forge, "add", &new_events[0]).await?;
+
+    let author = commit_author(email);
+
+    // Create a commit to add or modify the file.
+    if let Some(existing_file) = forge.get_file(filename, &pr_branch).await? {
+        // File already exists, add to it.
+        trace!("Got existing file, version {}", existing_file.version);
+        let mut events = serde_yaml::from_str::<Events>(&existing_file.content)?;
+
+        // Append events to it and sort.
+        events.events.extend(new_events);
+        events.sort();
+        let new_content = events.to_yaml_string().map_err(InternalError::Internal)?;
+
+        // Update the file
+        forge
+            .update_file(
+                filename,
+                &pr_branch,
+                &existing_file,
+                &new_content,
+                commit_message,
+                author.as_ref(),
+            )
+            .await?;
+    } else {
+        // File doesn't exist, create it.
+        let mut events = Events { events: new_events };
+        events.sort();
+        let content = events.to_yaml_string().map_err(InternalError::Internal)?;
+        forge
+            .create_file(
+                filename,
+                &pr_branch,
+                &content,
+                commit_message,
+                author.as_ref(),
+            )
+            .await?;
+    }
+
+    // Create PR for the branch.
+    let pr_url = forge
+        .create_pull_request(&pr_branch, commit_message, body)
+        .await?;
+    trace!("Made PR {}", pr_url);
+    Ok(pr_url)
+}
+
+/// Creates a PR to replace the given original event with the edited version, in the given file.
+///
+/// Returns the URL of the new PR.
+pub async fn edit_event_in_file(
+    original: &Event,
+    edited: Event,
+    filename: &str,
+    email: Option<&str>,
+    forge: &dyn Forge,
+) -> Result<Url, InternalError> {
+    let commit_message = edit_message(original);
+    let body = format!(
+        "Edited from web form.\n\n{}",
+        event_changes_markdown(original, &edited)
+    );
+    replace_event_in_file(
+        original,
+        edited,
+        filename,
+        email,
+        forge,
+        "edit",
+        &commit_message,
+        &body,
+    )
+    .await
+}
+
+/// Creates a PR to mark the given event as cancelled in the given file, with the reason given by
+/// the user if any.
+///
+/// Returns the URL of the new PR.
+pub async fn cancel_event_in_file(
+    original: &Event,
+    reason: Option<&str>,
+    filename: &str,
+    email: Option<&str>,
+    forge: &dyn Forge,
+) -> Result<Url, InternalError> {
+    let commit_message = cancel_message(original);
+    let body = cancellation_body(original, reason);
+    replace_event_in_file(
+        original,
+        cancelled(original),
+        filename,
+        email,
+        forge,
+        "cancel",
+        &commit_message,
+        &body,
+    )
+    .await
+}
+
+/// Returns the body for a PR to cancel the given event, quoting the reason given if any.
+fn cancellation_body(event: &Event, reason: Option<&str>) -> String {
+    let mut body = format!(
+        "Reported cancelled from web form.\n\n{} on {}.",
+        event.name,
+        event.time.start_date()
+    );
+    if let Some(reason) = reason {
+        body += &format!("\n\nReason given:\n\n> {}", reason.replace('\n', "\n> "));
+    }
+    body
+}
+
+/// Creates a PR to replace the given original event with a new version, in the given file.
+///
+/// `prefix` is the action being taken, for the branch name.
+#[allow(clippy::too_many_arguments)]
+async fn replace_event_in_file(
+    original: &Event,
+    replacement: Event,
+    filename: &str,
+    email: Option<&str>,
+    forge: &dyn Forge,
+    prefix: &str,
+    commit_message: &str,
+    body: &str,
+) -> Result<Url, InternalError> {
+    // Check that the event is there before creating a branch, so nothing is left behind if not.
+    // The new branch starts from the main branch, so the file will be the same version on it.
+    let existing_file = forge
+        .get_file(filename, forge.main_branch())
+        .await?
+        .ok_or(InternalError::NotFound)?;
+    trace!("Got existing file, version {}", existing_file.version);
+    let mut events = serde_yaml::from_str::<Events>(&existing_file.content)?;
+    if !replace_event(&mut events, original, replacement) {
+        return Err(InternalError::NotFound);
+    }
+    let new_content = events.to_yaml_string().map_err(InternalError::Internal)?;
+
+    let pr_branch = create_branch(forge, prefix, original).await?;
+
+    forge
+        .update_file(
+            filename,
+            &pr_branch,
+            &existing_file,
+            &new_content,
+            commit_message,
+            commit_author(email).as_ref(),
+        )
+        .await?;
+
+    let pr_url = forge
+        .create_pull_request(&pr_branch, commit_message, body)
+        .await?;
+    trace!("Made PR {}", pr_url);
+    Ok(pr_url)
+}
+
+/// Replaces the first event in the list which matches the original, ignoring its source, with the
+/// edited version, and sorts the events.
+///
+/// Returns false if the original event wasn't found.
+pub fn replace_event(events: &mut Events, original: &Event, edited: Event) -> bool {
+    let original = Event {
+        source: None,
+        ..original.clone()
+    };
+    let Some(existing) = events.events.iter_mut().find(|event| {
+        Event {
+            source: None,
+            ..(*event).clone()
+        } == original
+    }) else {
+        return false;
+    };
+    *existing = edited;
+    events.sort();
+    true
+}
+
+/// Returns the author to use for commits made on behalf of the user with the given email address,
+/// if they gave one.
+pub fn commit_author(email: Option<&str>) -> Option<CommitAuthor> {
+    email.map(|email| CommitAuthor {
+        name: "Add form user".to_string(),
+        email: email.to_string(),
+    })
+}
+
+/// Decodes base64 file contents as returned by forge APIs, which may be split over several lines.
+fn decode_base64_content(content: &str) -> Result<String, InternalError> {
+    let mut content = content.to_owned();
+    content.retain(|c| !c.is_ascii_whitespace());
+    Ok(String::from_utf8(STANDARD.decode(content)?)?)
+}
+
+fn encode_base64_content(content: &str) -> String {
+    STANDARD.encode(content)
+}
+
+/// Converts the given string to a suitable filename by converting it to lowercase, replacing spaces
+/// with underscores, and removing special characters.
+///
+/// The returned string will only contain ASCII alphanumeric characters, underscores and hyphens.
+pub fn to_safe_filename(s: &str) -> String {
+    let mut filename = s.to_lowercase().replace(' ', "_");
+    filename.retain(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    filename.truncate(30);
+    filename
+}
+
+/// Value returned by [`choose_file_for_event`] when the event is a duplicate of an existing one.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DuplicateEvent {
+    pub existing: Event,
+    pub merged: Event,
+}
+
+/// Checks whether the given event is a duplicate of any event we already know about, or what file
+/// it might belong in.
+#[allow(clippy::result_large_err)]
+pub fn choose_file_for_event(events: &Events, event: &Event) -> Result<String, DuplicateEvent> {
+    let mut organisation_files = HashSet::new();
+    let mut city_files = HashSet::new();
+    for existing_event in &events.events {
+        if let Some(merged) = existing_event.merge(event) {
+            return Err(DuplicateEvent {
+                existing: existing_event.to_owned(),
+                merged,
+            });
+        } else if let Some(source) = &existing_event.source {
+            if event.organisation.is_some() && event.organisation == existing_event.organisation {
+                organisation_files.insert(source.to_owned());
+            }
+            if event.country == existing_event.country && event.city == existing_event.city {
+                city_files.insert(source.to_owned());
+            }
+        }
+    }
+
+    let chosen_file = if !organisation_files.is_empty() {
+        organisation_files.iter().next().unwrap().to_owned()
+    } else if city_files.len() == 1 {
+        city_files.iter().next().unwrap().to_owned()
+    } else {
+        format!(
+            "events/{}/{}.yaml",
+            to_safe_filename(&event.country),
+            to_safe_filename(&event.city),
+        )
+    };
+
+    trace!("Possible files for organisation: {:?}", organisation_files);
+    trace!("Possible files for city: {:?}", city_files);
+    trace!("Chosen file: {}", chosen_file);
+
+    Ok(chosen_file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{dancestyle::DanceStyle, event::EventTime};
+    use chrono::NaiveDate;
+
+    fn test_event() -> Event {
+        Event {
+            name: "Dance".to_string(),
+            details: None,
+            links: vec![],
+            time: EventTime::DateOnly {
+                start_date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+                end_date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            },
+            country: "UK".to_string(),
+            state: None,
+            city: "London".to_string(),
+            styles: vec![DanceStyle::Contra],
+            workshop: false,
+            social: true,
+            bands: vec![],
+            callers: vec![],
+            price: None,
+            organisation: None,
+            cancelled: false,
+            source: None,
+        }
+    }
+
+    #[test]
+    fn replace_edited_event() {
+        let event = test_event();
+        let other = Event {
+            name: "Other".to_string(),
+            ..event.clone()
+        };
+        let loaded = Event {
+            source: Some("events/uk/london.yaml".to_string()),
+            ..event.clone()
+        };
+        let edited = Event {
+            bands: vec!["Band".to_string()],
+            ..event.clone()
+        };
+        let mut events = Events {
+            events: vec![other.clone(), event.clone()],
+        };
+
+        assert!(replace_event(&mut events, &loaded, edited.clone()));
+        assert_eq!(events.events, vec![edited.clone(), other.clone()]);
+        assert!(!replace_event(&mut events, &loaded, edited));
+    }
+
+    #[test]
+    fn cancellation_reason() {
+        assert_eq!(
+            cancellation_body(&test_event(), Some("Venue closed.\nSee website.")),
+            "Reported cancelled from web form.\n\n\
+             Dance on 2024-01-01.\n\n\
+             Reason given:\n\n\
+             > Venue closed.\n\
+             > See website."
+        );
+    }
+
+    #[test]
+    fn safe_filenames() {
+        assert_eq!(to_safe_filename("Southend-on-Sea"), "southend-on-sea");
+        assert_eq!(
+            to_safe_filename("weird'\"@\\/ characters"),
+            "weird_characters"
+        )
+    }
+}
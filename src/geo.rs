@@ -0,0 +1,413 @@
+// Copyright 2024 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use eyre::{eyre, Report};
+use std::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+
+/// The mean radius of the Earth, in kilometres.
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+/// A city name, as (country, state, city).
+type CityName = (&'static str, Option<&'static str>, &'static str);
+
+/// Approximate coordinates of cities with dance events, as (latitude, longitude) in degrees.
+///
+/// Entries with a state only match events in that state; entries without a state match events in
+/// any state of the country.
+pub const CITY_COORDINATES: [(CityName, (f64, f64)); 217] = [
+    (("Australia", Some("ACT"), "Canberra"), (-35.28, 149.13)),
+    (("Australia", Some("NSW"), "Sydney"), (-33.87, 151.21)),
+    (("Australia", Some("QLD"), "Brisbane"), (-27.47, 153.03)),
+    (("Australia", Some("SA"), "Adelaide"), (-34.93, 138.60)),
+    (("Australia", Some("TAS"), "Hobart"), (-42.88, 147.33)),
+    (("Australia", Some("VIC"), "Melbourne"), (-37.81, 144.96)),
+    (("Australia", Some("WA"), "Perth"), (-31.95, 115.86)),
+    (("Austria", None, "Graz"), (47.07, 15.44)),
+    (("Austria", None, "Innsbruck"), (47.27, 11.40)),
+    (("Austria", None, "Linz"), (48.31, 14.29)),
+    (("Austria", None, "Salzburg"), (47.81, 13.04)),
+    (("Austria", None, "Vienna"), (48.21, 16.37)),
+    (("Belgium", None, "Antwerp"), (51.22, 4.40)),
+    (("Belgium", None, "Brugge"), (51.21, 3.22)),
+    (("Belgium", None, "Brussels"), (50.85, 4.35)),
+    (("Belgium", None, "Gent"), (51.05, 3.72)),
+    (("Belgium", None, "Hasselt"), (50.93, 5.34)),
+    (("Belgium", None, "Leuven"), (50.88, 4.70)),
+    (("Belgium", None, "Liège"), (50.63, 5.57)),
+    (("Belgium", None, "Mechelen"), (51.03, 4.48)),
+    (("Belgium", None, "Namur"), (50.47, 4.87)),
+    (("Canada", Some("BC"), "Vancouver"), (49.28, -123.12)),
+    (("Canada", Some("BC"), "Victoria"), (48.43, -123.37)),
+    (("Canada", Some("Ontario"), "Ottawa"), (45.42, -75.70)),
+    (("Canada", Some("Ontario"), "Toronto"), (43.65, -79.38)),
+    (("Canada", Some("QC"), "Montreal"), (45.50, -73.57)),
+    (("Czechia", None, "Brno"), (49.20, 16.61)),
+    (("Czechia", None, "Prague"), (50.08, 14.44)),
+    (("Denmark", None, "Aarhus"), (56.16, 10.20)),
+    (("Denmark", None, "Copenhagen"), (55.68, 12.57)),
+    (("France", None, "Bordeaux"), (44.84, -0.58)),
+    (("France", None, "Grenoble"), (45.19, 5.72)),
+    (("France", None, "Lille"), (50.63, 3.06)),
+    (("France", None, "Lyon"), (45.76, 4.84)),
+    (("France", None, "Marseille"), (43.30, 5.37)),
+    (("France", None, "Montpellier"), (43.61, 3.88)),
+    (("France", None, "Nantes"), (47.22, -1.55)),
+    (("France", None, "Paris"), (48.86, 2.35)),
+    (("France", None, "Rennes"), (48.11, -1.68)),
+    (("France", None, "Strasbourg"), (48.57, 7.75)),
+    (("France", None, "Toulouse"), (43.60, 1.44)),
+    (("Germany", None, "Aachen"), (50.78, 6.08)),
+    (("Germany", None, "Berlin"), (52.52, 13.40)),
+    (("Germany", None, "Bonn"), (50.74, 7.10)),
+    (("Germany", None, "Bremen"), (53.08, 8.80)),
+    (("Germany", None, "Darmstadt"), (49.87, 8.65)),
+    (("Germany", None, "Dresden"), (51.05, 13.74)),
+    (("Germany", None, "Düsseldorf"), (51.23, 6.78)),
+    (("Germany", None, "Frankfurt am Main"), (50.11, 8.68)),
+    (("Germany", None, "Freiburg"), (47.99, 7.84)),
+    (("Germany", None, "Göttingen"), (51.54, 9.93)),
+    (("Germany", None, "Hamburg"), (53.55, 9.99)),
+    (("Germany", None, "Hannover"), (52.37, 9.74)),
+    (("Germany", None, "Heidelberg"), (49.40, 8.67)),
+    (("Germany", None, "Karlsruhe"), (49.01, 8.40)),
+    (("Germany", None, "Kassel"), (51.31, 9.48)),
+    (("Germany", None, "Kleve"), (51.79, 6.14)),
+    (("Germany", None, "Köln"), (50.94, 6.96)),
+    (("Germany", None, "Leipzig"), (51.34, 12.37)),
+    (("Germany", None, "Mainz"), (50.00, 8.27)),
+    (("Germany", None, "Marburg"), (50.81, 8.77)),
+    (("Germany", None, "München"), (48.14, 11.58)),
+    (("Germany", None, "Münster"), (51.96, 7.63)),
+    (("Germany", None, "Nürnberg"), (49.45, 11.08)),
+    (("Germany", None, "Stuttgart"), (48.78, 9.18)),
+    (("Ireland", None, "Cork"), (51.90, -8.47)),
+    (("Ireland", None, "Dublin"), (53.35, -6.26)),
+    (("Ireland", None, "Galway"), (53.27, -9.05)),
+    (("Ireland", None, "Limerick"), (52.66, -8.63)),
+    (("Italy", None, "Bologna"), (44.49, 11.34)),
+    (("Italy", None, "Florence"), (43.77, 11.26)),
+    (("Italy", None, "Milan"), (45.46, 9.19)),
+    (("Italy", None, "Rome"), (41.90, 12.50)),
+    (("Italy", None, "Turin"), (45.07, 7.69)),
+    (("Italy", None, "Verona"), (45.44, 10.99)),
+    (("Netherlands", None, "'s-Hertogenbosch"), (51.70, 5.30)),
+    (("Netherlands", None, "Amersfoort"), (52.16, 5.39)),
+    (("Netherlands", None, "Amsterdam"), (52.37, 4.90)),
+    (("Netherlands", None, "Arnhem"), (51.98, 5.91)),
+    (("Netherlands", None, "Delft"), (52.01, 4.36)),
+    (("Netherlands", None, "Den Haag"), (52.08, 4.30)),
+    (("Netherlands", None, "Deventer"), (52.25, 6.16)),
+    (("Netherlands", None, "Eindhoven"), (51.44, 5.48)),
+    (("Netherlands", None, "Enschede"), (52.22, 6.89)),
+    (("Netherlands", None, "Groningen"), (53.22, 6.57)),
+    (("Netherlands", None, "Haarlem"), (52.38, 4.64)),
+    (("Netherlands", None, "Leiden"), (52.16, 4.49)),
+    (("Netherlands", None, "Nijmegen"), (51.84, 5.86)),
+    (("Netherlands", None, "Rotterdam"), (51.92, 4.48)),
+    (("Netherlands", None, "Tilburg"), (51.56, 5.09)),
+    (("Netherlands", None, "Utrecht"), (52.09, 5.12)),
+    (("Netherlands", None, "Wageningen"), (51.97, 5.67)),
+    (("Netherlands", None, "Zeist"), (52.09, 5.23)),
+    (("Netherlands", None, "Zwolle"), (52.51, 6.09)),
+    (("New Zealand", None, "Auckland"), (-36.85, 174.76)),
+    (("New Zealand", None, "Christchurch"), (-43.53, 172.64)),
+    (("New Zealand", None, "Wellington"), (-41.29, 174.78)),
+    (("Norway", None, "Bergen"), (60.39, 5.32)),
+    (("Norway", None, "Oslo"), (59.91, 10.75)),
+    (("Norway", None, "Trondheim"), (63.43, 10.40)),
+    (("Poland", None, "Kraków"), (50.06, 19.94)),
+    (("Poland", None, "Warsaw"), (52.23, 21.01)),
+    (("Poland", None, "Wrocław"), (51.11, 17.04)),
+    (("Portugal", None, "Lisbon"), (38.72, -9.14)),
+    (("Portugal", None, "Porto"), (41.15, -8.61)),
+    (("Slovenia", None, "Ljubljana"), (46.06, 14.51)),
+    (("Spain", None, "Barcelona"), (41.39, 2.17)),
+    (("Spain", None, "Madrid"), (40.42, -3.70)),
+    (("Spain", None, "Valencia"), (39.47, -0.38)),
+    (("Sweden", None, "Gothenburg"), (57.71, 11.97)),
+    (("Sweden", None, "Malmö"), (55.60, 13.00)),
+    (("Sweden", None, "Stockholm"), (59.33, 18.07)),
+    (("Sweden", None, "Uppsala"), (59.86, 17.64)),
+    (("Switzerland", None, "Basel"), (47.56, 7.59)),
+    (("Switzerland", None, "Bern"), (46.95, 7.45)),
+    (("Switzerland", None, "Geneva"), (46.20, 6.14)),
+    (("Switzerland", None, "Lausanne"), (46.52, 6.63)),
+    (("Switzerland", None, "Zürich"), (47.38, 8.54)),
+    (("UK", None, "Aberdeen"), (57.15, -2.09)),
+    (("UK", None, "Bath"), (51.38, -2.36)),
+    (("UK", None, "Belfast"), (54.60, -5.93)),
+    (("UK", None, "Birmingham"), (52.49, -1.89)),
+    (("UK", None, "Brighton"), (50.82, -0.14)),
+    (("UK", None, "Bristol"), (51.45, -2.59)),
+    (("UK", None, "Cambridge"), (52.21, 0.12)),
+    (("UK", None, "Canterbury"), (51.28, 1.08)),
+    (("UK", None, "Cardiff"), (51.48, -3.18)),
+    (("UK", None, "Chester"), (53.19, -2.89)),
+    (("UK", None, "Coventry"), (52.41, -1.51)),
+    (("UK", None, "Derby"), (52.92, -1.48)),
+    (("UK", None, "Dundee"), (56.46, -2.97)),
+    (("UK", None, "Durham"), (54.78, -1.57)),
+    (("UK", None, "Edinburgh"), (55.95, -3.19)),
+    (("UK", None, "Exeter"), (50.72, -3.53)),
+    (("UK", None, "Glasgow"), (55.86, -4.25)),
+    (("UK", None, "Gloucester"), (51.86, -2.24)),
+    (("UK", None, "Guildford"), (51.24, -0.57)),
+    (("UK", None, "Lancaster"), (54.05, -2.80)),
+    (("UK", None, "Leeds"), (53.80, -1.55)),
+    (("UK", None, "Leicester"), (52.64, -1.13)),
+    (("UK", None, "Lewes"), (50.87, 0.01)),
+    (("UK", None, "Liverpool"), (53.41, -2.98)),
+    (("UK", None, "London"), (51.51, -0.13)),
+    (("UK", None, "Manchester"), (53.48, -2.24)),
+    (("UK", None, "Milton Keynes"), (52.04, -0.76)),
+    (("UK", None, "Newcastle upon Tyne"), (54.98, -1.62)),
+    (("UK", None, "Norwich"), (52.63, 1.30)),
+    (("UK", None, "Nottingham"), (52.95, -1.15)),
+    (("UK", None, "Oxford"), (51.75, -1.26)),
+    (("UK", None, "Plymouth"), (50.38, -4.14)),
+    (("UK", None, "Reading"), (51.45, -0.98)),
+    (("UK", None, "Sheffield"), (53.38, -1.47)),
+    (("UK", None, "Sidmouth"), (50.68, -3.24)),
+    (("UK", None, "Southampton"), (50.91, -1.40)),
+    (("UK", None, "St Albans"), (51.75, -0.34)),
+    (("UK", None, "Winchester"), (51.06, -1.31)),
+    (("UK", None, "Worcester"), (52.19, -2.22)),
+    (("UK", None, "York"), (53.96, -1.08)),
+    (("USA", Some("AZ"), "Phoenix"), (33.45, -112.07)),
+    (("USA", Some("AZ"), "Tucson"), (32.22, -110.97)),
+    (("USA", Some("CA"), "Berkeley"), (37.87, -122.27)),
+    (("USA", Some("CA"), "Hayward"), (37.67, -122.08)),
+    (("USA", Some("CA"), "Los Angeles"), (34.05, -118.24)),
+    (("USA", Some("CA"), "Oakland"), (37.80, -122.27)),
+    (("USA", Some("CA"), "Palo Alto"), (37.44, -122.14)),
+    (("USA", Some("CA"), "Sacramento"), (38.58, -121.49)),
+    (("USA", Some("CA"), "San Diego"), (32.72, -117.16)),
+    (("USA", Some("CA"), "San Francisco"), (37.77, -122.42)),
+    (("USA", Some("CO"), "Boulder"), (40.01, -105.27)),
+    (("USA", Some("CO"), "Denver"), (39.74, -104.99)),
+    (("USA", Some("CT"), "Hartford"), (41.76, -72.68)),
+    (("USA", Some("CT"), "New Haven"), (41.31, -72.92)),
+    (("USA", Some("DC"), "Washington"), (38.91, -77.04)),
+    (("USA", Some("FL"), "Gainesville"), (29.65, -82.32)),
+    (("USA", Some("GA"), "Atlanta"), (33.75, -84.39)),
+    (("USA", Some("IL"), "Chicago"), (41.88, -87.63)),
+    (("USA", Some("MA"), "Amherst"), (42.37, -72.52)),
+    (("USA", Some("MA"), "Boston"), (42.36, -71.06)),
+    (("USA", Some("MA"), "Cambridge"), (42.37, -71.11)),
+    (("USA", Some("MA"), "Concord"), (42.46, -71.35)),
+    (("USA", Some("MA"), "Greenfield"), (42.59, -72.60)),
+    (("USA", Some("MA"), "Northampton"), (42.32, -72.63)),
+    (("USA", Some("MA"), "Worcester"), (42.26, -71.80)),
+    (("USA", Some("MD"), "Baltimore"), (39.29, -76.61)),
+    (("USA", Some("MD"), "Glen Echo"), (38.97, -77.14)),
+    (("USA", Some("MD"), "Takoma Park"), (38.98, -77.01)),
+    (("USA", Some("ME"), "Portland"), (43.66, -70.26)),
+    (("USA", Some("MI"), "Ann Arbor"), (42.28, -83.74)),
+    (("USA", Some("MN"), "Minneapolis"), (44.98, -93.27)),
+    (("USA", Some("MN"), "Saint Paul"), (44.95, -93.09)),
+    (("USA", Some("NC"), "Asheville"), (35.60, -82.55)),
+    (("USA", Some("NC"), "Durham"), (35.99, -78.90)),
+    (("USA", Some("NH"), "Nelson"), (42.99, -72.13)),
+    (("USA", Some("NM"), "Albuquerque"), (35.08, -106.65)),
+    (("USA", Some("NM"), "Santa Fe"), (35.69, -105.94)),
+    (("USA", Some("NY"), "Brooklyn"), (40.68, -73.94)),
+    (("USA", Some("NY"), "New York"), (40.71, -74.01)),
+    (("USA", Some("OH"), "Cincinnati"), (39.10, -84.51)),
+    (("USA", Some("OH"), "Columbus"), (39.96, -83.00)),
+    (("USA", Some("OR"), "Ashland"), (42.19, -122.71)),
+    (("USA", Some("OR"), "Eugene"), (44.05, -123.09)),
+    (("USA", Some("OR"), "Portland"), (45.52, -122.68)),
+    (("USA", Some("PA"), "Lancaster"), (40.04, -76.31)),
+    (("USA", Some("PA"), "Philadelphia"), (39.95, -75.17)),
+    (("USA", Some("PA"), "Pittsburgh"), (40.44, -80.00)),
+    (("USA", Some("RI"), "Providence"), (41.82, -71.41)),
+    (("USA", Some("TN"), "Knoxville"), (35.96, -83.92)),
+    (("USA", Some("TN"), "Nashville"), (36.16, -86.78)),
+    (("USA", Some("TX"), "Austin"), (30.27, -97.74)),
+    (("USA", Some("TX"), "Houston"), (29.76, -95.37)),
+    (("USA", Some("UT"), "Salt Lake City"), (40.76, -111.89)),
+    (("USA", Some("VA"), "Charlottesville"), (38.03, -78.48)),
+    (("USA", Some("VA"), "Richmond"), (37.54, -77.44)),
+    (("USA", Some("VT"), "Brattleboro"), (42.85, -72.56)),
+    (("USA", Some("VT"), "Burlington"), (44.48, -73.21)),
+    (("USA", Some("WA"), "Seattle"), (47.61, -122.33)),
+    (("USA", Some("WI"), "Madison"), (43.07, -89.40)),
+];
+
+/// A point on the Earth's surface.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Coordinates {
+    /// The latitude in degrees north.
+    pub latitude: f64,
+    /// The longitude in degrees east.
+    pub longitude: f64,
+}
+
+impl Coordinates {
+    /// Returns the great-circle distance between the two points, in kilometres.
+    pub fn distance_km(self, other: Coordinates) -> f64 {
+        let latitude_a = self.latitude.to_radians();
+        let latitude_b = other.latitude.to_radians();
+        let delta_latitude = latitude_b - latitude_a;
+        let delta_longitude = (other.longitude - self.longitude).to_radians();
+
+        let a = (delta_latitude / 2.0).sin().powi(2)
+            + latitude_a.cos() * latitude_b.cos() * (delta_longitude / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+    }
+}
+
+impl Display for Coordinates {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{},{}", self.latitude, self.longitude)
+    }
+}
+
+impl FromStr for Coordinates {
+    type Err = Report;
+
+    /// Parses coordinates in the form "latitude,longitude".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (latitude, longitude) = s
+            .split_once(',')
+            .ok_or_else(|| eyre!("Coordinates '{}' must be of the form 'lat,lon'", s))?;
+        let latitude: f64 = latitude.trim().parse()?;
+        let longitude: f64 = longitude.trim().parse()?;
+        if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+            return Err(eyre!("Coordinates '{}' out of range", s));
+        }
+        Ok(Self {
+            latitude,
+            longitude,
+        })
+    }
+}
+
+/// Looks up the approximate coordinates of the given city.
+pub fn city_coordinates(country: &str, state: Option<&str>, city: &str) -> Option<Coordinates> {
+    CITY_COORDINATES
+        .iter()
+        .find(|((entry_country, entry_state, entry_city), _)| {
+            *entry_country == country
+                && *entry_city == city
+                && (entry_state.is_none() || *entry_state == state)
+        })
+        .map(|(_, (latitude, longitude))| Coordinates {
+            latitude: *latitude,
+            longitude: *longitude,
+        })
+}
+
+/// Looks up the approximate coordinates of a city given by name, optionally followed by the state
+/// and country or just the country, separated by commas, like "Bristol", "Portland, USA" or
+/// "Portland, OR, USA".
+///
+/// Returns an error if the city isn't known, or if there are several cities matching the name.
+pub fn named_city_coordinates(name: &str) -> Result<Coordinates, Report> {
+    let parts = name.split(',').map(str::trim).collect::<Vec<_>>();
+    let (city, state, country) = match parts.as_slice() {
+        [city] => (*city, None, None),
+        [city, country] => (*city, None, Some(*country)),
+        [city, state, country] => (*city, Some(*state), Some(*country)),
+        _ => return Err(eyre!("Invalid city name '{}'", name)),
+    };
+    let mut matching =
+        CITY_COORDINATES
+            .iter()
+            .filter(|((entry_country, entry_state, entry_city), _)| {
+                entry_city.eq_ignore_ascii_case(city)
+                    && matches_if_given(state, entry_state.unwrap_or_default())
+                    && matches_if_given(country, entry_country)
+            });
+    let (_, (latitude, longitude)) = matching
+        .next()
+        .ok_or_else(|| eyre!("Unknown city '{}'", name))?;
+    if matching.next().is_some() {
+        return Err(eyre!(
+            "Ambiguous city '{}', please include the state and country",
+            name
+        ));
+    }
+    Ok(Coordinates {
+        latitude: *latitude,
+        longitude: *longitude,
+    })
+}
+
+/// Returns whether the given name is the same as the value ignoring case, or no name is given.
+fn matches_if_given(name: Option<&str>, value: &str) -> bool {
+    name.is_none_or(|name| name.eq_ignore_ascii_case(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn london_to_oxford() {
+        let london = city_coordinates("UK", None, "London").unwrap();
+        let oxford = city_coordinates("UK", None, "Oxford").unwrap();
+        let distance = london.distance_km(oxford);
+        assert!((80.0..90.0).contains(&distance), "{}", distance);
+    }
+
+    #[test]
+    fn city_state() {
+        assert_eq!(
+            city_coordinates("USA", Some("ME"), "Portland"),
+            Some(Coordinates {
+                latitude: 43.66,
+                longitude: -70.26
+            })
+        );
+        assert_eq!(
+            named_city_coordinates("portland, me, usa").ok(),
+            city_coordinates("USA", Some("ME"), "Portland"),
+        );
+        assert_eq!(city_coordinates("USA", Some("CA"), "Portland"), None);
+    }
+
+    #[test]
+    fn named_city() {
+        assert_eq!(
+            named_city_coordinates("London").ok(),
+            city_coordinates("UK", None, "London")
+        );
+        assert_eq!(
+            named_city_coordinates("Cambridge, UK").ok(),
+            city_coordinates("UK", None, "Cambridge")
+        );
+        assert!(named_city_coordinates("Londn").is_err());
+        assert!(named_city_coordinates("Portland, CA, USA").is_err());
+        // These names match several cities.
+        assert!(named_city_coordinates("Cambridge").is_err());
+        assert!(named_city_coordinates("Portland, USA").is_err());
+    }
+
+    #[test]
+    fn parse_coordinates() {
+        assert_eq!(
+            "51.5,-0.1".parse::<Coordinates>().unwrap(),
+            Coordinates {
+                latitude: 51.5,
+                longitude: -0.1
+            }
+        );
+        assert!("51.5".parse::<Coordinates>().is_err());
+        assert!("91,0".parse::<Coordinates>().is_err());
+    }
+}
@@ -0,0 +1,238 @@
+// Copyright 2024 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! GeoJSON export of events, for showing on maps.
+
+use crate::{
+    geo::{city_coordinates, Coordinates},
+    model::{event::Event, events::Events, filters::Filters},
+};
+use axum::{
+    body::Body,
+    http::{header, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use eyre::Report;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// Options for how to group events into features.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+pub struct GeoJsonOptions {
+    #[serde(default)]
+    pub cluster: Cluster,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Cluster {
+    /// One feature per event.
+    #[default]
+    Event,
+    /// One feature per city, with the number of events there.
+    City,
+}
+
+/// Makes a GeoJSON feature collection with a point for each of the given events.
+///
+/// Events in cities whose coordinates aren't known are left out.
+pub fn events_to_geojson(events: &[&Event]) -> Value {
+    let features = events
+        .iter()
+        .filter_map(|event| {
+            let coordinates =
+                city_coordinates(&event.country, event.state.as_deref(), &event.city)?;
+            let (start_date, end_date) = event.local_date_range();
+            Some(feature(
+                coordinates,
+                json!({
+                    "uid": event.uid(),
+                    "name": event.name,
+                    "start_date": start_date,
+                    "end_date": end_date,
+                    "country": event.country,
+                    "state": event.state,
+                    "city": event.city,
+                    "styles": event.styles.iter().map(|style| style.tag()).collect::<Vec<_>>(),
+                    "cancelled": event.cancelled,
+                    "link": event.main_link(),
+                }),
+            ))
+        })
+        .collect::<Vec<_>>();
+    feature_collection(features)
+}
+
+/// Makes a GeoJSON feature collection with a point for each city which has events matching the
+/// given filters.
+///
+/// Each feature has the number of events in the city, and a link to the list of them with the
+/// given `link_filters` narrowed down to the city. Any of the site's `defaults` which the link
+/// filters don't use are cleared explicitly in the link.
+pub fn cities_to_geojson(
+    events: &Events,
+    filters: &Filters,
+    link_filters: &Filters,
+    defaults: &Filters,
+) -> Result<Value, Report> {
+    let mut counts = HashMap::<(&str, Option<&str>, &str), usize>::new();
+    for event in events.matching(filters) {
+        *counts
+            .entry((&event.country, event.state.as_deref(), &event.city))
+            .or_default() += 1;
+    }
+
+    let mut features = vec![];
+    for country in events.countries(filters) {
+        let country_filters = link_filters.with_country(Some(&country.name));
+        let cities = country
+            .states
+            .iter()
+            .flat_map(|state| {
+                state
+                    .cities
+                    .iter()
+                    .map(|city| (Some(state.name.as_str()), city))
+            })
+            .chain(country.cities.iter().map(|city| (None, city)));
+        for (state, city) in cities {
+            let Some(coordinates) = city_coordinates(&country.name, state, city) else {
+                continue;
+            };
+            let city_filters = country_filters.with_state(state).with_city(Some(city));
+            features.push(feature(
+                coordinates,
+                json!({
+                    "country": country.name,
+                    "state": state,
+                    "city": city,
+                    "count": counts
+                        .get(&(country.name.as_str(), state, city.as_str()))
+                        .copied()
+                        .unwrap_or_default(),
+                    "link": format!(
+                        "/?{}",
+                        city_filters.to_query_string_with_defaults(defaults)?
+                    ),
+                }),
+            ));
+        }
+    }
+    Ok(feature_collection(features))
+}
+
+fn feature(coordinates: Coordinates, properties: Value) -> Value {
+    json!({
+        "type": "Feature",
+        "geometry": {
+            "type": "Point",
+            // GeoJSON positions are longitude first.
+            "coordinates": [coordinates.longitude, coordinates.latitude],
+        },
+        "properties": properties,
+    })
+}
+
+fn feature_collection(features: Vec<Value>) -> Value {
+    json!({
+        "type": "FeatureCollection",
+        "features": features,
+    })
+}
+
+#[derive(Debug)]
+pub struct GeoJson(pub String);
+
+impl IntoResponse for GeoJson {
+    fn into_response(self) -> Response {
+        let mut res = Response::new(Body::from(self.0));
+        res.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/geo+json"),
+        );
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{dancestyle::DanceStyle, event::EventTime};
+    use chrono::NaiveDate;
+
+    fn test_event(name: &str, city: &str) -> Event {
+        Event {
+            name: name.to_string(),
+            details: None,
+            links: vec![],
+            time: EventTime::DateOnly {
+                start_date: NaiveDate::from_ymd_opt(2100, 1, 1).unwrap(),
+                end_date: NaiveDate::from_ymd_opt(2100, 1, 2).unwrap(),
+            },
+            country: "UK".to_string(),
+            state: None,
+            city: city.to_string(),
+            styles: vec![DanceStyle::Contra],
+            workshop: false,
+            social: true,
+            bands: vec![],
+            callers: vec![],
+            price: None,
+            organisation: None,
+            cancelled: false,
+            source: None,
+        }
+    }
+
+    #[test]
+    fn event_features() {
+        let event = test_event("Dance", "London");
+        let unknown = test_event("Other", "Nowhere");
+        let geojson = events_to_geojson(&[&event, &unknown]);
+        let features = geojson["features"].as_array().unwrap();
+        assert_eq!(features.len(), 1);
+        assert_eq!(features[0]["geometry"]["coordinates"][1], 51.51);
+        assert_eq!(features[0]["properties"]["name"], "Dance");
+        assert_eq!(features[0]["properties"]["end_date"], "2100-01-02");
+        assert_eq!(features[0]["properties"]["styles"], json!(["contra"]));
+    }
+
+    #[test]
+    fn city_features() {
+        let events = Events {
+            events: vec![
+                test_event("A", "London"),
+                test_event("B", "London"),
+                test_event("C", "Oxford"),
+            ],
+        };
+        let geojson = cities_to_geojson(
+            &events,
+            &Filters::default(),
+            &Filters::default(),
+            &Filters::default(),
+        )
+        .unwrap();
+        let features = geojson["features"].as_array().unwrap();
+        assert_eq!(features.len(), 2);
+        assert_eq!(features[0]["properties"]["city"], "London");
+        assert_eq!(features[0]["properties"]["count"], 2);
+        assert_eq!(
+            features[0]["properties"]["link"],
+            "/?country=UK&city=London"
+        );
+        assert_eq!(features[1]["properties"]["count"], 1);
+    }
+}
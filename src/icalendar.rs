@@ -1,23 +1,71 @@
-use crate::model::event::{Event, EventTime};
+use crate::{
+    geo::city_coordinates,
+    model::{
+        event::{Event, EventTime},
+        history::EventHistory,
+    },
+    util::default_timezone,
+};
 use axum::{
     body::Body,
     http::{header, HeaderValue},
     response::{IntoResponse, Response},
 };
-use chrono::Utc;
-use icalendar::{Calendar, Component, EventLike, EventStatus};
-use std::fmt::Write;
+use chrono::{DateTime, Datelike, FixedOffset, Offset, TimeDelta, TimeZone, Utc};
+use chrono_tz::{OffsetComponents, OffsetName, Tz, TzOffset};
+use icalendar::{Calendar, CalendarDateTime, Component, EventLike, EventStatus};
+use std::{collections::BTreeMap, fmt::Write};
+
+/// Converts the given events to an iCalendar string, with VTIMEZONE components for any timezones
+/// used.
+pub fn events_to_calendar(
+    events: &[&Event],
+    name: &str,
+    history: &EventHistory,
+    now: DateTime<Utc>,
+) -> String {
+    // The range of years for which each timezone is used.
+    let mut timezones: BTreeMap<&str, (Tz, i32, i32)> = BTreeMap::new();
+    for event in events {
+        if let (Some(timezone), EventTime::DateTime { start, end }) =
+            (event_timezone(event), &event.time)
+        {
+            timezones
+                .entry(timezone.name())
+                .and_modify(|(_, first_year, last_year)| {
+                    *first_year = (*first_year).min(start.year());
+                    *last_year = (*last_year).max(end.year());
+                })
+                .or_insert((timezone, start.year(), end.year()));
+        }
+    }
 
-pub fn events_to_calendar(events: &[&Event], name: &str) -> Calendar {
-    events
+    let calendar = events
         .iter()
-        .map(|event| event_to_event(event))
+        .map(|event| event_to_event(event, history, now))
         .collect::<Calendar>()
         .name(name)
         .done()
+        .to_string();
+
+    let vtimezones = timezones
+        .values()
+        .map(|(timezone, first_year, last_year)| vtimezone(*timezone, *first_year, *last_year))
+        .collect::<String>();
+    // Timezones must come after the calendar properties, so put them before the first event.
+    match calendar.find("BEGIN:VEVENT") {
+        Some(position) => {
+            let mut calendar = calendar;
+            calendar.insert_str(position, &vtimezones);
+            calendar
+        }
+        None => calendar,
+    }
 }
 
-fn event_to_event(event: &Event) -> icalendar::Event {
+/// Builds a plain text description of the event, including all the details which aren't in the
+/// title, time or location.
+pub fn event_description(event: &Event) -> String {
     let mut description = String::new();
     if let Some(details) = &event.details {
         writeln!(description, "{}", details).unwrap();
@@ -56,25 +104,58 @@ fn event_to_event(event: &Event) -> icalendar::Event {
     for link in &event.links {
         writeln!(description, "{}", link).unwrap();
     }
+    description
+}
 
-    let categories = event
-        .styles
-        .iter()
-        .map(ToString::to_string)
-        .collect::<Vec<_>>()
-        .join(",");
+/// Formats the location of the event as the city, state if any and country.
+pub fn event_location(event: &Event) -> String {
+    if let Some(state) = &event.state {
+        format!("{}, {}, {}", event.city, state, event.country)
+    } else {
+        format!("{}, {}", event.city, event.country)
+    }
+}
+
+fn event_to_event(event: &Event, history: &EventHistory, now: DateTime<Utc>) -> icalendar::Event {
+    let description = event_description(event);
+    let location = event_location(event);
 
     let mut calendar_event = icalendar::Event::new();
     calendar_event
+        .uid(&format!("{}@dancelist", event.uid()))
+        .timestamp(history.last_modified(event).unwrap_or(now))
+        .sequence(history.sequence(event))
         .summary(&event.name)
-        .location(&format!("{}, {}", event.city, event.country))
+        .location(&location)
         .description(&description)
         .status(if event.cancelled {
             EventStatus::Cancelled
         } else {
             EventStatus::Confirmed
-        })
-        .add_property("CATEGORIES", &categories);
+        });
+    if let Some(first_seen) = history.first_seen(event) {
+        calendar_event.created(first_seen);
+    }
+    if let Some(last_modified) = history.last_modified(event) {
+        // The `last_modified` method of the icalendar crate uses the wrong property name.
+        calendar_event.add_property(
+            "LAST-MODIFIED",
+            last_modified.format("%Y%m%dT%H%M%SZ").to_string(),
+        );
+    }
+    if let Some(link) = event.main_link() {
+        calendar_event.url(link);
+    }
+    if let Some(coordinates) = city_coordinates(&event.country, event.state.as_deref(), &event.city)
+    {
+        calendar_event.add_property(
+            "GEO",
+            format!("{};{}", coordinates.latitude, coordinates.longitude),
+        );
+    }
+    for style in &event.styles {
+        calendar_event.add_multi_property("CATEGORIES", &style.to_string());
+    }
     match event.time {
         EventTime::DateOnly {
             start_date,
@@ -86,23 +167,125 @@ fn event_to_event(event: &Event) -> icalendar::Event {
                 .ends(end_date.succ_opt().unwrap());
         }
         EventTime::DateTime { start, end } => {
+            let timezone = event_timezone(event);
             calendar_event
-                .starts(start.with_timezone(&Utc))
-                .ends(end.with_timezone(&Utc));
+                .starts(calendar_date_time(start, timezone))
+                .ends(calendar_date_time(end, timezone));
         }
     }
-    for link in &event.links {
-        calendar_event.add_multi_property("ATTACH", link);
-    }
     calendar_event
 }
 
+/// Returns the timezone to use for the event's start and end times, if it is known and matches
+/// their offsets.
+pub fn event_timezone(event: &Event) -> Option<Tz> {
+    let EventTime::DateTime { start, end } = event.time else {
+        return None;
+    };
+    let timezone = default_timezone(&event.country, event.state.as_deref())?;
+    if timezone_matches(timezone, start) && timezone_matches(timezone, end) {
+        Some(timezone)
+    } else {
+        None
+    }
+}
+
+/// Returns whether the given timezone has the same offset as the given time, at that time.
+fn timezone_matches(timezone: Tz, time: DateTime<FixedOffset>) -> bool {
+    timezone.offset_from_utc_datetime(&time.naive_utc()).fix() == *time.offset()
+}
+
+fn calendar_date_time(time: DateTime<FixedOffset>, timezone: Option<Tz>) -> CalendarDateTime {
+    if let Some(timezone) = timezone {
+        CalendarDateTime::WithTimezone {
+            date_time: time.naive_local(),
+            tzid: timezone.name().to_owned(),
+        }
+    } else {
+        CalendarDateTime::Utc(time.with_timezone(&Utc))
+    }
+}
+
+/// Builds a VTIMEZONE component for the given timezone, with an observance for each offset change
+/// between the start of `first_year` and the end of `last_year`.
+fn vtimezone(timezone: Tz, first_year: i32, last_year: i32) -> String {
+    let start = Utc.with_ymd_and_hms(first_year, 1, 1, 0, 0, 0).unwrap();
+    let end = Utc.with_ymd_and_hms(last_year + 1, 1, 1, 0, 0, 0).unwrap();
+    let offset_at = |time: DateTime<Utc>| timezone.offset_from_utc_datetime(&time.naive_utc());
+
+    let mut vtimezone = String::new();
+    write!(vtimezone, "BEGIN:VTIMEZONE\r\nTZID:{}\r\n", timezone.name()).unwrap();
+    let mut offset = offset_at(start);
+    write_observance(&mut vtimezone, start, &offset, &offset);
+
+    let mut day = start;
+    while day < end {
+        let next_day = day + TimeDelta::days(1);
+        if offset_at(next_day).fix() != offset.fix() {
+            // Find the first minute of the day with the new offset.
+            let (mut low, mut high) = (0, 24 * 60);
+            while high - low > 1 {
+                let middle = (low + high) / 2;
+                if offset_at(day + TimeDelta::minutes(middle)).fix() == offset.fix() {
+                    low = middle;
+                } else {
+                    high = middle;
+                }
+            }
+            let transition = day + TimeDelta::minutes(high);
+            let new_offset = offset_at(transition);
+            write_observance(&mut vtimezone, transition, &offset, &new_offset);
+            offset = new_offset;
+        }
+        day = next_day;
+    }
+
+    vtimezone += "END:VTIMEZONE\r\n";
+    vtimezone
+}
+
+/// Writes a STANDARD or DAYLIGHT observance for a change from one offset to another at the given
+/// time.
+fn write_observance(out: &mut String, time: DateTime<Utc>, from: &TzOffset, to: &TzOffset) {
+    let kind = if to.dst_offset().is_zero() {
+        "STANDARD"
+    } else {
+        "DAYLIGHT"
+    };
+    write!(out, "BEGIN:{}\r\n", kind).unwrap();
+    // DTSTART is given in the local time before the change.
+    write!(
+        out,
+        "DTSTART:{}\r\n",
+        time.with_timezone(&from.fix()).format("%Y%m%dT%H%M%S")
+    )
+    .unwrap();
+    write!(out, "TZOFFSETFROM:{}\r\n", format_offset(from.fix())).unwrap();
+    write!(out, "TZOFFSETTO:{}\r\n", format_offset(to.fix())).unwrap();
+    if let Some(abbreviation) = to.abbreviation() {
+        write!(out, "TZNAME:{}\r\n", abbreviation).unwrap();
+    }
+    write!(out, "END:{}\r\n", kind).unwrap();
+}
+
+/// Formats a UTC offset in the iCalendar format, like `+0100`.
+fn format_offset(offset: FixedOffset) -> String {
+    let seconds = offset.local_minus_utc();
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let seconds = seconds.abs();
+    let mut formatted = format!("{}{:02}{:02}", sign, seconds / 3600, seconds / 60 % 60);
+    if seconds % 60 != 0 {
+        write!(formatted, "{:02}", seconds % 60).unwrap();
+    }
+    formatted
+}
+
 #[derive(Debug)]
-pub struct Ics(pub Calendar);
+pub struct Ics(pub String);
 
 impl IntoResponse for Ics {
     fn into_response(self) -> Response {
-        let mut res = Response::new(Body::from(self.0.to_string()));
+        let mut res = Response::new(Body::from(self.0));
         res.headers_mut().insert(
             header::CONTENT_TYPE,
             HeaderValue::from_static("text/calendar"),
@@ -110,3 +293,115 @@ impl IntoResponse for Ics {
         res
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{dancestyle::DanceStyle, events::Events};
+
+    #[test]
+    fn london_vtimezone() {
+        assert_eq!(
+            vtimezone(Tz::Europe__London, 2024, 2024),
+            "BEGIN:VTIMEZONE\r\n\
+            TZID:Europe/London\r\n\
+            BEGIN:STANDARD\r\n\
+            DTSTART:20240101T000000\r\n\
+            TZOFFSETFROM:+0000\r\n\
+            TZOFFSETTO:+0000\r\n\
+            TZNAME:GMT\r\n\
+            END:STANDARD\r\n\
+            BEGIN:DAYLIGHT\r\n\
+            DTSTART:20240331T010000\r\n\
+            TZOFFSETFROM:+0000\r\n\
+            TZOFFSETTO:+0100\r\n\
+            TZNAME:BST\r\n\
+            END:DAYLIGHT\r\n\
+            BEGIN:STANDARD\r\n\
+            DTSTART:20241027T020000\r\n\
+            TZOFFSETFROM:+0100\r\n\
+            TZOFFSETTO:+0000\r\n\
+            TZNAME:GMT\r\n\
+            END:STANDARD\r\n\
+            END:VTIMEZONE\r\n"
+        );
+    }
+
+    #[test]
+    fn calendar_properties() {
+        let offset = FixedOffset::east_opt(3600).unwrap();
+        let event = Event {
+            name: "Bal".to_string(),
+            details: None,
+            links: vec!["https://example.com/bal".to_string()],
+            time: EventTime::DateTime {
+                start: offset.with_ymd_and_hms(2024, 1, 5, 20, 0, 0).unwrap(),
+                end: offset.with_ymd_and_hms(2024, 1, 5, 23, 0, 0).unwrap(),
+            },
+            country: "Belgium".to_string(),
+            state: None,
+            city: "Gent".to_string(),
+            styles: vec![DanceStyle::Balfolk, DanceStyle::Contra],
+            workshop: false,
+            social: true,
+            bands: vec![],
+            callers: vec![],
+            price: None,
+            organisation: None,
+            cancelled: false,
+            source: None,
+        };
+        let now = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut history = EventHistory::default();
+        history.record(
+            &Events {
+                events: vec![event.clone()],
+            },
+            now,
+        );
+
+        let calendar = events_to_calendar(&[&event], "Events", &history, Utc::now());
+        assert!(calendar.contains("BEGIN:VTIMEZONE\r\nTZID:Europe/Brussels\r\n"));
+        assert!(calendar.find("END:VTIMEZONE") < calendar.find("BEGIN:VEVENT"));
+        assert!(calendar.contains(&format!("UID:{}@dancelist\r\n", event.uid())));
+        assert!(calendar.contains("DTSTAMP:20240101T000000Z\r\n"));
+        assert!(calendar.contains("LAST-MODIFIED:20240101T000000Z\r\n"));
+        assert!(calendar.contains("SEQUENCE:0\r\n"));
+        assert!(calendar.contains("DTSTART;TZID=Europe/Brussels:20240105T200000\r\n"));
+        assert!(calendar.contains("DTEND;TZID=Europe/Brussels:20240105T230000\r\n"));
+        assert!(calendar.contains("URL:https://example.com/bal\r\n"));
+        assert!(calendar.contains("CATEGORIES:balfolk\r\nCATEGORIES:contra\r\n"));
+        assert!(!calendar.contains("ATTACH"));
+    }
+
+    #[test]
+    fn unknown_timezone_uses_utc() {
+        let offset = FixedOffset::east_opt(3600).unwrap();
+        let event = Event {
+            name: "Dance".to_string(),
+            details: None,
+            links: vec![],
+            time: EventTime::DateTime {
+                start: offset.with_ymd_and_hms(2024, 1, 5, 20, 0, 0).unwrap(),
+                end: offset.with_ymd_and_hms(2024, 1, 5, 23, 0, 0).unwrap(),
+            },
+            country: "Nowhere".to_string(),
+            state: None,
+            city: "City".to_string(),
+            styles: vec![DanceStyle::Contra],
+            workshop: false,
+            social: true,
+            bands: vec![],
+            callers: vec![],
+            price: None,
+            organisation: None,
+            cancelled: false,
+            source: None,
+        };
+
+        let calendar =
+            events_to_calendar(&[&event], "Events", &EventHistory::default(), Utc::now());
+        assert!(!calendar.contains("VTIMEZONE"));
+        assert!(calendar.contains("DTSTART:20240105T190000Z\r\n"));
+    }
+}
@@ -85,6 +85,34 @@ fn convert<S: IcalendarSource>(parts: EventParts) -> Result<Option<event::Event>
         return Ok(None);
     };
     let links = S::links(&parts);
+    let event = convert_without_location(parts)?;
+
+    let organisation = Some(
+        event
+            .organisation
+            .clone()
+            .unwrap_or_else(|| S::DEFAULT_ORGANISATION.to_owned()),
+    );
+
+    Ok(S::fixup(event::Event {
+        links,
+        country,
+        state,
+        city,
+        styles,
+        workshop,
+        social,
+        organisation,
+        ..event
+    }))
+}
+
+/// Converts the given event parts to an event, finding the price, bands and callers from the
+/// summary and description.
+///
+/// The location, dance styles, workshop and social are left empty, as there is no general way to
+/// find them.
+pub(crate) fn convert_without_location(parts: EventParts) -> Result<event::Event, Report> {
     let price = get_price(&parts.description)?;
     let description_lower = parts.description.to_lowercase();
     let summary_lower = parts.summary.to_lowercase();
@@ -98,30 +126,24 @@ fn convert<S: IcalendarSource>(parts: EventParts) -> Result<Option<event::Event>
         Some(details)
     };
 
-    let organisation = Some(
-        parts
-            .organiser
-            .unwrap_or_else(|| S::DEFAULT_ORGANISATION.to_owned()),
-    );
-
-    Ok(S::fixup(event::Event {
+    Ok(event::Event {
         name: parts.summary.trim().to_owned(),
         details,
-        links,
+        links: parts.url.into_iter().collect(),
         time: parts.time,
-        country,
-        state,
-        city,
-        styles,
-        workshop,
-        social,
+        country: String::new(),
+        state: None,
+        city: String::new(),
+        styles: vec![],
+        workshop: false,
+        social: false,
         bands,
         callers,
         price,
-        organisation,
+        organisation: parts.organiser,
         cancelled: false,
         source: None,
-    }))
+    })
 }
 
 /// Figure out price from description.
@@ -173,19 +195,9 @@ pub async fn import_events<S: IcalendarSource>(old_events: Events) -> Result<Eve
 async fn import_new_events<S: IcalendarSource>() -> Result<Events, Report> {
     let mut events = Events::default();
     for url in S::URLS {
-        let calendar = reqwest::get(*url)
-            .await?
-            .text()
-            .await?
-            .parse::<Calendar>()
-            .map_err(|e| eyre!("Error parsing iCalendar file: {}", e))?;
-        let timezone = calendar.get_timezone().or(S::DEFAULT_TIMEZONE);
-        for component in calendar.iter() {
-            if let CalendarComponent::Event(event) = component {
-                events
-                    .events
-                    .extend(convert::<S>(get_parts(event, timezone)?)?);
-            }
+        let contents = reqwest::get(*url).await?.text().await?;
+        for parts in parse_calendar(&contents, S::DEFAULT_TIMEZONE)? {
+            events.events.extend(convert::<S>(parts)?);
         }
     }
     events.sort();
@@ -193,6 +205,29 @@ async fn import_new_events<S: IcalendarSource>() -> Result<Events, Report> {
     Ok(events)
 }
 
+/// Parses the given iCalendar file, and returns the parts of each event in it.
+///
+/// `default_timezone` is used for UTC times if the calendar doesn't specify its own timezone.
+pub(crate) fn parse_calendar(
+    contents: &str,
+    default_timezone: Option<&str>,
+) -> Result<Vec<EventParts>, Report> {
+    let calendar = contents
+        .parse::<Calendar>()
+        .map_err(|e| eyre!("Error parsing iCalendar file: {}", e))?;
+    let timezone = calendar.get_timezone().or(default_timezone);
+    calendar
+        .iter()
+        .filter_map(|component| {
+            if let CalendarComponent::Event(event) = component {
+                Some(get_parts(event, timezone))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
 fn get_parts(event: &Event, timezone: Option<&str>) -> Result<EventParts, Report> {
     let url = event.get_url().map(|url| {
         if url.contains("://") {
@@ -221,14 +256,12 @@ fn get_parts(event: &Event, timezone: Option<&str>) -> Result<EventParts, Report
             .ok_or_else(|| eyre!("Event {:?} missing organiser name", event))?
             .value();
         Some(organiser_name.to_owned())
-    } else if let Some(attendee) = event
-        .multi_properties()
-        .get("ATTENDEE")
-        .and_then(|attendees| attendees.first())
-    {
-        Some(attendee.value().to_owned())
     } else {
-        None
+        event
+            .multi_properties()
+            .get("ATTENDEE")
+            .and_then(|attendees| attendees.first())
+            .map(|attendee| attendee.value().to_owned())
     };
     let categories = get_categories(event);
     let uid = event.get_uid().map(ToOwned::to_owned);
@@ -280,7 +313,7 @@ fn lowercase_matches(needles: &[&str], a: &str, b: &str) -> Vec<String> {
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
-struct EventParts {
+pub(crate) struct EventParts {
     pub url: Option<String>,
     pub summary: String,
     pub description: String,
@@ -112,10 +112,8 @@ impl IcalendarSource for Boulder {
                     event.price = Some("$5".to_string());
                 }
             }
-            "Scandinavian Weekly Dance" | "Scandinavian Basics Class" => {
-                if event.price.is_none() {
-                    event.price = Some("$7".to_string());
-                }
+            "Scandinavian Weekly Dance" | "Scandinavian Basics Class" if event.price.is_none() => {
+                event.price = Some("$7".to_string());
             }
             _ => {}
         }
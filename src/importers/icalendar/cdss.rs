@@ -109,7 +109,7 @@ impl IcalendarSource for Cdss {
             return Ok(Some((
                 "USA".to_owned(),
                 location_parts.get(1).cloned(),
-                location_parts.get(0).cloned().unwrap_or_default(),
+                location_parts.first().cloned().unwrap_or_default(),
             )));
         }
         let mut country = location_parts[location_parts.len() - 1].to_owned();
@@ -174,11 +174,8 @@ fn shorten_name(name: &str) -> String {
 
 /// Apply fixes for specific event series.
 fn apply_fixes(event: &mut Event) {
-    match (event.city.as_str(), event.state.as_deref()) {
-        ("Henrico", Some("VA")) => {
-            event.city = "Richmond".to_string();
-        }
-        _ => {}
+    if let ("Henrico", Some("VA")) = (event.city.as_str(), event.state.as_deref()) {
+        event.city = "Richmond".to_string();
     }
     match event.name.as_str() {
         "2nd Saturdays Contra Dance" | "4th Saturdays Contra Dance" if event.city == "Portland" => {
@@ -26,7 +26,7 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use crate::{github::to_safe_filename, model::events::Events};
+use crate::{forge::to_safe_filename, model::events::Events};
 use eyre::Report;
 use log::info;
 
@@ -0,0 +1,239 @@
+// Copyright 2024 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! schema.org JSON-LD markup for events, so that search engines can show them as rich results.
+
+use crate::{
+    geo::city_coordinates,
+    model::event::{Event, EventTime},
+};
+use serde_json::{json, Map, Value};
+
+/// Returns a schema.org `DanceEvent` for the given event, serialised as JSON-LD which is safe to
+/// include directly in a `<script>` element.
+pub fn json_ld(event: &Event) -> String {
+    // Escape `<` so that strings containing `</script>` can't end the element early.
+    event_to_json_ld(event).to_string().replace('<', "\\u003c")
+}
+
+fn event_to_json_ld(event: &Event) -> Value {
+    let mut object = Map::new();
+    object.insert("@context".to_owned(), json!("https://schema.org"));
+    object.insert("@type".to_owned(), json!("DanceEvent"));
+    object.insert("name".to_owned(), json!(event.name));
+    if let Some(details) = &event.details {
+        object.insert("description".to_owned(), json!(details));
+    }
+    if let Some(link) = event.main_link() {
+        object.insert("url".to_owned(), json!(link));
+    }
+    match event.time {
+        EventTime::DateOnly {
+            start_date,
+            end_date,
+        } => {
+            object.insert("startDate".to_owned(), json!(start_date.to_string()));
+            object.insert("endDate".to_owned(), json!(end_date.to_string()));
+        }
+        EventTime::DateTime { start, end } => {
+            object.insert("startDate".to_owned(), json!(start.to_rfc3339()));
+            object.insert("endDate".to_owned(), json!(end.to_rfc3339()));
+        }
+    }
+    object.insert(
+        "eventStatus".to_owned(),
+        json!(if event.cancelled {
+            "https://schema.org/EventCancelled"
+        } else {
+            "https://schema.org/EventScheduled"
+        }),
+    );
+    object.insert(
+        "eventAttendanceMode".to_owned(),
+        json!("https://schema.org/OfflineEventAttendanceMode"),
+    );
+    object.insert("location".to_owned(), location(event));
+
+    let performers = event
+        .bands
+        .iter()
+        .map(|band| json!({ "@type": "MusicGroup", "name": band }))
+        .chain(
+            event
+                .callers
+                .iter()
+                .map(|caller| json!({ "@type": "Person", "name": caller })),
+        )
+        .collect::<Vec<_>>();
+    if !performers.is_empty() {
+        object.insert("performer".to_owned(), Value::Array(performers));
+    }
+    if let Some(organisation) = &event.organisation {
+        object.insert(
+            "organizer".to_owned(),
+            json!({ "@type": "Organization", "name": organisation }),
+        );
+    }
+    if let Some(price) = &event.price {
+        object.insert("offers".to_owned(), offer(event, price));
+    }
+
+    Value::Object(object)
+}
+
+/// Makes a schema.org `Place` for the city of the event.
+fn location(event: &Event) -> Value {
+    let mut address = json!({
+        "@type": "PostalAddress",
+        "addressLocality": event.city,
+        "addressCountry": event.country,
+    });
+    if let Some(state) = &event.state {
+        address["addressRegion"] = json!(state);
+    }
+    let mut place = json!({
+        "@type": "Place",
+        "name": event.city,
+        "address": address,
+    });
+    if let Some(coordinates) = city_coordinates(&event.country, event.state.as_deref(), &event.city)
+    {
+        place["geo"] = json!({
+            "@type": "GeoCoordinates",
+            "latitude": coordinates.latitude,
+            "longitude": coordinates.longitude,
+        });
+    }
+    place
+}
+
+/// Makes a schema.org `Offer` or `AggregateOffer` for the given price of the event.
+///
+/// Prices are free text, so if they can't be parsed as an amount or range of amounts in a known
+/// currency then the text is used as the description of the offer instead.
+fn offer(event: &Event, price: &str) -> Value {
+    let mut offer = if let Some((low, high, currency)) = parse_price_range(&event.country, price) {
+        if low == high {
+            json!({ "@type": "Offer", "price": low, "priceCurrency": currency })
+        } else {
+            json!({
+                "@type": "AggregateOffer",
+                "lowPrice": low,
+                "highPrice": high,
+                "priceCurrency": currency,
+            })
+        }
+    } else {
+        json!({ "@type": "Offer", "description": price })
+    };
+    if let Some(link) = event.main_link() {
+        offer["url"] = json!(link);
+    }
+    offer
+}
+
+/// Parses a price like "£10" or "€8-€12" into the lowest and highest amount and the currency code.
+fn parse_price_range(country: &str, price: &str) -> Option<(f64, f64, &'static str)> {
+    let (low, high) = match price.split_once(['-', '–']) {
+        Some((low, high)) => (low, high),
+        None => (price, price),
+    };
+    let (low, low_currency) = parse_amount(country, low)?;
+    let (high, high_currency) = parse_amount(country, high).or_else(|| {
+        // Allow the currency symbol to be omitted from the second part, like "$5-10".
+        Some((high.trim().parse().ok()?, low_currency))
+    })?;
+    if low_currency != high_currency {
+        return None;
+    }
+    Some((low, high, low_currency))
+}
+
+/// Parses a single amount with a currency symbol like "£10".
+fn parse_amount(country: &str, amount: &str) -> Option<(f64, &'static str)> {
+    let amount = amount.trim();
+    let mut chars = amount.chars();
+    let currency = match chars.next()? {
+        '£' => "GBP",
+        '€' => "EUR",
+        '$' => match country {
+            "Australia" => "AUD",
+            "Canada" => "CAD",
+            "New Zealand" => "NZD",
+            _ => "USD",
+        },
+        _ => return None,
+    };
+    Some((chars.as_str().trim().parse().ok()?, currency))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::dancestyle::DanceStyle;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn parse_prices() {
+        assert_eq!(parse_price_range("UK", "£10"), Some((10.0, 10.0, "GBP")));
+        assert_eq!(
+            parse_price_range("Canada", "$5-10"),
+            Some((5.0, 10.0, "CAD"))
+        );
+        assert_eq!(
+            parse_price_range("Belgium", "€8 – €12.50"),
+            Some((8.0, 12.5, "EUR"))
+        );
+        assert_eq!(parse_price_range("UK", "donation"), None);
+        assert_eq!(parse_price_range("UK", "£5-€6"), None);
+    }
+
+    #[test]
+    fn event_json_ld() {
+        let event = Event {
+            name: "Contra </script> dance".to_string(),
+            details: None,
+            links: vec!["https://example.com/".to_string()],
+            time: EventTime::DateOnly {
+                start_date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+                end_date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            },
+            country: "UK".to_string(),
+            state: None,
+            city: "Oxford".to_string(),
+            styles: vec![DanceStyle::Contra],
+            workshop: false,
+            social: true,
+            bands: vec!["Band".to_string()],
+            callers: vec!["Caller".to_string()],
+            price: Some("£10".to_string()),
+            organisation: Some("Org".to_string()),
+            cancelled: true,
+            source: None,
+        };
+
+        let json = json_ld(&event);
+        assert!(!json.contains("</script>"));
+        let value: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["name"], "Contra </script> dance");
+        assert_eq!(value["startDate"], "2024-01-01");
+        assert_eq!(value["eventStatus"], "https://schema.org/EventCancelled");
+        assert_eq!(value["location"]["address"]["addressLocality"], "Oxford");
+        assert_eq!(value["performer"][0]["@type"], "MusicGroup");
+        assert_eq!(value["performer"][1]["name"], "Caller");
+        assert_eq!(value["organizer"]["name"], "Org");
+        assert_eq!(value["offers"]["price"], 10.0);
+        assert_eq!(value["offers"]["priceCurrency"], "GBP");
+    }
+}
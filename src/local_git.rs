@@ -0,0 +1,463 @@
+// Copyright 2024 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Committing submitted events directly to a local git checkout of the events data repository, for
+//! small deployments which don't use a forge.
+
+use crate::{
+    change::Change,
+    config::LocalGitConfig,
+    forge::{branch_name_base, commit_author},
+    model::events::Events,
+    moderation::{apply_to_local_file, local_events_path},
+};
+use eyre::{bail, eyre, Report, WrapErr};
+use log::info;
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+/// The higher suffix number to add to a branch name.
+const MAX_SUFFIX: u32 = 9;
+
+/// Used to give each temporary index file a unique name.
+static INDEX_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+/// Makes the given change to the given file in the local git repository, and commits it with the
+/// submitter as author.
+///
+/// If a branch is configured the change is committed to it in the working tree, otherwise it is
+/// committed to a new branch from `HEAD` without touching the working tree. Returns the name of the
+/// branch to which it was committed.
+pub fn commit_change(
+    change: &Change,
+    filename: &str,
+    email: Option<&str>,
+    events_dir: &str,
+    config: &LocalGitConfig,
+) -> Result<String, Report> {
+    if change.events().is_empty() {
+        bail!("No events to commit");
+    }
+    let path = local_events_path(filename, events_dir)?;
+    let repository_path = repository_relative_path(&path, &config.repository)?;
+    let message = change.message();
+    let git = Git::new(config, email);
+
+    let branch = if let Some(branch) = &config.branch {
+        let head = git.run(&["symbolic-ref", "--short", "HEAD"])?;
+        if &head != branch {
+            bail!(
+                "Branch {} is not checked out in {:?}, {} is",
+                branch,
+                config.repository,
+                head
+            );
+        }
+        apply_to_local_file(&path, change)?;
+        git.run(&["add", "--", &repository_path])?;
+        git.run(&["commit", "--quiet", "-m", &message, "--", &repository_path])?;
+        branch.to_owned()
+    } else {
+        let base = git.run(&["rev-parse", "HEAD"])?;
+        let commit = commit_without_checkout(&git, &base, &repository_path, change, &message)?;
+        create_branch(
+            &git,
+            &branch_name_base(change.action(), change.subject()),
+            &commit,
+        )?
+    };
+    info!(
+        "Committed {} to {} on branch {}",
+        message, repository_path, branch
+    );
+    Ok(branch)
+}
+
+/// Makes a commit on top of `base` which makes the given change to the file at the given path in
+/// the repository, without changing the working tree or index.
+///
+/// Returns the ID of the new commit.
+fn commit_without_checkout(
+    git: &Git,
+    base: &str,
+    repository_path: &str,
+    change: &Change,
+    message: &str,
+) -> Result<String, Report> {
+    let object = format!("{}:{}", base, repository_path);
+    let mut events = if git.run(&["cat-file", "-e", &object]).is_ok() {
+        let contents = git.run(&["show", &object])?;
+        serde_yaml::from_str::<Events>(&contents).wrap_err_with(|| format!("Parsing {}", object))?
+    } else {
+        Events::default()
+    };
+    change
+        .apply(&mut events)
+        .wrap_err_with(|| format!("Changing {}", object))?;
+    let blob = git.run_with_input(&["hash-object", "-w", "--stdin"], &events.to_yaml_string()?)?;
+
+    // Build the new tree in a temporary index, so as not to disturb the real one.
+    let index = PathBuf::from(git.run(&["rev-parse", "--absolute-git-dir"])?).join(format!(
+        "dancelist-index-{}-{}",
+        process::id(),
+        INDEX_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let cacheinfo = format!("100644,{},{}", blob, repository_path);
+    let tree = git
+        .run_with_index(&index, &["read-tree", base])
+        .and_then(|_| {
+            git.run_with_index(
+                &index,
+                &["update-index", "--add", "--cacheinfo", &cacheinfo],
+            )
+        })
+        .and_then(|_| git.run_with_index(&index, &["write-tree"]));
+    let _ = fs::remove_file(&index);
+
+    git.run(&["commit-tree", &tree?, "-p", base, "-m", message])
+}
+
+/// Creates a new branch pointing to the given commit, retrying with different suffixes if it
+/// already exists.
+///
+/// Returns the name of the new branch.
+fn create_branch(git: &Git, branch_base: &str, commit: &str) -> Result<String, Report> {
+    let mut last_error = eyre!("Failed to create branch");
+    for suffix in 0..=MAX_SUFFIX {
+        let branch = if suffix == 0 {
+            branch_base.to_owned()
+        } else {
+            format!("{}{}", branch_base, suffix)
+        };
+        // An empty old value makes sure that the branch doesn't already exist.
+        match git.run(&["update-ref", &format!("refs/heads/{}", branch), commit, ""]) {
+            Ok(_) => return Ok(branch),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error.wrap_err(format!(
+        "Failed to create branch {} after trying all suffixes",
+        branch_base
+    )))
+}
+
+/// Returns the given local path relative to the root of the repository, in the form git expects.
+fn repository_relative_path(path: &Path, repository: &Path) -> Result<String, Report> {
+    let repository = repository
+        .canonicalize()
+        .wrap_err_with(|| format!("Finding repository {:?}", repository))?;
+    let path = canonicalize_new(path)?;
+    let Ok(relative) = path.strip_prefix(&repository) else {
+        bail!("{:?} is not inside repository {:?}", path, repository);
+    };
+    let components = relative
+        .iter()
+        .map(|component| {
+            component
+                .to_str()
+                .ok_or_else(|| eyre!("Invalid path {:?}", relative))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(components.join("/"))
+}
+
+/// Returns the canonical form of the given path, which may not exist yet.
+fn canonicalize_new(path: &Path) -> Result<PathBuf, Report> {
+    if path.exists() {
+        return Ok(path.canonicalize()?);
+    }
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        bail!("Invalid path {:?}", path);
+    };
+    let parent = if parent.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        parent
+    };
+    Ok(canonicalize_new(parent)?.join(name))
+}
+
+/// Runs git commands in the repository, with the submitter as author.
+struct Git<'a> {
+    config: &'a LocalGitConfig,
+    author_name: String,
+    author_email: String,
+}
+
+impl<'a> Git<'a> {
+    fn new(config: &'a LocalGitConfig, email: Option<&str>) -> Self {
+        let (author_name, author_email) = match commit_author(email) {
+            Some(author) => (author.name, author.email),
+            None => (
+                config.committer_name.clone(),
+                config.committer_email.clone(),
+            ),
+        };
+        Self {
+            config,
+            author_name,
+            author_email,
+        }
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new("git");
+        command
+            .current_dir(&self.config.repository)
+            .args(args)
+            .env("GIT_AUTHOR_NAME", &self.author_name)
+            .env("GIT_AUTHOR_EMAIL", &self.author_email)
+            .env("GIT_COMMITTER_NAME", &self.config.committer_name)
+            .env("GIT_COMMITTER_EMAIL", &self.config.committer_email);
+        command
+    }
+
+    /// Runs git with the given arguments, and returns its standard output without any trailing
+    /// whitespace.
+    fn run(&self, args: &[&str]) -> Result<String, Report> {
+        output(self.command(args), args, None)
+    }
+
+    fn run_with_input(&self, args: &[&str], input: &str) -> Result<String, Report> {
+        output(self.command(args), args, Some(input))
+    }
+
+    /// Runs git with the given arguments, using the given index file rather than the default.
+    fn run_with_index(&self, index: &Path, args: &[&str]) -> Result<String, Report> {
+        let mut command = self.command(args);
+        command.env("GIT_INDEX_FILE", index);
+        output(command, args, None)
+    }
+}
+
+fn output(mut command: Command, args: &[&str], input: Option<&str>) -> Result<String, Report> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .wrap_err("Running git")?;
+    let mut stdin = child.stdin.take().unwrap();
+    if let Some(input) = input {
+        stdin.write_all(input.as_bytes())?;
+    }
+    drop(stdin);
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        );
+    }
+    Ok(String::from_utf8(output.stdout)?.trim_end().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        change::cancelled,
+        model::{
+            dancestyle::DanceStyle,
+            event::{Event, EventTime},
+        },
+    };
+    use chrono::NaiveDate;
+    use std::fs::create_dir_all;
+    use tempfile::tempdir;
+
+    fn test_event(name: &str, country: &str, city: &str, day: u32) -> Event {
+        Event {
+            name: name.to_string(),
+            details: None,
+            links: vec!["https://example.com/".to_string()],
+            time: EventTime::DateOnly {
+                start_date: NaiveDate::from_ymd_opt(2030, 1, day).unwrap(),
+                end_date: NaiveDate::from_ymd_opt(2030, 1, day).unwrap(),
+            },
+            country: country.to_string(),
+            state: None,
+            city: city.to_string(),
+            styles: vec![DanceStyle::Contra],
+            workshop: false,
+            social: true,
+            bands: vec![],
+            callers: vec![],
+            price: None,
+            organisation: None,
+            cancelled: false,
+            source: None,
+        }
+    }
+
+    #[test]
+    fn commit_to_branches() {
+        let dir = tempdir().unwrap();
+        let events_dir = dir.path().join("events");
+        create_dir_all(events_dir.join("uk")).unwrap();
+        let existing = test_event("Existing", "UK", "London", 1);
+        apply_to_local_file(
+            &events_dir.join("uk/london.yaml"),
+            &Change::Add(vec![existing.clone()]),
+        )
+        .unwrap();
+        let mut config = LocalGitConfig {
+            repository: dir.path().to_owned(),
+            branch: Some("main".to_string()),
+            committer_name: "dancelist".to_string(),
+            committer_email: "dancelist@localhost".to_string(),
+        };
+        let initial_config = config.clone();
+        let git = Git::new(&initial_config, None);
+        git.run(&["init", "--quiet", "--initial-branch=main"])
+            .unwrap();
+        git.run(&["add", "."]).unwrap();
+        git.run(&["commit", "--quiet", "-m", "Initial"]).unwrap();
+        let events_dir = events_dir.to_str().unwrap();
+
+        // Commit directly to the checked out branch.
+        let new = test_event("New", "UK", "London", 2);
+        let branch = commit_change(
+            &Change::Add(vec![new.clone()]),
+            "events/uk/london.yaml",
+            Some("user@example.com"),
+            events_dir,
+            &config,
+        )
+        .unwrap();
+        assert_eq!(branch, "main");
+        assert_eq!(
+            serde_yaml::from_str::<Events>(
+                &fs::read_to_string(dir.path().join("events/uk/london.yaml")).unwrap()
+            )
+            .unwrap()
+            .events,
+            vec![existing.clone(), new]
+        );
+        assert_eq!(
+            git.run(&["log", "-1", "--format=%an <%ae> %cn: %s"])
+                .unwrap(),
+            "Add form user <user@example.com> dancelist: Add New in London"
+        );
+        assert_eq!(git.run(&["status", "--porcelain"]).unwrap(), "");
+
+        // Cancel an existing event on the checked out branch.
+        let cancel = Change::Cancel {
+            original: existing.clone(),
+            reason: None,
+        };
+        commit_change(&cancel, "events/uk/london.yaml", None, events_dir, &config).unwrap();
+        assert!(
+            serde_yaml::from_str::<Events>(
+                &fs::read_to_string(dir.path().join("events/uk/london.yaml")).unwrap()
+            )
+            .unwrap()
+            .events[0]
+                .cancelled
+        );
+        assert_eq!(
+            git.run(&["log", "-1", "--format=%s"]).unwrap(),
+            "Cancel Existing in London"
+        );
+        // It can't be cancelled again, as the original is no longer there.
+        assert!(
+            commit_change(&cancel, "events/uk/london.yaml", None, events_dir, &config).is_err()
+        );
+        assert_eq!(git.run(&["status", "--porcelain"]).unwrap(), "");
+
+        // Commit to new branches.
+        config.branch = None;
+        let bals = vec![
+            test_event("Bal", "France", "Paris", 4),
+            test_event("Bal", "France", "Paris", 3),
+        ];
+        let branch = commit_change(
+            &Change::Add(bals.clone()),
+            "events/france/paris.yaml",
+            None,
+            events_dir,
+            &config,
+        )
+        .unwrap();
+        assert_eq!(branch, "add-france-paris-bal");
+        let committed = git
+            .run(&["show", "add-france-paris-bal:events/france/paris.yaml"])
+            .unwrap();
+        assert_eq!(
+            serde_yaml::from_str::<Events>(&committed).unwrap().events,
+            vec![bals[1].clone(), bals[0].clone()]
+        );
+        assert_eq!(
+            git.run(&["log", "-1", "--format=%an: %s", "add-france-paris-bal"])
+                .unwrap(),
+            "dancelist: Add 2 events for Bal in Paris"
+        );
+        assert_eq!(git.run(&["status", "--porcelain"]).unwrap(), "");
+        assert!(!dir.path().join("events/france").exists());
+
+        let branch = commit_change(
+            &Change::Add(bals.clone()),
+            "events/france/paris.yaml",
+            None,
+            events_dir,
+            &config,
+        )
+        .unwrap();
+        assert_eq!(branch, "add-france-paris-bal1");
+
+        // Edit an event on a new branch.
+        let new = test_event("New", "UK", "London", 2);
+        let edited = Event {
+            bands: vec!["Band".to_string()],
+            ..new.clone()
+        };
+        let edit = Change::Edit {
+            original: new,
+            edited: edited.clone(),
+        };
+        let branch =
+            commit_change(&edit, "events/uk/london.yaml", None, events_dir, &config).unwrap();
+        assert_eq!(branch, "edit-uk-london-new");
+        let committed = git
+            .run(&["show", "edit-uk-london-new:events/uk/london.yaml"])
+            .unwrap();
+        assert_eq!(
+            serde_yaml::from_str::<Events>(&committed).unwrap().events,
+            vec![cancelled(&existing), edited]
+        );
+        assert_eq!(
+            git.run(&["log", "-1", "--format=%s", "edit-uk-london-new"])
+                .unwrap(),
+            "Update New in London"
+        );
+        assert_eq!(git.run(&["status", "--porcelain"]).unwrap(), "");
+
+        // The configured branch must be checked out.
+        config.branch = Some("other".to_string());
+        assert!(commit_change(
+            &Change::Add(vec![test_event("New", "UK", "London", 2)]),
+            "events/uk/london.yaml",
+            None,
+            events_dir,
+            &config,
+        )
+        .is_err());
+    }
+}
@@ -12,20 +12,33 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod calendar_links;
+mod change;
 mod config;
 mod controllers;
 mod diff;
 mod errors;
 mod extractors;
-mod github;
+mod forge;
+mod geo;
+mod geojson;
 mod icalendar;
 mod importers;
+mod jsonld;
+mod local_git;
 mod model;
+mod moderation;
+mod spam;
+mod stats;
+mod syndication;
 mod util;
 
 use crate::{
     config::Config,
-    controllers::{add, bands, callers, cities, index, organisations, reload},
+    controllers::{
+        add, admin, api, bands, callers, cancel, cities, embed, index, organisations, reload,
+        sources, suggest, upload,
+    },
     diff::diff_markdown,
     errors::internal_error,
     importers::{
@@ -37,7 +50,9 @@ use crate::{
         },
         plugevents, trycontra, webfeet,
     },
-    model::events::Events,
+    model::{events::Events, filters::Filters, history::EventHistory},
+    spam::SpamGuard,
+    stats::Stats,
 };
 use axum::{
     extract::FromRef,
@@ -51,6 +66,7 @@ use log::info;
 use schemars::schema_for;
 use std::{
     fs::write,
+    net::SocketAddr,
     path::{Path, PathBuf},
     sync::{Arc, Mutex},
 };
@@ -77,7 +93,12 @@ enum Command {
     ///
     /// If no path or URL is specified, uses the one configured in the config file.
     #[command(name = "cat")]
-    Concatenate { events: Option<String> },
+    Concatenate {
+        events: Option<String>,
+        /// The format in which to print the events.
+        #[arg(long, value_enum, default_value = "yaml")]
+        format: OutputFormat,
+    },
     /// Loads all events from the given file, directory or URL, and prints them sorted by start
     /// time, country then city.
     Sort { events: String },
@@ -98,11 +119,28 @@ enum Command {
         /// The file to which to write the imported events.
         filename: PathBuf,
     },
+    /// Prints statistics about the events matching the given filters as JSON.
+    ///
+    /// If no path or URL is specified, uses the one configured in the config file.
+    Stats {
+        events: Option<String>,
+        /// Filters for which events to include, in the same query string format as the website.
+        #[arg(long, default_value = "date=all")]
+        filters: String,
+    },
     /// Loads events as configured in the config file and tries to find duplicates.
     #[command(name = "dups")]
     Duplicates,
 }
 
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+enum OutputFormat {
+    Csv,
+    Json,
+    Toml,
+    Yaml,
+}
+
 #[derive(Copy, Clone, Debug, ValueEnum)]
 enum ImportSource {
     /// Imports events from folkbalbende.be.
@@ -148,8 +186,11 @@ async fn main() -> Result<(), Report> {
             Ok(())
         }
         Some(Command::Validate { events }) => validate(events.as_deref()).await,
-        Some(Command::Concatenate { events }) => concatenate(events.as_deref()).await,
+        Some(Command::Concatenate { events, format }) => {
+            concatenate(events.as_deref(), *format).await
+        }
         Some(Command::Sort { events }) => sort(events).await,
+        Some(Command::Stats { events, filters }) => stats(events.as_deref(), filters).await,
         Some(Command::Duplicates) => find_duplicates().await,
         Some(Command::Diff { old, new }) => diff(old, new).await,
         Some(Command::Import { source, filename }) => import(*source, filename).await,
@@ -177,9 +218,23 @@ async fn validate(path: Option<&str>) -> Result<(), Report> {
     Ok(())
 }
 
-async fn concatenate(path: Option<&str>) -> Result<(), Report> {
+async fn concatenate(path: Option<&str>, format: OutputFormat) -> Result<(), Report> {
     let events = load_events(path).await?;
-    print!("{}", serde_yaml::to_string(&events)?);
+    match format {
+        OutputFormat::Csv => print!("{}", events.to_csv_string()?),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&events)?),
+        OutputFormat::Toml => print!("{}", toml::to_string(&events)?),
+        OutputFormat::Yaml => print!("{}", serde_yaml::to_string(&events)?),
+    }
+    Ok(())
+}
+
+/// Prints statistics about the events matching the given filters, as JSON.
+async fn stats(path: Option<&str>, filters: &str) -> Result<(), Report> {
+    let events = load_events(path).await?;
+    let filters: Filters = serde_html_form::from_str(filters)?;
+    let stats = Stats::new(&events.matching(&filters));
+    println!("{}", serde_json::to_string_pretty(&stats)?);
     Ok(())
 }
 
@@ -272,26 +327,64 @@ async fn find_duplicates() -> Result<(), Report> {
 async fn serve() -> Result<(), Report> {
     let config = Arc::new(Config::from_file()?);
     let events = Events::load_events(&config.events).await?;
-    let events = Arc::new(Mutex::new(events));
+    let mut history = if let Some(history_file) = &config.history_file {
+        EventHistory::load(history_file)?
+    } else {
+        EventHistory::default()
+    };
+    history.record_and_save(&events, config.history_file.as_deref())?;
     let state = AppState {
         config: config.clone(),
-        events,
+        events: Arc::new(Mutex::new(events)),
+        history: Arc::new(Mutex::new(history)),
+        spam: Arc::new(SpamGuard::new(config.spam.clone())),
     };
 
     let app = Router::new()
         .route("/", get(index::index))
+        .route("/index.csv", get(index::index_csv))
+        .route("/index.geojson", get(index::index_geojson))
         .route("/index.ics", get(index::index_ics))
+        .route("/index.rss", get(index::index_rss))
+        .route("/index.atom", get(index::index_atom))
         .route("/index.json", get(index::index_json))
         .route("/index.toml", get(index::index_toml))
         .route("/index.yaml", get(index::index_yaml))
         .route("/calendar", get(index::calendar))
+        .route("/events/:uid", get(index::event_ics))
+        .route("/events/:uid/:service", get(index::event_calendar_link))
+        .route("/feeds/:filename", get(index::feed))
         .route("/add", get(add::add))
         .route("/add", post(add::submit))
+        .route("/admin/sources", get(sources::sources))
+        .route("/admin/submissions", get(admin::submissions))
+        .route("/admin/submissions/:id/approve", post(admin::approve))
+        .route("/admin/submissions/:id/reject", post(admin::reject))
+        .route("/edit/:uid", get(add::edit))
+        .route("/edit/:uid", post(add::submit_edit))
+        .route("/api/v1/events", get(api::events))
+        .route("/api/v1/openapi.json", get(api::openapi))
+        .route("/api/suggest/bands", get(suggest::bands))
+        .route("/api/suggest/callers", get(suggest::callers))
+        .route("/api/suggest/cities", get(suggest::cities))
+        .route("/api/suggest/organisations", get(suggest::organisations))
         .route("/bands", get(bands::bands))
+        .route("/bands/:name", get(bands::band))
         .route("/callers", get(callers::callers))
+        .route("/callers/:name", get(callers::caller))
+        .route("/cancel/:uid", get(cancel::cancel))
+        .route("/cancel/:uid", post(cancel::submit))
         .route("/cities", get(cities::cities))
+        .route("/embed", get(embed::embed))
+        .route("/embed.json", get(embed::embed_json))
+        .route("/map", get(index::map))
         .route("/organisations", get(organisations::organisations))
+        .route("/organisations/:name", get(organisations::organisation))
         .route("/reload", post(reload::reload))
+        .route("/stats", get(controllers::stats::stats))
+        .route("/upload", get(upload::upload))
+        .route("/upload", post(upload::preview))
+        .route("/upload/submit", post(upload::submit))
         .nest_service(
             "/scripts",
             get_service(ServeDir::new(config.public_dir.join("scripts")))
@@ -306,7 +399,11 @@ async fn serve() -> Result<(), Report> {
 
     info!("Listening on {}", config.bind_address);
     let listener = TcpListener::bind(&config.bind_address).await?;
-    axum::serve(listener, app.into_make_service()).await?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
 
     Ok(())
 }
@@ -315,6 +412,8 @@ async fn serve() -> Result<(), Report> {
 struct AppState {
     config: Arc<Config>,
     events: Arc<Mutex<Events>>,
+    history: Arc<Mutex<EventHistory>>,
+    spam: Arc<SpamGuard>,
 }
 
 /// Returns the JSON schema for events.
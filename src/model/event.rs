@@ -16,7 +16,8 @@ use super::dancestyle::DanceStyle;
 use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, TimeDelta, TimeZone, Utc};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize, Serializer};
-use std::ops::Not;
+use sha2::{Digest, Sha256};
+use std::{cmp::max, ops::Not};
 
 /// The prefix which Facebook event URLs start with.
 const FACEBOOK_EVENT_PREFIX: &str = "https://www.facebook.com/events/";
@@ -132,6 +133,26 @@ impl EventTime {
 }
 
 impl Event {
+    /// Returns a stable unique identifier for the event, in UUID format.
+    ///
+    /// This is derived from the name, start time, location and organisation of the event, so stays
+    /// the same when other details such as the bands or price are changed, but differs between
+    /// separate sessions of the same event on the same day.
+    pub fn uid(&self) -> String {
+        let start = match self.time {
+            EventTime::DateOnly { start_date, .. } => start_date.to_string(),
+            EventTime::DateTime { start, .. } => start.to_rfc3339(),
+        };
+        uid_from_parts(&[
+            self.name.as_str(),
+            &start,
+            &self.country,
+            self.state.as_deref().unwrap_or_default(),
+            &self.city,
+            self.organisation.as_deref().unwrap_or_default(),
+        ])
+    }
+
     /// Check that the event information is valid. Returns an empty list if it is, or a list of
     /// problems if not.
     pub fn validate(&self) -> Vec<&'static str> {
@@ -314,14 +335,24 @@ impl Event {
 
     /// Checks whether the event lasts more than one day.
     pub fn multiday(&self) -> bool {
+        let (start_date, end_date) = self.local_date_range();
+        start_date != end_date
+    }
+
+    /// Gets the first and last days of the event, in its local timezone.
+    ///
+    /// An event which finishes a few hours after midnight is considered to finish the day before.
+    pub fn local_date_range(&self) -> (NaiveDate, NaiveDate) {
         match self.time {
             EventTime::DateOnly {
                 start_date,
                 end_date,
-            } => start_date != end_date,
+            } => (start_date, end_date),
             // Subtract a few hours from the end time in case it finishes after midnight.
             EventTime::DateTime { start, end } => {
-                start.date_naive() < (end - TimeDelta::try_hours(5).unwrap()).date_naive()
+                let start_date = start.date_naive();
+                let end_date = (end - TimeDelta::try_hours(5).unwrap()).date_naive();
+                (start_date, max(start_date, end_date))
             }
         }
     }
@@ -467,11 +498,80 @@ pub struct Link {
     pub url: String,
 }
 
+/// Makes an identifier in UUID format from a hash of the given parts.
+fn uid_from_parts(parts: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part.as_bytes());
+        hasher.update(b"\n");
+    }
+    let hash = hasher.finalize();
+    let hex = hash[..16]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use chrono::TimeZone;
 
+    #[test]
+    fn uid_distinguishes_sessions() {
+        let timezone = FixedOffset::east_opt(3600).unwrap();
+        let afternoon = Event {
+            name: "Balfolk".to_string(),
+            details: None,
+            links: vec![],
+            time: EventTime::DateTime {
+                start: timezone.with_ymd_and_hms(2024, 3, 2, 14, 0, 0).unwrap(),
+                end: timezone.with_ymd_and_hms(2024, 3, 2, 17, 0, 0).unwrap(),
+            },
+            country: "Netherlands".to_string(),
+            state: None,
+            city: "Utrecht".to_string(),
+            styles: vec![DanceStyle::Balfolk],
+            workshop: false,
+            social: true,
+            bands: vec![],
+            callers: vec![],
+            price: None,
+            organisation: Some("Organisation".to_string()),
+            cancelled: false,
+            source: None,
+        };
+        let evening = Event {
+            time: EventTime::DateTime {
+                start: timezone.with_ymd_and_hms(2024, 3, 2, 20, 0, 0).unwrap(),
+                end: timezone.with_ymd_and_hms(2024, 3, 2, 23, 0, 0).unwrap(),
+            },
+            ..afternoon.clone()
+        };
+        let other_organisation = Event {
+            organisation: Some("Other organisation".to_string()),
+            ..afternoon.clone()
+        };
+        assert_ne!(afternoon.uid(), evening.uid());
+        assert_ne!(afternoon.uid(), other_organisation.uid());
+
+        // Changing other details shouldn't change the UID.
+        let changed = Event {
+            bands: vec!["Band".to_string()],
+            price: Some("€10".to_string()),
+            ..afternoon.clone()
+        };
+        assert_eq!(afternoon.uid(), changed.uid());
+    }
+
     #[test]
     fn multiday() {
         // An event which starts in the evening and finishes a bit after midnight shouldn't count as
@@ -12,13 +12,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::{dancestyle::DanceStyle, event::Event, filters::Filters};
-use chrono::Utc;
-use eyre::{bail, Report, WrapErr};
+use super::{
+    dancestyle::DanceStyle,
+    event::{Event, EventTime},
+    filters::{DateFilter, Filters},
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use eyre::{bail, eyre, Report, WrapErr};
 use log::trace;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::{
+    borrow::Cow,
     collections::HashMap,
     ffi::OsStr,
     fs::{read_dir, read_to_string},
@@ -135,6 +140,54 @@ impl Events {
         ))
     }
 
+    /// Converts the events to CSV, with a header row followed by one row per event.
+    ///
+    /// The columns are:
+    /// - `name`
+    /// - `start_date`, `end_date`: the local dates of the event, in ISO 8601 format.
+    /// - `start_time`, `end_time`: the local date and time with UTC offset in RFC 3339 format, or
+    ///   empty if the event doesn't have a time.
+    /// - `country`, `state`, `city`
+    /// - `styles`: dance style tags such as `contra`, separated by semicolons.
+    /// - `workshop`, `social`, `cancelled`: `true` or `false`.
+    /// - `bands`, `callers`: separated by semicolons.
+    /// - `price`, `organisation`
+    /// - `links`: separated by semicolons.
+    /// - `details`
+    ///
+    /// Items of a list which contain a semicolon or start with a double quote are wrapped in double
+    /// quotes, with any double quotes inside them doubled, like `"Band; Two"`.
+    ///
+    /// So that spreadsheets don't treat values as formulas, any text field starting with `=`, `+`,
+    /// `-`, `@`, a tab or a carriage return has a `'` added to the start. This is removed again by
+    /// [`Events::parse_csv_rows`].
+    pub fn to_csv_string(&self) -> Result<String, Report> {
+        let mut writer = csv::Writer::from_writer(vec![]);
+        for event in &self.events {
+            writer.serialize(CsvEvent::from(event))?;
+        }
+        Ok(String::from_utf8(writer.into_inner()?)?)
+    }
+
+    /// Parses events from CSV in the format produced by [`Events::to_csv_string`].
+    ///
+    /// Returns a result for each row, so that one invalid row doesn't prevent the rest from being
+    /// used. The events are not validated.
+    pub fn parse_csv_rows(csv: &str) -> Vec<Result<Event, Report>> {
+        csv::Reader::from_reader(csv.as_bytes())
+            .deserialize::<CsvRecord>()
+            .enumerate()
+            .map(|(i, record)| {
+                // Row 1 is the header.
+                let row = i + 2;
+                record
+                    .map_err(Report::from)
+                    .and_then(Event::try_from)
+                    .wrap_err_with(|| format!("Row {}", row))
+            })
+            .collect()
+    }
+
     /// Sorts events by start time then location.
     pub fn sort(&mut self) {
         self.events.sort_by_key(Event::date_location_sort_key);
@@ -185,6 +238,70 @@ impl Events {
         organisations
     }
 
+    /// Summarises all past and future events matching the given filters, such as all the events
+    /// for a particular band, or returns `None` if there are none.
+    pub fn appearances(&self, filters: &Filters) -> Option<Appearances> {
+        let filters = filters.with_date(DateFilter::All);
+        let mut events = self.matching(&filters);
+        events.sort_by_key(|event| event.time.start_time_sort_key());
+        let first_date = events
+            .iter()
+            .map(|event| event.local_date_range().0)
+            .min()?;
+        let last_date = events
+            .iter()
+            .map(|event| event.local_date_range().1)
+            .max()?;
+
+        let future_filters = filters.with_date(DateFilter::Future);
+        let now = Utc::now();
+        let (upcoming, mut past): (Vec<&Event>, Vec<&Event>) = events
+            .into_iter()
+            .partition(|event| future_filters.matches(event, now));
+        // Show the most recent past events first.
+        past.reverse();
+
+        Some(Appearances {
+            upcoming: upcoming.into_iter().cloned().collect(),
+            past: past.into_iter().cloned().collect(),
+            countries: self
+                .countries(&filters)
+                .into_iter()
+                .map(|country| country.name)
+                .collect(),
+            styles: self.styles(&filters),
+            first_date,
+            last_date,
+        })
+    }
+
+    /// Gets all source files which events were loaded from, in alphabetical order.
+    pub fn sources(&self) -> Vec<Source> {
+        let mut sources: HashMap<&str, Source> = HashMap::new();
+        for event in &self.events {
+            let Some(name) = &event.source else {
+                continue;
+            };
+            let (start_date, end_date) = event.local_date_range();
+            sources
+                .entry(name)
+                .and_modify(|source| {
+                    source.event_count += 1;
+                    source.first_date = source.first_date.min(start_date);
+                    source.last_date = source.last_date.max(end_date);
+                })
+                .or_insert_with(|| Source {
+                    name: name.to_owned(),
+                    event_count: 1,
+                    first_date: start_date,
+                    last_date: end_date,
+                });
+        }
+        let mut sources: Vec<Source> = sources.into_values().collect();
+        sources.sort();
+        sources
+    }
+
     /// Gets all cities which have dance events matching the given filters, grouped by country and
     /// possibly state, in alphabetical order.
     pub fn countries(&self, filters: &Filters) -> Vec<Country> {
@@ -303,12 +420,262 @@ pub struct Caller {
     pub event_count: usize,
 }
 
+/// A row of the CSV format for events. See `Events::to_csv_string` for details.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+struct CsvEvent<'a> {
+    name: Cow<'a, str>,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    start_time: Option<String>,
+    end_time: Option<String>,
+    country: Cow<'a, str>,
+    state: Option<Cow<'a, str>>,
+    city: Cow<'a, str>,
+    styles: String,
+    workshop: bool,
+    social: bool,
+    cancelled: bool,
+    bands: String,
+    callers: String,
+    price: Option<Cow<'a, str>>,
+    organisation: Option<Cow<'a, str>>,
+    links: String,
+    details: Option<Cow<'a, str>>,
+}
+
+impl<'a> From<&'a Event> for CsvEvent<'a> {
+    fn from(event: &'a Event) -> Self {
+        let (start_date, end_date, start_time, end_time) = match event.time {
+            EventTime::DateOnly {
+                start_date,
+                end_date,
+            } => (start_date, end_date, None, None),
+            EventTime::DateTime { start, end } => (
+                start.date_naive(),
+                end.date_naive(),
+                Some(start.to_rfc3339()),
+                Some(end.to_rfc3339()),
+            ),
+        };
+        Self {
+            name: escape_csv_formula(&event.name),
+            start_date,
+            end_date,
+            start_time,
+            end_time,
+            country: escape_csv_formula(&event.country),
+            state: event.state.as_deref().map(escape_csv_formula),
+            city: escape_csv_formula(&event.city),
+            styles: join_csv_list(event.styles.iter().map(|style| style.tag())),
+            workshop: event.workshop,
+            social: event.social,
+            cancelled: event.cancelled,
+            bands: join_csv_list(event.bands.iter().map(String::as_str)),
+            callers: join_csv_list(event.callers.iter().map(String::as_str)),
+            price: event.price.as_deref().map(escape_csv_formula),
+            organisation: event.organisation.as_deref().map(escape_csv_formula),
+            links: join_csv_list(event.links.iter().map(String::as_str)),
+            details: event.details.as_deref().map(escape_csv_formula),
+        }
+    }
+}
+
+/// A row read from CSV in the format described by `Events::to_csv_string`.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+struct CsvRecord {
+    name: String,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    start_time: Option<String>,
+    end_time: Option<String>,
+    country: String,
+    state: Option<String>,
+    city: String,
+    #[serde(default)]
+    styles: String,
+    #[serde(default)]
+    workshop: bool,
+    #[serde(default)]
+    social: bool,
+    #[serde(default)]
+    cancelled: bool,
+    #[serde(default)]
+    bands: String,
+    #[serde(default)]
+    callers: String,
+    #[serde(default)]
+    price: Option<String>,
+    #[serde(default)]
+    organisation: Option<String>,
+    #[serde(default)]
+    links: String,
+    #[serde(default)]
+    details: Option<String>,
+}
+
+impl TryFrom<CsvRecord> for Event {
+    type Error = Report;
+
+    fn try_from(record: CsvRecord) -> Result<Self, Self::Error> {
+        let time = match (record.start_time, record.end_time) {
+            (Some(start), Some(end)) => EventTime::DateTime {
+                start: DateTime::parse_from_rfc3339(&start)
+                    .wrap_err_with(|| format!("Invalid start time {:?}", start))?,
+                end: DateTime::parse_from_rfc3339(&end)
+                    .wrap_err_with(|| format!("Invalid end time {:?}", end))?,
+            },
+            (None, None) => EventTime::DateOnly {
+                start_date: record.start_date,
+                end_date: record.end_date,
+            },
+            _ => bail!("Must have both a start time and an end time, or neither."),
+        };
+        let styles = split_csv_list(&record.styles)
+            .into_iter()
+            .map(|tag| {
+                DanceStyle::values()
+                    .find(|style| style.tag() == tag)
+                    .ok_or_else(|| eyre!("Unknown dance style {:?}", tag))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            name: unescape_csv_formula(&record.name).trim().to_owned(),
+            details: record.details.as_deref().map(unescape_csv_text),
+            links: split_csv_list(&record.links),
+            time,
+            country: unescape_csv_formula(&record.country).trim().to_owned(),
+            state: record.state.as_deref().map(unescape_csv_text),
+            city: unescape_csv_formula(&record.city).trim().to_owned(),
+            styles,
+            workshop: record.workshop,
+            social: record.social,
+            bands: split_csv_list(&record.bands),
+            callers: split_csv_list(&record.callers),
+            price: record.price.as_deref().map(unescape_csv_text),
+            organisation: record.organisation.as_deref().map(unescape_csv_text),
+            cancelled: record.cancelled,
+            source: None,
+        })
+    }
+}
+
+/// Characters which spreadsheets may treat as the start of a formula if a cell starts with them.
+const CSV_FORMULA_PREFIXES: [char; 6] = ['=', '+', '-', '@', '\t', '\r'];
+
+/// Adds a `'` to the start of the given CSV field if a spreadsheet might otherwise treat it as a
+/// formula.
+fn escape_csv_formula(field: &str) -> Cow<'_, str> {
+    if field.starts_with(CSV_FORMULA_PREFIXES) {
+        Cow::Owned(format!("'{}", field))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+/// Removes a `'` added to the start of the given CSV field by [`escape_csv_formula`].
+fn unescape_csv_formula(field: &str) -> &str {
+    match field.strip_prefix('\'') {
+        Some(rest) if rest.starts_with(CSV_FORMULA_PREFIXES) => rest,
+        _ => field,
+    }
+}
+
+/// Removes any formula escaping from the given optional CSV text field.
+fn unescape_csv_text(field: &str) -> String {
+    unescape_csv_formula(field).to_owned()
+}
+
+/// Joins the given items into a single CSV field, quoting any which would otherwise be ambiguous.
+fn join_csv_list<'a>(items: impl IntoIterator<Item = &'a str>) -> String {
+    let list = items
+        .into_iter()
+        .map(|item| {
+            if item.contains(';') || item.starts_with('"') {
+                Cow::Owned(format!("\"{}\"", item.replace('"', "\"\"")))
+            } else {
+                Cow::Borrowed(item)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(CSV_LIST_SEPARATOR);
+    escape_csv_formula(&list).into_owned()
+}
+
+/// Splits a CSV field containing a list of items in the format produced by [`join_csv_list`],
+/// ignoring empty items.
+fn split_csv_list(field: &str) -> Vec<String> {
+    let mut items = vec![];
+    let mut chars = unescape_csv_formula(field).chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let mut item = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            while let Some(c) = chars.next() {
+                if c != '"' {
+                    item.push(c);
+                } else if chars.next_if_eq(&'"').is_some() {
+                    // A doubled quote is a literal quote.
+                    item.push('"');
+                } else {
+                    break;
+                }
+            }
+            // Ignore anything between the closing quote and the next separator.
+            while chars.next_if(|&c| c != ';').is_some() {}
+        } else {
+            while let Some(c) = chars.next_if(|&c| c != ';') {
+                item.push(c);
+            }
+        }
+        let item = item.trim();
+        if !item.is_empty() {
+            items.push(item.to_owned());
+        }
+        // Skip the separator, or stop if there isn't one.
+        if chars.next().is_none() {
+            break;
+        }
+    }
+    items
+}
+
+/// The separator used between items of a list in a single CSV field.
+const CSV_LIST_SEPARATOR: &str = "; ";
+
 #[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub struct Organisation {
     pub name: String,
     pub event_count: usize,
 }
 
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct Source {
+    /// The filename from which the events were loaded.
+    pub name: String,
+    pub event_count: usize,
+    /// The date of the first day of the earliest event.
+    pub first_date: NaiveDate,
+    /// The date of the last day of the latest event.
+    pub last_date: NaiveDate,
+}
+
+/// A summary of all the events matching some filters. See `Events::appearances`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Appearances {
+    /// Events which haven't finished yet, in order of start time.
+    pub upcoming: Vec<Event>,
+    /// Events which have finished, most recent first.
+    pub past: Vec<Event>,
+    /// The countries in which the events are, in alphabetical order.
+    pub countries: Vec<String>,
+    /// The dance styles of the events, in order.
+    pub styles: Vec<DanceStyle>,
+    /// The date of the first day of the earliest event.
+    pub first_date: NaiveDate,
+    /// The date of the last day of the latest event.
+    pub last_date: NaiveDate,
+}
+
 /// Counts the number of occurrences of duplicate items in the iterator.
 fn count_duplicates(elements: impl Iterator<Item = String>) -> HashMap<String, usize> {
     let mut counts = HashMap::new();
@@ -322,8 +689,7 @@ fn count_duplicates(elements: impl Iterator<Item = String>) -> HashMap<String, u
 mod tests {
     use super::*;
 
-    use crate::model::{dancestyle::DanceStyle, event::EventTime, filters::DateFilter};
-    use chrono::NaiveDate;
+    use chrono::{FixedOffset, TimeZone};
 
     #[test]
     fn countries() {
@@ -625,4 +991,261 @@ mod tests {
             vec![&past_event, &future_event]
         );
     }
+
+    #[test]
+    fn sources() {
+        let event = Event {
+            name: "Name".to_string(),
+            time: EventTime::DateOnly {
+                start_date: NaiveDate::from_ymd_opt(2022, 1, 1).unwrap(),
+                end_date: NaiveDate::from_ymd_opt(2022, 1, 1).unwrap(),
+            },
+            details: None,
+            links: vec![],
+            country: "Test".to_string(),
+            state: None,
+            city: "Test".to_string(),
+            styles: vec![DanceStyle::EnglishCountryDance],
+            workshop: true,
+            social: false,
+            bands: vec![],
+            callers: vec![],
+            price: None,
+            organisation: None,
+            cancelled: false,
+            source: Some("b.yaml".to_string()),
+        };
+        let events = Events {
+            events: vec![
+                event.clone(),
+                Event {
+                    time: EventTime::DateOnly {
+                        start_date: NaiveDate::from_ymd_opt(2022, 3, 1).unwrap(),
+                        end_date: NaiveDate::from_ymd_opt(2022, 3, 2).unwrap(),
+                    },
+                    ..event.clone()
+                },
+                Event {
+                    source: Some("a.yaml".to_string()),
+                    ..event.clone()
+                },
+                Event {
+                    source: None,
+                    ..event.clone()
+                },
+            ],
+        };
+
+        assert_eq!(
+            events.sources(),
+            vec![
+                Source {
+                    name: "a.yaml".to_string(),
+                    event_count: 1,
+                    first_date: NaiveDate::from_ymd_opt(2022, 1, 1).unwrap(),
+                    last_date: NaiveDate::from_ymd_opt(2022, 1, 1).unwrap(),
+                },
+                Source {
+                    name: "b.yaml".to_string(),
+                    event_count: 2,
+                    first_date: NaiveDate::from_ymd_opt(2022, 1, 1).unwrap(),
+                    last_date: NaiveDate::from_ymd_opt(2022, 3, 2).unwrap(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn appearances() {
+        let event = Event {
+            name: "Past".to_string(),
+            time: EventTime::DateOnly {
+                start_date: NaiveDate::from_ymd_opt(2022, 1, 1).unwrap(),
+                end_date: NaiveDate::from_ymd_opt(2022, 1, 1).unwrap(),
+            },
+            details: None,
+            links: vec![],
+            country: "UK".to_string(),
+            state: None,
+            city: "London".to_string(),
+            styles: vec![DanceStyle::Contra],
+            workshop: false,
+            social: true,
+            bands: vec!["Band".to_string()],
+            callers: vec![],
+            price: None,
+            organisation: None,
+            cancelled: false,
+            source: None,
+        };
+        let earlier = Event {
+            name: "Earlier".to_string(),
+            time: EventTime::DateOnly {
+                start_date: NaiveDate::from_ymd_opt(2021, 6, 1).unwrap(),
+                end_date: NaiveDate::from_ymd_opt(2021, 6, 1).unwrap(),
+            },
+            country: "Belgium".to_string(),
+            styles: vec![DanceStyle::Balfolk],
+            ..event.clone()
+        };
+        let future = Event {
+            name: "Future".to_string(),
+            time: EventTime::DateOnly {
+                start_date: NaiveDate::from_ymd_opt(2100, 1, 1).unwrap(),
+                end_date: NaiveDate::from_ymd_opt(2100, 1, 2).unwrap(),
+            },
+            ..event.clone()
+        };
+        let other_band = Event {
+            bands: vec!["Other band".to_string()],
+            ..event.clone()
+        };
+        let events = Events {
+            events: vec![future.clone(), event.clone(), other_band, earlier.clone()],
+        };
+        let filters = Filters {
+            bands: vec!["Band".to_string()],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            events.appearances(&filters),
+            Some(Appearances {
+                upcoming: vec![future],
+                past: vec![event, earlier],
+                countries: vec!["Belgium".to_string(), "UK".to_string()],
+                styles: vec![DanceStyle::Balfolk, DanceStyle::Contra],
+                first_date: NaiveDate::from_ymd_opt(2021, 6, 1).unwrap(),
+                last_date: NaiveDate::from_ymd_opt(2100, 1, 2).unwrap(),
+            })
+        );
+        assert_eq!(
+            events.appearances(&Filters {
+                bands: vec!["Nobody".to_string()],
+                ..Default::default()
+            }),
+            None
+        );
+    }
+
+    #[test]
+    fn csv() {
+        let offset = FixedOffset::east_opt(3600).unwrap();
+        let events = Events {
+            events: vec![
+                Event {
+                    name: "Dance, with comma".to_string(),
+                    time: EventTime::DateTime {
+                        start: offset.with_ymd_and_hms(2024, 1, 5, 19, 0, 0).unwrap(),
+                        end: offset.with_ymd_and_hms(2024, 1, 5, 23, 0, 0).unwrap(),
+                    },
+                    details: None,
+                    links: vec!["https://example.com/".to_string()],
+                    country: "Belgium".to_string(),
+                    state: None,
+                    city: "Gent".to_string(),
+                    styles: vec![DanceStyle::Balfolk, DanceStyle::Contra],
+                    workshop: true,
+                    social: true,
+                    bands: vec!["Band 1".to_string(), "Band 2".to_string()],
+                    callers: vec![],
+                    price: Some("€10".to_string()),
+                    organisation: None,
+                    cancelled: false,
+                    source: None,
+                },
+                Event {
+                    name: "Weekend".to_string(),
+                    time: EventTime::DateOnly {
+                        start_date: NaiveDate::from_ymd_opt(2024, 2, 2).unwrap(),
+                        end_date: NaiveDate::from_ymd_opt(2024, 2, 4).unwrap(),
+                    },
+                    details: Some("Details".to_string()),
+                    links: vec![],
+                    country: "USA".to_string(),
+                    state: Some("MA".to_string()),
+                    city: "Boston".to_string(),
+                    styles: vec![DanceStyle::Contra],
+                    workshop: false,
+                    social: true,
+                    bands: vec![],
+                    callers: vec!["Caller".to_string()],
+                    price: None,
+                    organisation: Some("Org".to_string()),
+                    cancelled: true,
+                    source: None,
+                },
+            ],
+        };
+
+        assert_eq!(
+            events.to_csv_string().unwrap(),
+            "name,start_date,end_date,start_time,end_time,country,state,city,styles,workshop,social,cancelled,bands,callers,price,organisation,links,details\n\
+            \"Dance, with comma\",2024-01-05,2024-01-05,2024-01-05T19:00:00+01:00,2024-01-05T23:00:00+01:00,Belgium,,Gent,balfolk; contra,true,true,false,Band 1; Band 2,,€10,,https://example.com/,\n\
+            Weekend,2024-02-02,2024-02-04,,,USA,MA,Boston,contra,false,true,true,,Caller,,Org,,Details\n"
+        );
+
+        // Parsing the CSV should give back the same events.
+        let parsed = Events::parse_csv_rows(&events.to_csv_string().unwrap())
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(parsed, events.events);
+    }
+
+    #[test]
+    fn csv_escaping() {
+        let events = Events {
+            events: vec![Event {
+                name: "=HYPERLINK(\"https://example.com/\")".to_string(),
+                time: EventTime::DateOnly {
+                    start_date: NaiveDate::from_ymd_opt(2024, 2, 2).unwrap(),
+                    end_date: NaiveDate::from_ymd_opt(2024, 2, 2).unwrap(),
+                },
+                details: Some("-details".to_string()),
+                links: vec![],
+                country: "UK".to_string(),
+                state: None,
+                city: "London".to_string(),
+                styles: vec![DanceStyle::Contra],
+                workshop: false,
+                social: true,
+                bands: vec!["@Band".to_string(), "Band; Two".to_string()],
+                callers: vec!["\"Caller\"".to_string()],
+                price: Some("+5".to_string()),
+                organisation: None,
+                cancelled: false,
+                source: None,
+            }],
+        };
+
+        let csv = events.to_csv_string().unwrap();
+        assert_eq!(
+            csv,
+            "name,start_date,end_date,start_time,end_time,country,state,city,styles,workshop,social,cancelled,bands,callers,price,organisation,links,details\n\
+            \"'=HYPERLINK(\"\"https://example.com/\"\")\",2024-02-02,2024-02-02,,,UK,,London,contra,false,true,false,\"'@Band; \"\"Band; Two\"\"\",\"\"\"\"\"\"\"Caller\"\"\"\"\"\"\",'+5,,,'-details\n"
+        );
+
+        // Parsing the CSV should give back the same events.
+        let parsed = Events::parse_csv_rows(&csv)
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(parsed, events.events);
+    }
+
+    #[test]
+    fn csv_invalid_rows() {
+        let rows = Events::parse_csv_rows(
+            "name,start_date,end_date,start_time,end_time,country,state,city,styles\n\
+            Dance,2024-01-05,2024-01-05,,,UK,,London,contra\n\
+            Bad style,2024-01-05,2024-01-05,,,UK,,London,tango\n\
+            Bad date,2024-13-05,2024-01-05,,,UK,,London,contra\n",
+        );
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].as_ref().unwrap().name, "Dance");
+        assert_eq!(rows[0].as_ref().unwrap().styles, vec![DanceStyle::Contra]);
+        assert!(rows[1].is_err());
+        assert!(rows[2].as_ref().unwrap_err().to_string().contains("Row 4"));
+    }
 }
@@ -16,23 +16,54 @@ use super::{
     dancestyle::DanceStyle,
     event::{Event, EventTime},
 };
-use chrono::{DateTime, Utc};
+use crate::geo::{city_coordinates, named_city_coordinates, Coordinates};
+use chrono::{DateTime, Datelike, NaiveTime, Utc, Weekday};
 use enum_iterator::{all, Sequence};
 use eyre::Report;
-use serde::{de::IntoDeserializer, Deserialize, Deserializer, Serialize, Serializer};
+use glob::Pattern;
+use serde::{
+    de::{Error, IntoDeserializer},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
 use std::{
-    cmp::Ordering,
     collections::HashSet,
     fmt::{self, Display, Formatter},
+    slice,
 };
 
-#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+/// A set of filters to choose which events to show.
+///
+/// Most filters may be given several times in the query string to match any of the given values,
+/// and have a corresponding `not_` filter to exclude events with any of the given values.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct Filters {
     #[serde(default, skip_serializing_if = "is_default")]
     pub date: DateFilter,
-    pub country: Option<String>,
-    pub state: Option<String>,
-    pub city: Option<String>,
+    #[serde(rename = "country", default, skip_serializing_if = "Vec::is_empty")]
+    pub countries: Vec<String>,
+    #[serde(rename = "not_country", default, skip_serializing_if = "Vec::is_empty")]
+    pub not_countries: Vec<String>,
+    #[serde(rename = "state", default, skip_serializing_if = "Vec::is_empty")]
+    pub states: Vec<String>,
+    #[serde(rename = "not_state", default, skip_serializing_if = "Vec::is_empty")]
+    pub not_states: Vec<String>,
+    #[serde(rename = "city", default, skip_serializing_if = "Vec::is_empty")]
+    pub cities: Vec<String>,
+    #[serde(rename = "not_city", default, skip_serializing_if = "Vec::is_empty")]
+    pub not_cities: Vec<String>,
+    /// Only include events in cities within `radius` km of the given coordinates.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "coordinates_ser",
+        deserialize_with = "coordinates_de"
+    )]
+    pub near: Option<Coordinates>,
+    /// Only include events in cities within `radius` km of the given city.
+    #[serde(default, deserialize_with = "near_city_de")]
+    pub near_city: Option<String>,
+    /// The radius in km for `near` or `near_city`, or [`DEFAULT_RADIUS_KM`] if not specified.
+    pub radius: Option<u32>,
     #[serde(
         alias = "style", // For backwards compatibility with old URLs.
         default,
@@ -41,13 +72,133 @@ pub struct Filters {
         deserialize_with = "styles_de"
     )]
     pub styles: HashSet<DanceStyle>,
+    #[serde(
+        default,
+        skip_serializing_if = "is_default",
+        serialize_with = "styles_ser",
+        deserialize_with = "styles_de"
+    )]
+    pub not_styles: HashSet<DanceStyle>,
+    /// Only include events on any of the given days of the week, in the event's local time.
+    #[serde(
+        default,
+        skip_serializing_if = "is_default",
+        serialize_with = "weekdays_ser",
+        deserialize_with = "weekdays_de"
+    )]
+    pub weekdays: HashSet<Weekday>,
+    /// Only include events starting at or after the given local time.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "time_ser",
+        deserialize_with = "time_de"
+    )]
+    pub start_after: Option<NaiveTime>,
+    /// Only include events starting before the given local time.
+    ///
+    /// If this is earlier than `start_after` then the window is taken to wrap around midnight.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "time_ser",
+        deserialize_with = "time_de"
+    )]
+    pub start_before: Option<NaiveTime>,
     pub multiday: Option<bool>,
     pub workshop: Option<bool>,
     pub social: Option<bool>,
-    pub band: Option<String>,
-    pub caller: Option<String>,
-    pub organisation: Option<String>,
+    #[serde(rename = "band", default, skip_serializing_if = "Vec::is_empty")]
+    pub bands: Vec<String>,
+    #[serde(rename = "not_band", default, skip_serializing_if = "Vec::is_empty")]
+    pub not_bands: Vec<String>,
+    #[serde(rename = "caller", default, skip_serializing_if = "Vec::is_empty")]
+    pub callers: Vec<String>,
+    #[serde(rename = "not_caller", default, skip_serializing_if = "Vec::is_empty")]
+    pub not_callers: Vec<String>,
+    #[serde(
+        rename = "organisation",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub organisations: Vec<String>,
+    #[serde(
+        rename = "not_organisation",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub not_organisations: Vec<String>,
     pub cancelled: Option<bool>,
+    /// A glob pattern or prefix to match against the source file of the event.
+    pub source: Option<String>,
+}
+
+/// The radius to use for a distance filter if none is specified.
+pub const DEFAULT_RADIUS_KM: u32 = 50;
+
+fn coordinates_ser<S: Serializer>(
+    coordinates: &Option<Coordinates>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match coordinates {
+        Some(coordinates) => serializer.serialize_str(&coordinates.to_string()),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn coordinates_de<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Coordinates>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|string| string.parse().map_err(D::Error::custom))
+        .transpose()
+}
+
+/// Deserialises the name of a city for the distance filter, checking that its location is known so
+/// that a misspelled or ambiguous city gives an error rather than silently matching nothing or the
+/// wrong place.
+fn near_city_de<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    let near_city = Option::<String>::deserialize(deserializer)?;
+    if let Some(near_city) = &near_city {
+        named_city_coordinates(near_city)
+            .map_err(|e| D::Error::custom(format!("{} for near_city", e)))?;
+    }
+    Ok(near_city)
+}
+
+fn weekdays_ser<S: Serializer>(
+    weekdays: &HashSet<Weekday>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut weekdays: Vec<_> = weekdays.iter().collect();
+    // Sort so as to maintain a consistent serialisation.
+    weekdays.sort_by_key(|weekday| weekday.num_days_from_monday());
+    let weekday_tags: Vec<_> = weekdays
+        .into_iter()
+        .map(|weekday| weekday.to_string().to_lowercase())
+        .collect();
+    serializer.serialize_str(&weekday_tags.join(","))
+}
+
+fn weekdays_de<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HashSet<Weekday>, D::Error> {
+    let string = String::deserialize(deserializer)?;
+    string
+        .split(',')
+        .map(|weekday| weekday.parse().map_err(D::Error::custom))
+        .collect()
+}
+
+fn time_ser<S: Serializer>(time: &Option<NaiveTime>, serializer: S) -> Result<S::Ok, S::Error> {
+    match time {
+        Some(time) => serializer.serialize_str(&time.format("%H:%M").to_string()),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn time_de<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<NaiveTime>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|string| string.parse().map_err(D::Error::custom))
+        .transpose()
 }
 
 fn styles_ser<S: Serializer>(
@@ -68,12 +219,13 @@ fn styles_de<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HashSet<Dance
         .collect()
 }
 
-#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Sequence, Serialize)]
+#[derive(Copy, Clone, Debug, Default, Deserialize, Eq, PartialEq, Sequence, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum DateFilter {
     /// Include only events which started before the current day.
     Past,
     /// Include only events which finish on or after the current day.
+    #[default]
     Future,
     /// Include all events, past and future.
     All,
@@ -85,12 +237,6 @@ impl DateFilter {
     }
 }
 
-impl Default for DateFilter {
-    fn default() -> Self {
-        Self::Future
-    }
-}
-
 impl Display for DateFilter {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         let s = match self {
@@ -111,21 +257,58 @@ impl Filters {
     }
 
     pub fn has_some(&self) -> bool {
-        self.country.is_some()
-            || self.state.is_some()
-            || self.city.is_some()
+        !self.countries.is_empty()
+            || !self.not_countries.is_empty()
+            || !self.states.is_empty()
+            || !self.not_states.is_empty()
+            || !self.cities.is_empty()
+            || !self.not_cities.is_empty()
+            || self.has_distance()
             || !self.styles.is_empty()
+            || !self.not_styles.is_empty()
+            || !self.weekdays.is_empty()
+            || self.start_after.is_some()
+            || self.start_before.is_some()
             || self.multiday.is_some()
             || self.workshop.is_some()
             || self.social.is_some()
-            || self.band.is_some()
-            || self.caller.is_some()
-            || self.organisation.is_some()
+            || !self.bands.is_empty()
+            || !self.not_bands.is_empty()
+            || !self.callers.is_empty()
+            || !self.not_callers.is_empty()
+            || !self.organisations.is_empty()
+            || !self.not_organisations.is_empty()
             || self.cancelled.is_some()
+            || self.source.is_some()
+    }
+
+    /// Returns whether there is a filter on distance from some point.
+    pub fn has_distance(&self) -> bool {
+        self.near.is_some() || self.near_city.is_some()
+    }
+
+    /// Returns the centre point of the distance filter, if there is one and it is known.
+    pub fn near_coordinates(&self) -> Option<Coordinates> {
+        self.near
+            .or_else(|| named_city_coordinates(self.near_city.as_deref()?).ok())
+    }
+
+    /// Returns the radius of the distance filter in km.
+    pub fn radius_km(&self) -> u32 {
+        self.radius.unwrap_or(DEFAULT_RADIUS_KM)
+    }
+
+    /// Returns the distance in km from the centre of the distance filter to the city of the given
+    /// event, if there is a distance filter and both locations are known.
+    pub fn distance_km(&self, event: &Event) -> Option<f64> {
+        let centre = self.near_coordinates()?;
+        let event_coordinates =
+            city_coordinates(&event.country, event.state.as_deref(), &event.city)?;
+        Some(centre.distance_km(event_coordinates))
     }
 
     pub fn to_query_string(&self) -> Result<String, Report> {
-        Ok(serde_urlencoded::to_string(self)?)
+        Ok(serde_html_form::to_string(self)?)
     }
 
     pub fn matches(&self, event: &Event, now: DateTime<Utc>) -> bool {
@@ -146,25 +329,39 @@ impl Filters {
             },
         }
 
-        if let Some(country) = &self.country {
-            if &event.country != country {
-                return false;
-            }
-        }
-        if let Some(state) = &self.state {
-            if event.state.as_deref().unwrap_or_default() != state {
-                return false;
-            }
+        if !matches_values(
+            slice::from_ref(&event.country),
+            &self.countries,
+            &self.not_countries,
+        ) || !matches_values(event.state.as_slice(), &self.states, &self.not_states)
+            || !matches_values(slice::from_ref(&event.city), &self.cities, &self.not_cities)
+        {
+            return false;
         }
-        if let Some(city) = &self.city {
-            if &event.city != city {
-                return false;
+        if self.has_distance() {
+            // Events whose location isn't known can't be shown to be nearby.
+            match self.distance_km(event) {
+                Some(distance) if distance <= f64::from(self.radius_km()) => {}
+                _ => return false,
             }
         }
         if !self.styles.is_empty() && !event.styles.iter().any(|style| self.styles.contains(style))
         {
             return false;
         }
+        if event
+            .styles
+            .iter()
+            .any(|style| self.not_styles.contains(style))
+        {
+            return false;
+        }
+        if !self.weekdays.is_empty() && !self.matches_weekday(event) {
+            return false;
+        }
+        if !self.matches_start_time(event) {
+            return false;
+        }
         if let Some(multiday) = self.multiday {
             if event.multiday() != multiday {
                 return false;
@@ -180,23 +377,27 @@ impl Filters {
                 return false;
             }
         }
-        if let Some(band) = &self.band {
-            if !event.bands.contains(band) {
-                return false;
-            }
-        }
-        if let Some(caller) = &self.caller {
-            if !event.callers.contains(caller) {
-                return false;
-            }
+        if !matches_values(&event.bands, &self.bands, &self.not_bands)
+            || !matches_values(&event.callers, &self.callers, &self.not_callers)
+            || !matches_values(
+                event.organisation.as_slice(),
+                &self.organisations,
+                &self.not_organisations,
+            )
+        {
+            return false;
         }
-        if let Some(organisation) = &self.organisation {
-            if event.organisation.as_deref().unwrap_or_default() != organisation {
+        if let Some(cancelled) = self.cancelled {
+            if event.cancelled != cancelled {
                 return false;
             }
         }
-        if let Some(cancelled) = self.cancelled {
-            if event.cancelled != cancelled {
+        if let Some(source) = &self.source {
+            if !event
+                .source
+                .as_deref()
+                .is_some_and(|event_source| matches_source(source, event_source))
+            {
                 return false;
             }
         }
@@ -204,61 +405,171 @@ impl Filters {
         true
     }
 
+    /// Checks whether any day of the event is on one of the filtered weekdays.
+    fn matches_weekday(&self, event: &Event) -> bool {
+        let (start_date, end_date) = event.local_date_range();
+        start_date
+            .iter_days()
+            .take_while(|date| *date <= end_date)
+            // After a week every weekday has been covered.
+            .take(7)
+            .any(|date| self.weekdays.contains(&date.weekday()))
+    }
+
+    /// Checks whether the event starts within the filtered time window.
+    ///
+    /// Events without a time cover whole days, so are always included.
+    fn matches_start_time(&self, event: &Event) -> bool {
+        let EventTime::DateTime { start, .. } = event.time else {
+            return true;
+        };
+        let start_time = start.time();
+        match (self.start_after, self.start_before) {
+            (None, None) => true,
+            (Some(after), None) => start_time >= after,
+            (None, Some(before)) => start_time < before,
+            (Some(after), Some(before)) if after <= before => {
+                start_time >= after && start_time < before
+            }
+            // The window wraps around midnight.
+            (Some(after), Some(before)) => start_time >= after || start_time < before,
+        }
+    }
+
     /// Make a page title for this set of filters.
     pub fn make_title(&self) -> String {
         let style = if self.styles.is_empty() {
             "Folk dance".to_string()
         } else {
-            let mut style_string = String::new();
             let mut styles: Vec<_> = self.styles.iter().collect();
             // Sort to ensure a consistent title.
             styles.sort();
-            for (i, style) in styles.iter().enumerate() {
-                style_string += &uppercase_first_letter(style.name());
-                match self.styles.len().cmp(&(i + 2)) {
-                    Ordering::Greater => {
-                        style_string += " , ";
-                    }
-                    Ordering::Equal => {
-                        style_string += " and ";
-                    }
-                    Ordering::Less => {}
-                }
-            }
-            style_string
+            join_names(
+                styles
+                    .into_iter()
+                    .map(|style| uppercase_first_letter(style.name())),
+            )
         };
 
-        match (&self.country, &self.state, &self.city) {
-            (None, None, None) => format!("{} events", style),
-            (Some(country), None, None) => {
+        let countries = if self.states.is_empty() && self.cities.is_empty() {
+            // Countries like "the UK" need an article when they are used on their own.
+            join_names(self.countries.iter().map(|country| {
                 if country == "UK" || country == "USA" {
-                    format!("{} events in the {}", style, country)
+                    format!("the {}", country)
                 } else {
-                    format!("{} events in {}", style, country)
+                    country.to_owned()
                 }
+            }))
+        } else {
+            join_names(self.countries.iter().cloned())
+        };
+        let location = [
+            join_names(self.cities.iter().cloned()),
+            join_names(self.states.iter().cloned()),
+            countries,
+        ]
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+        let mut title = if location.is_empty() {
+            format!("{} events", style)
+        } else {
+            format!("{} events in {}", style, location)
+        };
+        if !self.weekdays.is_empty() {
+            let mut weekdays: Vec<_> = self.weekdays.iter().collect();
+            weekdays.sort_by_key(|weekday| weekday.num_days_from_monday());
+            title += &format!(
+                " on {}",
+                join_names(
+                    weekdays
+                        .into_iter()
+                        .map(|weekday| format!("{}s", weekday_name(*weekday)))
+                )
+            );
+        }
+        match (self.start_after, self.start_before) {
+            (None, None) => {}
+            (Some(after), None) => title += &format!(" starting from {}", after.format("%H:%M")),
+            (None, Some(before)) => {
+                title += &format!(" starting before {}", before.format("%H:%M"))
             }
-            (None, None, Some(city)) => format!("{} events in {}", style, city),
-            (None, Some(state), None) => format!("{} events in {}", style, state),
-            (None, Some(state), Some(city)) => format!("{} events in {}, {}", style, city, state),
-            (Some(country), None, Some(city)) => {
-                format!("{} events in {}, {}", style, city, country)
-            }
-            (Some(country), Some(state), None) => {
-                format!("{} events in {}, {}", style, state, country)
+            (Some(after), Some(before)) => {
+                title += &format!(
+                    " starting between {} and {}",
+                    after.format("%H:%M"),
+                    before.format("%H:%M")
+                )
             }
-            (Some(country), Some(state), Some(city)) => {
-                format!("{} events in {}, {}, {}", style, city, state, country)
+        }
+        if let Some(near_description) = self.near_description() {
+            title += &format!(" within {} km of {}", self.radius_km(), near_description);
+        }
+        if let Some(source) = &self.source {
+            title += &format!(" from {}", source);
+        }
+        title
+    }
+
+    /// Describes the centre of the distance filter, if there is one.
+    pub fn near_description(&self) -> Option<String> {
+        if let Some(near) = self.near {
+            Some(near.to_string())
+        } else {
+            self.near_city.clone()
+        }
+    }
+
+    /// Parses filters from the given query string, taking any filter whose key isn't present in it
+    /// from the given defaults instead.
+    ///
+    /// A key which is present with an empty value, like `country=`, overrides the default without
+    /// setting any filter of its own.
+    pub fn from_query_string_with_defaults(
+        query: &str,
+        defaults: &Filters,
+    ) -> Result<Self, Report> {
+        let pairs = query_pairs(query)?;
+        let keys: HashSet<_> = pairs.iter().map(|(key, _)| canonical_key(key)).collect();
+        let merged: Vec<_> = query_pairs(&defaults.to_query_string()?)?
+            .into_iter()
+            .filter(|(key, _)| !keys.contains(canonical_key(key)))
+            .chain(pairs.iter().cloned())
+            .filter(|(_, value)| !value.is_empty())
+            .collect();
+        Ok(serde_html_form::from_str(&serde_html_form::to_string(
+            &merged,
+        )?)?)
+    }
+
+    /// Returns a query string for these filters which gives the same filters when parsed with
+    /// [`Filters::from_query_string_with_defaults`] with the given defaults.
+    ///
+    /// Any key which the defaults set but these filters don't is included with an empty value, so
+    /// that the default isn't applied.
+    pub fn to_query_string_with_defaults(&self, defaults: &Filters) -> Result<String, Report> {
+        let mut pairs = query_pairs(&self.to_query_string()?)?;
+        let mut keys: HashSet<_> = pairs
+            .iter()
+            .map(|(key, _)| canonical_key(key).to_owned())
+            .collect();
+        for (key, _) in query_pairs(&defaults.to_query_string()?)? {
+            if keys.insert(canonical_key(&key).to_owned()) {
+                pairs.push((key, String::new()));
             }
         }
+        Ok(serde_html_form::to_string(&pairs)?)
     }
 
     /// Makes a new set of filters like this one but with the given country filter and no state or
     /// city filter.
     pub fn with_country(&self, country: Option<&str>) -> Self {
         Self {
-            country: owned(country),
-            state: None,
-            city: None,
+            countries: owned(country).into_iter().collect(),
+            states: vec![],
+            cities: vec![],
             ..self.clone()
         }
     }
@@ -266,8 +577,8 @@ impl Filters {
     /// Makes a new set of filters like this one but with the given state filter and no city filter.
     pub fn with_state(&self, state: Option<&str>) -> Self {
         Self {
-            state: owned(state),
-            city: None,
+            states: owned(state).into_iter().collect(),
+            cities: vec![],
             ..self.clone()
         }
     }
@@ -275,7 +586,25 @@ impl Filters {
     /// Makes a new set of filters like this one but with the given city filter.
     pub fn with_city(&self, city: Option<&str>) -> Self {
         Self {
-            city: owned(city),
+            cities: owned(city).into_iter().collect(),
+            ..self.clone()
+        }
+    }
+
+    /// Makes a new set of filters like this one but with no distance filter.
+    pub fn without_distance(&self) -> Self {
+        Self {
+            near: None,
+            near_city: None,
+            radius: None,
+            ..self.clone()
+        }
+    }
+
+    /// Makes a new set of filters like this one but with the given weekday filter.
+    pub fn with_weekday(&self, weekday: Option<Weekday>) -> Self {
+        Self {
+            weekdays: weekday.into_iter().collect(),
             ..self.clone()
         }
     }
@@ -321,6 +650,59 @@ impl Filters {
     }
 }
 
+/// Checks whether any of the given values are included, and none of them are excluded.
+///
+/// An empty list of included values includes everything.
+fn matches_values(values: &[String], included: &[String], excluded: &[String]) -> bool {
+    (included.is_empty() || values.iter().any(|value| included.contains(value)))
+        && !values.iter().any(|value| excluded.contains(value))
+}
+
+/// Checks whether the given source filename matches the given pattern.
+///
+/// The pattern is treated as a glob if it contains any wildcards, or else as a prefix.
+fn matches_source(pattern: &str, source: &str) -> bool {
+    if pattern.contains(['*', '?', '[']) {
+        Pattern::new(pattern).is_ok_and(|pattern| pattern.matches(source))
+    } else {
+        source.starts_with(pattern)
+    }
+}
+
+/// Joins the given names into an English list, like "A, B and C".
+fn join_names(names: impl IntoIterator<Item = String>) -> String {
+    let names: Vec<_> = names.into_iter().collect();
+    match names.split_last() {
+        None => String::new(),
+        Some((last, [])) => last.to_owned(),
+        Some((last, rest)) => format!("{} and {}", rest.join(", "), last),
+    }
+}
+
+/// All days of the week, starting from Monday.
+pub const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+/// Returns the full English name of the given day of the week.
+pub fn weekday_name(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "Monday",
+        Weekday::Tue => "Tuesday",
+        Weekday::Wed => "Wednesday",
+        Weekday::Thu => "Thursday",
+        Weekday::Fri => "Friday",
+        Weekday::Sat => "Saturday",
+        Weekday::Sun => "Sunday",
+    }
+}
+
 /// Make the first letter of the given string uppercase.
 fn uppercase_first_letter(s: &str) -> String {
     let mut chars = s.chars();
@@ -339,9 +721,23 @@ fn is_default<T: Default + PartialEq>(value: &T) -> bool {
     value == &T::default()
 }
 
+/// Splits the given query string into its keys and values, in order.
+fn query_pairs(query: &str) -> Result<Vec<(String, String)>, Report> {
+    Ok(serde_html_form::from_str(query)?)
+}
+
+/// Returns the name of the field for the given query string key, taking aliases into account.
+fn canonical_key(key: &str) -> &str {
+    match key {
+        "style" => "styles",
+        _ => key,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::{FixedOffset, NaiveDate, TimeZone};
 
     #[test]
     fn empty_filters_title() {
@@ -353,7 +749,7 @@ mod tests {
     fn one_style_country_title() {
         let filters = Filters {
             styles: [DanceStyle::EnglishCountryDance].into_iter().collect(),
-            country: Some("New Zealand".to_string()),
+            countries: vec!["New Zealand".to_string()],
             ..Default::default()
         };
         assert_eq!(filters.make_title(), "ECD events in New Zealand");
@@ -407,7 +803,7 @@ mod tests {
     fn deserialize_styles_filters() {
         let query_string = "styles=balfolk%2Ccontra%2Ce-ceilidh";
         assert_eq!(
-            serde_urlencoded::from_str::<Filters>(query_string).unwrap(),
+            serde_html_form::from_str::<Filters>(query_string).unwrap(),
             Filters {
                 styles: [
                     DanceStyle::Balfolk,
@@ -420,4 +816,370 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn two_country_title() {
+        let filters = Filters {
+            countries: vec!["UK".to_string(), "Ireland".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            filters.make_title(),
+            "Folk dance events in the UK and Ireland"
+        );
+    }
+
+    #[test]
+    fn city_country_title() {
+        let filters = Filters {
+            countries: vec!["UK".to_string()],
+            cities: vec!["London".to_string(), "Oxford".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            filters.make_title(),
+            "Folk dance events in London and Oxford, UK"
+        );
+    }
+
+    #[test]
+    fn multiple_values_query_string() {
+        let filters = Filters {
+            countries: vec!["Netherlands".to_string(), "Belgium".to_string()],
+            not_organisations: vec!["Org".to_string()],
+            not_styles: [DanceStyle::Contra].into_iter().collect(),
+            ..Default::default()
+        };
+        let query_string = filters.to_query_string().unwrap();
+        assert_eq!(
+            query_string,
+            "country=Netherlands&country=Belgium&not_styles=contra&not_organisation=Org"
+        );
+        assert_eq!(
+            serde_html_form::from_str::<Filters>(&query_string).unwrap(),
+            filters
+        );
+    }
+
+    #[test]
+    fn deserialize_old_filters() {
+        let query_string = "country=UK&city=London&band=Band&style=ecd";
+        assert_eq!(
+            serde_html_form::from_str::<Filters>(query_string).unwrap(),
+            Filters {
+                countries: vec!["UK".to_string()],
+                cities: vec!["London".to_string()],
+                bands: vec!["Band".to_string()],
+                styles: [DanceStyle::EnglishCountryDance].into_iter().collect(),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn match_included_and_excluded() {
+        let event = Event {
+            name: "Name".to_string(),
+            details: None,
+            links: vec![],
+            time: EventTime::DateOnly {
+                start_date: NaiveDate::from_ymd_opt(2022, 1, 1).unwrap(),
+                end_date: NaiveDate::from_ymd_opt(2022, 1, 1).unwrap(),
+            },
+            country: "Belgium".to_string(),
+            state: None,
+            city: "Gent".to_string(),
+            styles: vec![DanceStyle::Balfolk, DanceStyle::Contra],
+            workshop: false,
+            social: true,
+            bands: vec![],
+            callers: vec![],
+            price: None,
+            organisation: Some("Org".to_string()),
+            cancelled: false,
+            source: None,
+        };
+        let now = Utc::now();
+        let filters = Filters {
+            countries: vec!["Netherlands".to_string(), "Belgium".to_string()],
+            ..Filters::all()
+        };
+        assert!(filters.matches(&event, now));
+        assert!(!Filters {
+            not_organisations: vec!["Org".to_string()],
+            ..filters.clone()
+        }
+        .matches(&event, now));
+        assert!(!Filters {
+            not_styles: [DanceStyle::Contra].into_iter().collect(),
+            ..filters.clone()
+        }
+        .matches(&event, now));
+        assert!(!Filters {
+            countries: vec!["Netherlands".to_string()],
+            ..filters.clone()
+        }
+        .matches(&event, now));
+    }
+
+    #[test]
+    fn near_city_filter() {
+        let query_string = "near_city=London&radius=100";
+        let filters = serde_html_form::from_str::<Filters>(query_string).unwrap();
+        assert_eq!(filters.to_query_string().unwrap(), query_string);
+        assert_eq!(
+            filters.make_title(),
+            "Folk dance events within 100 km of London"
+        );
+
+        let mut event = Event {
+            name: "Name".to_string(),
+            details: None,
+            links: vec![],
+            time: EventTime::DateOnly {
+                start_date: NaiveDate::from_ymd_opt(2022, 1, 1).unwrap(),
+                end_date: NaiveDate::from_ymd_opt(2022, 1, 1).unwrap(),
+            },
+            country: "UK".to_string(),
+            state: None,
+            city: "Oxford".to_string(),
+            styles: vec![DanceStyle::EnglishCountryDance],
+            workshop: false,
+            social: true,
+            bands: vec![],
+            callers: vec![],
+            price: None,
+            organisation: None,
+            cancelled: false,
+            source: None,
+        };
+        let filters = Filters {
+            date: DateFilter::All,
+            ..filters
+        };
+        let now = Utc::now();
+        assert!(filters.matches(&event, now));
+        event.city = "York".to_string();
+        assert!(!filters.matches(&event, now));
+        event.city = "Unknown".to_string();
+        assert!(!filters.matches(&event, now));
+    }
+
+    #[test]
+    fn near_coordinates_query_string() {
+        let query_string = "near=51.5%2C-0.1";
+        let filters = serde_html_form::from_str::<Filters>(query_string).unwrap();
+        assert_eq!(
+            filters.near,
+            Some(Coordinates {
+                latitude: 51.5,
+                longitude: -0.1
+            })
+        );
+        assert_eq!(filters.to_query_string().unwrap(), query_string);
+        assert!(serde_html_form::from_str::<Filters>("near=nowhere").is_err());
+    }
+
+    #[test]
+    fn query_string_with_defaults() {
+        let defaults =
+            serde_html_form::from_str::<Filters>("date=all&country=UK&styles=contra").unwrap();
+
+        // Keys which aren't given are taken from the defaults.
+        assert_eq!(
+            Filters::from_query_string_with_defaults("city=London", &defaults).unwrap(),
+            Filters {
+                date: DateFilter::All,
+                countries: vec!["UK".to_string()],
+                cities: vec!["London".to_string()],
+                styles: [DanceStyle::Contra].into_iter().collect(),
+                ..Default::default()
+            }
+        );
+
+        // Keys which are given override the defaults, even with the default value or no value.
+        let filters = Filters::from_query_string_with_defaults(
+            "date=future&country=&style=balfolk",
+            &defaults,
+        )
+        .unwrap();
+        assert_eq!(
+            filters,
+            Filters {
+                styles: [DanceStyle::Balfolk].into_iter().collect(),
+                ..Default::default()
+            }
+        );
+
+        let query_string = filters.to_query_string_with_defaults(&defaults).unwrap();
+        assert_eq!(query_string, "styles=balfolk&date=&country=");
+        assert_eq!(
+            Filters::from_query_string_with_defaults(&query_string, &defaults).unwrap(),
+            filters
+        );
+    }
+
+    #[test]
+    fn near_city_unknown() {
+        let error = serde_html_form::from_str::<Filters>("near_city=Londn").unwrap_err();
+        assert!(error.to_string().contains("Londn"), "{}", error);
+    }
+
+    #[test]
+    fn weekday_and_time_filters() {
+        let query_string = "weekdays=mon%2Cfri&start_after=18%3A00&start_before=23%3A30";
+        let filters = serde_html_form::from_str::<Filters>(query_string).unwrap();
+        assert_eq!(
+            filters,
+            Filters {
+                weekdays: [Weekday::Mon, Weekday::Fri].into_iter().collect(),
+                start_after: NaiveTime::from_hms_opt(18, 0, 0),
+                start_before: NaiveTime::from_hms_opt(23, 30, 0),
+                ..Default::default()
+            }
+        );
+        assert_eq!(filters.to_query_string().unwrap(), query_string);
+        assert_eq!(
+            filters.make_title(),
+            "Folk dance events on Mondays and Fridays starting between 18:00 and 23:30"
+        );
+
+        let offset = FixedOffset::east_opt(3600).unwrap();
+        let mut event = Event {
+            name: "Name".to_string(),
+            details: None,
+            links: vec![],
+            // Friday evening.
+            time: EventTime::DateTime {
+                start: offset.with_ymd_and_hms(2024, 1, 5, 19, 0, 0).unwrap(),
+                end: offset.with_ymd_and_hms(2024, 1, 6, 1, 0, 0).unwrap(),
+            },
+            country: "Country".to_string(),
+            state: None,
+            city: "City".to_string(),
+            styles: vec![DanceStyle::Balfolk],
+            workshop: false,
+            social: true,
+            bands: vec![],
+            callers: vec![],
+            price: None,
+            organisation: None,
+            cancelled: false,
+            source: None,
+        };
+        let filters = Filters {
+            date: DateFilter::All,
+            ..filters
+        };
+        let now = Utc::now();
+        assert!(filters.matches(&event, now));
+
+        // Saturday afternoon.
+        event.time = EventTime::DateTime {
+            start: offset.with_ymd_and_hms(2024, 1, 6, 14, 0, 0).unwrap(),
+            end: offset.with_ymd_and_hms(2024, 1, 6, 17, 0, 0).unwrap(),
+        };
+        assert!(!filters.matches(&event, now));
+
+        // A date-only weekend which doesn't include Friday.
+        event.time = EventTime::DateOnly {
+            start_date: NaiveDate::from_ymd_opt(2024, 1, 6).unwrap(),
+            end_date: NaiveDate::from_ymd_opt(2024, 1, 7).unwrap(),
+        };
+        assert!(!filters.matches(&event, now));
+
+        // A date-only weekend from Friday.
+        event.time = EventTime::DateOnly {
+            start_date: NaiveDate::from_ymd_opt(2024, 1, 5).unwrap(),
+            end_date: NaiveDate::from_ymd_opt(2024, 1, 7).unwrap(),
+        };
+        assert!(filters.matches(&event, now));
+    }
+
+    #[test]
+    fn start_time_window_wraps_midnight() {
+        let filters = Filters {
+            date: DateFilter::All,
+            start_after: NaiveTime::from_hms_opt(22, 0, 0),
+            start_before: NaiveTime::from_hms_opt(2, 0, 0),
+            ..Default::default()
+        };
+        let offset = FixedOffset::east_opt(0).unwrap();
+        let mut event = Event {
+            name: "Name".to_string(),
+            details: None,
+            links: vec![],
+            time: EventTime::DateTime {
+                start: offset.with_ymd_and_hms(2024, 1, 5, 23, 0, 0).unwrap(),
+                end: offset.with_ymd_and_hms(2024, 1, 6, 3, 0, 0).unwrap(),
+            },
+            country: "Country".to_string(),
+            state: None,
+            city: "City".to_string(),
+            styles: vec![DanceStyle::Balfolk],
+            workshop: false,
+            social: true,
+            bands: vec![],
+            callers: vec![],
+            price: None,
+            organisation: None,
+            cancelled: false,
+            source: None,
+        };
+        let now = Utc::now();
+        assert!(filters.matches(&event, now));
+        event.time = EventTime::DateTime {
+            start: offset.with_ymd_and_hms(2024, 1, 5, 20, 0, 0).unwrap(),
+            end: offset.with_ymd_and_hms(2024, 1, 5, 23, 0, 0).unwrap(),
+        };
+        assert!(!filters.matches(&event, now));
+    }
+
+    #[test]
+    fn source_filter() {
+        let mut event = Event {
+            name: "Name".to_string(),
+            details: None,
+            links: vec![],
+            time: EventTime::DateOnly {
+                start_date: NaiveDate::from_ymd_opt(2022, 1, 1).unwrap(),
+                end_date: NaiveDate::from_ymd_opt(2022, 1, 1).unwrap(),
+            },
+            country: "Country".to_string(),
+            state: None,
+            city: "City".to_string(),
+            styles: vec![DanceStyle::Contra],
+            workshop: false,
+            social: true,
+            bands: vec![],
+            callers: vec![],
+            price: None,
+            organisation: None,
+            cancelled: false,
+            source: Some("events/imported/cdss.yaml".to_string()),
+        };
+        let now = Utc::now();
+        let prefix = Filters {
+            source: Some("events/imported/".to_string()),
+            ..Filters::all()
+        };
+        let glob = Filters {
+            source: Some("events/*/cdss.yaml".to_string()),
+            ..Filters::all()
+        };
+        assert!(prefix.matches(&event, now));
+        assert!(glob.matches(&event, now));
+        assert!(!Filters {
+            source: Some("events/imported/folkbalbende".to_string()),
+            ..Filters::all()
+        }
+        .matches(&event, now));
+
+        event.source = Some("events/uk/cecil_sharp_house.yaml".to_string());
+        assert!(!prefix.matches(&event, now));
+        assert!(!glob.matches(&event, now));
+
+        event.source = None;
+        assert!(!prefix.matches(&event, now));
+    }
 }
@@ -0,0 +1,231 @@
+// Copyright 2024 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{event::Event, events::Events};
+use chrono::{DateTime, Utc};
+use eyre::{Report, WrapErr};
+use log::info;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{btree_map, BTreeMap, HashSet},
+    fs::{read_to_string, write},
+    path::Path,
+};
+
+/// Records when each event was first seen and last changed, keyed by the event's UID.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct EventHistory {
+    events: BTreeMap<String, HistoryEntry>,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+struct HistoryEntry {
+    first_seen: DateTime<Utc>,
+    last_modified: DateTime<Utc>,
+    /// The number of times the event has been changed since it was first seen.
+    sequence: u32,
+    /// A hash of the event's contents, to tell when it changes.
+    hash: String,
+}
+
+impl EventHistory {
+    /// Loads the history from the given JSON file, or returns an empty history if it doesn't exist
+    /// yet.
+    pub fn load(filename: &Path) -> Result<Self, Report> {
+        if !filename.exists() {
+            return Ok(Self::default());
+        }
+        let contents =
+            read_to_string(filename).wrap_err_with(|| format!("Reading {:?}", filename))?;
+        serde_json::from_str(&contents).wrap_err_with(|| format!("Parsing {:?}", filename))
+    }
+
+    /// Saves the history to the given JSON file.
+    pub fn save(&self, filename: &Path) -> Result<(), Report> {
+        write(filename, serde_json::to_string_pretty(self)?)
+            .wrap_err_with(|| format!("Writing {:?}", filename))
+    }
+
+    /// Records the given time for all events which haven't been seen before or have changed since
+    /// they were last seen, and returns how many there were.
+    pub fn record(&mut self, events: &Events, now: DateTime<Utc>) -> usize {
+        let mut changed_count = 0;
+        let mut seen_uids = HashSet::new();
+        for event in &events.events {
+            let uid = event.uid();
+            if !seen_uids.insert(uid.clone()) {
+                // Don't let duplicate events flip the hash back and forth.
+                continue;
+            }
+            let hash = content_hash(event);
+            match self.events.entry(uid) {
+                btree_map::Entry::Vacant(entry) => {
+                    entry.insert(HistoryEntry {
+                        first_seen: now,
+                        last_modified: now,
+                        sequence: 0,
+                        hash,
+                    });
+                    changed_count += 1;
+                }
+                btree_map::Entry::Occupied(mut entry) => {
+                    let entry = entry.get_mut();
+                    if entry.hash != hash {
+                        entry.last_modified = now;
+                        entry.sequence += 1;
+                        entry.hash = hash;
+                        changed_count += 1;
+                    }
+                }
+            }
+        }
+        changed_count
+    }
+
+    /// Records any new or changed events as seen now, and then saves to the given file if there is
+    /// one.
+    pub fn record_and_save(
+        &mut self,
+        events: &Events,
+        filename: Option<&Path>,
+    ) -> Result<(), Report> {
+        let changed_count = self.record(events, Utc::now());
+        if let Some(filename) = filename {
+            if changed_count > 0 {
+                info!(
+                    "Saving {} new or changed events to {:?}.",
+                    changed_count, filename
+                );
+                self.save(filename)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns when the given event was first seen, if it has been recorded.
+    pub fn first_seen(&self, event: &Event) -> Option<DateTime<Utc>> {
+        Some(self.events.get(&event.uid())?.first_seen)
+    }
+
+    /// Returns when the given event was last changed, if it has been recorded.
+    pub fn last_modified(&self, event: &Event) -> Option<DateTime<Utc>> {
+        Some(self.events.get(&event.uid())?.last_modified)
+    }
+
+    /// Returns the number of times the given event has changed since it was first seen, or 0 if it
+    /// hasn't been recorded.
+    pub fn sequence(&self, event: &Event) -> u32 {
+        self.events
+            .get(&event.uid())
+            .map(|entry| entry.sequence)
+            .unwrap_or_default()
+    }
+}
+
+/// Returns a hash of all the details of the event except for the file it came from.
+fn content_hash(event: &Event) -> String {
+    let event = Event {
+        source: None,
+        ..event.clone()
+    };
+    let json = serde_json::to_string(&event).unwrap();
+    Sha256::digest(json.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{dancestyle::DanceStyle, event::EventTime};
+    use chrono::{NaiveDate, TimeZone};
+
+    #[test]
+    fn record_new_and_changed_events() {
+        let event = Event {
+            name: "Name".to_string(),
+            details: None,
+            links: vec![],
+            time: EventTime::DateOnly {
+                start_date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+                end_date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            },
+            country: "Country".to_string(),
+            state: None,
+            city: "City".to_string(),
+            styles: vec![DanceStyle::Contra],
+            workshop: false,
+            social: true,
+            bands: vec![],
+            callers: vec![],
+            price: None,
+            organisation: None,
+            cancelled: false,
+            source: None,
+        };
+        let other_event = Event {
+            name: "Other".to_string(),
+            ..event.clone()
+        };
+        let first_time = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let second_time = Utc.with_ymd_and_hms(2024, 2, 1, 0, 0, 0).unwrap();
+
+        let mut history = EventHistory::default();
+        assert_eq!(
+            history.record(
+                &Events {
+                    events: vec![event.clone()]
+                },
+                first_time
+            ),
+            1
+        );
+        // Seeing the same event again, even from a different file, isn't a change.
+        assert_eq!(
+            history.record(
+                &Events {
+                    events: vec![Event {
+                        source: Some("other.yaml".to_string()),
+                        ..event.clone()
+                    }]
+                },
+                second_time
+            ),
+            0
+        );
+        // Changing details which aren't part of the UID should update the same event.
+        let updated_event = Event {
+            bands: vec!["Band".to_string()],
+            ..event.clone()
+        };
+        assert_eq!(
+            history.record(
+                &Events {
+                    events: vec![updated_event.clone(), other_event.clone()]
+                },
+                second_time
+            ),
+            2
+        );
+
+        assert_eq!(history.first_seen(&updated_event), Some(first_time));
+        assert_eq!(history.last_modified(&updated_event), Some(second_time));
+        assert_eq!(history.sequence(&updated_event), 1);
+        assert_eq!(history.first_seen(&other_event), Some(second_time));
+        assert_eq!(history.sequence(&other_event), 0);
+    }
+}
@@ -16,3 +16,4 @@ pub mod dancestyle;
 pub mod event;
 pub mod events;
 pub mod filters;
+pub mod history;
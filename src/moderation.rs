@@ -0,0 +1,407 @@
+// Copyright 2024 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A local queue of submitted events waiting for a moderator to approve them, for sites which don't
+//! propose changes via a forge.
+
+use crate::{
+    change::Change,
+    config::ModerationConfig,
+    forge::to_safe_filename,
+    model::{event::Event, events::Events},
+};
+use chrono::{DateTime, Utc};
+use eyre::{bail, Report, WrapErr};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsStr,
+    fs::{self, create_dir_all, read_dir, read_to_string, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Component, Path, PathBuf},
+};
+
+/// The higher suffix number to add to a submission ID.
+const MAX_SUFFIX: u32 = 9;
+
+/// The subdirectory of the queue directory to which approved submissions are moved.
+const APPROVED_DIR: &str = "approved";
+/// The subdirectory of the queue directory to which rejected submissions are moved.
+const REJECTED_DIR: &str = "rejected";
+
+/// A set of events submitted together, waiting for approval.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Submission {
+    /// The ID of the submission, which is the name of its file in the queue.
+    #[serde(skip)]
+    pub id: String,
+    /// When the events were submitted.
+    pub submitted: DateTime<Utc>,
+    /// The path of the events file to which the events should be added, as chosen by
+    /// `choose_file_for_event`.
+    pub file: String,
+    /// The email address of the submitter, if they gave one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    /// The events as they will be once the submission is approved.
+    pub events: Vec<Event>,
+    /// The existing event which the submitted one replaces, if it is an edit or cancellation rather
+    /// than new events.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replaces: Option<Event>,
+    /// The reason the submitter gave for cancelling the event, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// The moderator's reason for rejecting the submission, if it was rejected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rejection_note: Option<String>,
+}
+
+impl Submission {
+    /// Returns the change to make to the events file when the submission is approved.
+    ///
+    /// Cancellations are returned as edits which mark the event as cancelled, as they are made in
+    /// the same way.
+    pub fn change(&self) -> Result<Change, Report> {
+        match (&self.replaces, self.events.as_slice()) {
+            (None, _) => Ok(Change::Add(self.events.clone())),
+            (Some(original), [edited]) => Ok(Change::Edit {
+                original: original.clone(),
+                edited: edited.clone(),
+            }),
+            (Some(_), _) => bail!("Submission {} must replace a single event", self.id),
+        }
+    }
+}
+
+/// Adds the given change to the queue, to be made to the given file once it is approved.
+///
+/// Returns the ID of the new submission.
+pub fn queue_change(
+    change: Change,
+    filename: &str,
+    email: Option<&str>,
+    config: &ModerationConfig,
+) -> Result<String, Report> {
+    let events = change.events();
+    let Some(first) = events.first() else {
+        bail!("No events to submit");
+    };
+    let submitted = Utc::now();
+    let id_base = format!(
+        "{}-{}",
+        submitted.format("%Y%m%d-%H%M%S"),
+        to_safe_filename(&first.name)
+    );
+    let reason = match &change {
+        Change::Cancel { reason, .. } => reason.clone(),
+        _ => None,
+    };
+    let submission = Submission {
+        id: String::new(),
+        submitted,
+        file: filename.to_owned(),
+        email: email.map(ToOwned::to_owned),
+        replaces: change.original().cloned(),
+        reason,
+        events,
+        rejection_note: None,
+    };
+    let contents = serde_yaml::to_string(&submission)?;
+
+    create_dir_all(&config.queue_dir)
+        .wrap_err_with(|| format!("Creating {:?}", config.queue_dir))?;
+    // Retry with different suffixes if there is already a submission with the same ID.
+    for suffix in 0..=MAX_SUFFIX {
+        let id = if suffix == 0 {
+            id_base.clone()
+        } else {
+            format!("{}{}", id_base, suffix)
+        };
+        let path = submission_path(&config.queue_dir, &id);
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => {
+                file.write_all(contents.as_bytes())
+                    .wrap_err_with(|| format!("Writing {:?}", path))?;
+                info!("Queued submission {}", id);
+                return Ok(id);
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(Report::new(e).wrap_err(format!("Creating {:?}", path))),
+        }
+    }
+    bail!("Failed to find a free name for submission {}", id_base);
+}
+
+/// Returns all submissions waiting for approval, oldest first.
+pub fn pending_submissions(config: &ModerationConfig) -> Result<Vec<Submission>, Report> {
+    if !config.queue_dir.exists() {
+        return Ok(vec![]);
+    }
+    let mut submissions = vec![];
+    for entry in read_dir(&config.queue_dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension() == Some(OsStr::new("yaml")) {
+            let id = path.file_stem().unwrap().to_string_lossy().into_owned();
+            submissions.push(load_submission(&config.queue_dir, &id)?);
+        }
+    }
+    submissions.sort_by(|a, b| a.submitted.cmp(&b.submitted).then(a.id.cmp(&b.id)));
+    Ok(submissions)
+}
+
+/// Loads the pending submission with the given ID, if there is one.
+pub fn get_submission(id: &str, config: &ModerationConfig) -> Result<Option<Submission>, Report> {
+    if !valid_id(id) || !submission_path(&config.queue_dir, id).is_file() {
+        return Ok(None);
+    }
+    load_submission(&config.queue_dir, id).map(Some)
+}
+
+/// Makes the change from the given submission to the appropriate file under `events_dir`, and
+/// moves the submission out of the queue.
+///
+/// Returns the path of the file which was changed.
+pub fn approve_submission(
+    submission: &Submission,
+    events_dir: &str,
+    config: &ModerationConfig,
+) -> Result<PathBuf, Report> {
+    let path = local_events_path(&submission.file, events_dir)?;
+    apply_to_local_file(&path, &submission.change()?)?;
+
+    move_submission(submission, APPROVED_DIR, config)?;
+    info!("Approved submission {}, changed {:?}", submission.id, path);
+    Ok(path)
+}
+
+/// Makes the given change to the events file at the given local path, creating it if it doesn't
+/// already exist, and sorts the events in it.
+pub fn apply_to_local_file(path: &Path, change: &Change) -> Result<(), Report> {
+    let mut events = if path.exists() {
+        let contents = read_to_string(path).wrap_err_with(|| format!("Reading {:?}", path))?;
+        serde_yaml::from_str::<Events>(&contents).wrap_err_with(|| format!("Parsing {:?}", path))?
+    } else {
+        Events::default()
+    };
+    change
+        .apply(&mut events)
+        .wrap_err_with(|| format!("Changing {:?}", path))?;
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent).wrap_err_with(|| format!("Creating {:?}", parent))?;
+    }
+    fs::write(path, events.to_yaml_string()?).wrap_err_with(|| format!("Writing {:?}", path))?;
+    Ok(())
+}
+
+/// Moves the given submission out of the queue, recording the moderator's note about why it was
+/// rejected.
+pub fn reject_submission(
+    submission: Submission,
+    note: Option<String>,
+    config: &ModerationConfig,
+) -> Result<(), Report> {
+    let submission = Submission {
+        rejection_note: note,
+        ..submission
+    };
+    move_submission(&submission, REJECTED_DIR, config)?;
+    info!("Rejected submission {}", submission.id);
+    Ok(())
+}
+
+/// Writes the given submission to the given subdirectory of the queue, and removes it from the
+/// queue.
+fn move_submission(
+    submission: &Submission,
+    subdirectory: &str,
+    config: &ModerationConfig,
+) -> Result<(), Report> {
+    let directory = config.queue_dir.join(subdirectory);
+    create_dir_all(&directory).wrap_err_with(|| format!("Creating {:?}", directory))?;
+    let new_path = submission_path(&directory, &submission.id);
+    fs::write(&new_path, serde_yaml::to_string(submission)?)
+        .wrap_err_with(|| format!("Writing {:?}", new_path))?;
+    let old_path = submission_path(&config.queue_dir, &submission.id);
+    fs::remove_file(&old_path).wrap_err_with(|| format!("Removing {:?}", old_path))?;
+    Ok(())
+}
+
+fn load_submission(queue_dir: &Path, id: &str) -> Result<Submission, Report> {
+    let path = submission_path(queue_dir, id);
+    let contents = read_to_string(&path).wrap_err_with(|| format!("Reading {:?}", path))?;
+    let submission = serde_yaml::from_str::<Submission>(&contents)
+        .wrap_err_with(|| format!("Parsing {:?}", path))?;
+    Ok(Submission {
+        id: id.to_owned(),
+        ..submission
+    })
+}
+
+fn submission_path(directory: &Path, id: &str) -> PathBuf {
+    directory.join(format!("{}.yaml", id))
+}
+
+/// Returns whether the given string could be a submission ID, so it is safe to use in a path.
+fn valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Converts the file chosen for a submission to a local path.
+///
+/// This is either the path of an existing events file from which events were loaded, or a new path
+/// under the `events/` directory of the data repository, which is taken to be relative to
+/// `events_dir`.
+pub fn local_events_path(file: &str, events_dir: &str) -> Result<PathBuf, Report> {
+    let path = Path::new(file);
+    if path.is_file() {
+        return Ok(path.to_owned());
+    }
+    let events_dir = Path::new(events_dir);
+    if !events_dir.is_dir() {
+        bail!(
+            "Events must be loaded from a local directory to add new files, not {:?}",
+            events_dir
+        );
+    }
+    let Ok(relative) = path.strip_prefix("events") else {
+        bail!("Unexpected events file {:?}", file);
+    };
+    if relative
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_)))
+    {
+        bail!("Invalid events file {:?}", file);
+    }
+    Ok(events_dir.join(relative))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{dancestyle::DanceStyle, event::EventTime};
+    use chrono::NaiveDate;
+    use tempfile::tempdir;
+
+    fn test_event(name: &str) -> Event {
+        Event {
+            name: name.to_string(),
+            details: None,
+            links: vec!["https://example.com/".to_string()],
+            time: EventTime::DateOnly {
+                start_date: NaiveDate::from_ymd_opt(2030, 1, 1).unwrap(),
+                end_date: NaiveDate::from_ymd_opt(2030, 1, 1).unwrap(),
+            },
+            country: "UK".to_string(),
+            state: None,
+            city: "London".to_string(),
+            styles: vec![DanceStyle::Contra],
+            workshop: false,
+            social: true,
+            bands: vec![],
+            callers: vec![],
+            price: None,
+            organisation: None,
+            cancelled: false,
+            source: None,
+        }
+    }
+
+    #[test]
+    fn approve_and_reject() {
+        let dir = tempdir().unwrap();
+        let events_dir = dir.path().join("events");
+        create_dir_all(&events_dir).unwrap();
+        let config = ModerationConfig {
+            queue_dir: dir.path().join("queue"),
+            username: "admin".to_string(),
+            password: "password".to_string(),
+        };
+
+        let first = queue_change(
+            Change::Add(vec![test_event("A"), test_event("B")]),
+            "events/uk/london.yaml",
+            Some("a@example.com"),
+            &config,
+        )
+        .unwrap();
+        let second = queue_change(
+            Change::Add(vec![test_event("C")]),
+            "events/uk/london.yaml",
+            None,
+            &config,
+        )
+        .unwrap();
+        assert_ne!(first, second);
+
+        let pending = pending_submissions(&config).unwrap();
+        assert_eq!(pending.len(), 2);
+        assert_eq!(pending[0].id, first);
+        assert_eq!(pending[0].events.len(), 2);
+        assert_eq!(pending[0].email.as_deref(), Some("a@example.com"));
+
+        let path = approve_submission(&pending[0], events_dir.to_str().unwrap(), &config).unwrap();
+        assert_eq!(path, events_dir.join("uk/london.yaml"));
+        let added = Events::load_file(&path).unwrap();
+        assert_eq!(added.events.len(), 2);
+
+        reject_submission(pending[1].clone(), Some("Spam".to_string()), &config).unwrap();
+        assert_eq!(pending_submissions(&config).unwrap(), vec![]);
+        assert_eq!(get_submission(&second, &config).unwrap(), None);
+        let rejected = load_submission(&config.queue_dir.join(REJECTED_DIR), &second).unwrap();
+        assert_eq!(rejected.rejection_note.as_deref(), Some("Spam"));
+
+        // Cancel one of the approved events.
+        let cancel = queue_change(
+            Change::Cancel {
+                original: test_event("A"),
+                reason: Some("Venue closed".to_string()),
+            },
+            "events/uk/london.yaml",
+            None,
+            &config,
+        )
+        .unwrap();
+        let submission = get_submission(&cancel, &config).unwrap().unwrap();
+        assert_eq!(submission.replaces, Some(test_event("A")));
+        assert_eq!(submission.reason.as_deref(), Some("Venue closed"));
+        approve_submission(&submission, events_dir.to_str().unwrap(), &config).unwrap();
+        let changed = Events::load_file(&path).unwrap();
+        assert_eq!(
+            changed
+                .events
+                .iter()
+                .map(|event| (event.name.as_str(), event.cancelled))
+                .collect::<Vec<_>>(),
+            vec![("A", true), ("B", false)]
+        );
+    }
+
+    #[test]
+    fn invalid_paths() {
+        let config = ModerationConfig {
+            queue_dir: PathBuf::from("/nonexistent"),
+            username: String::new(),
+            password: String::new(),
+        };
+        assert_eq!(get_submission("../secret", &config).unwrap(), None);
+        assert!(local_events_path("events/../../etc/passwd", ".").is_err());
+        assert!(local_events_path("other/file.yaml", ".").is_err());
+        assert!(local_events_path("events/uk/london.yaml", "https://example.com/").is_err());
+    }
+}
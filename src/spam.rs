@@ -0,0 +1,429 @@
+// Copyright 2024 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Protection against spam submitted through the web forms: per-IP rate limits, a honeypot field, a
+//! signed token to enforce a minimum fill time, and blocklists of link domains and keywords.
+
+use crate::{config::SpamConfig, model::event::Event};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use eyre::{Report, WrapErr};
+use hmac::{Hmac, Mac};
+use log::warn;
+use rand::RngCore;
+use serde::Serialize;
+use sha2::Sha256;
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    fs::OpenOptions,
+    io::Write,
+    net::IpAddr,
+    sync::Mutex,
+};
+use url::Url;
+
+/// The maximum age of a form token, in seconds.
+const MAX_TOKEN_AGE: i64 = 24 * 60 * 60;
+
+/// The period over which submissions are counted for rate limiting, in seconds.
+const RATE_LIMIT_PERIOD: i64 = 60 * 60;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Checks submissions for spam, and keeps track of recent submissions for rate limiting.
+#[derive(Debug)]
+pub struct SpamGuard {
+    config: SpamConfig,
+    key: Vec<u8>,
+    /// The times of recent accepted submissions from each IP address.
+    recent: Mutex<HashMap<IpAddr, Vec<DateTime<Utc>>>>,
+}
+
+/// The reason a submission was rejected as spam.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Rejection {
+    /// The honeypot field was filled in.
+    Honeypot,
+    /// The form token was missing or its signature didn't match.
+    InvalidToken,
+    /// The form was submitted too soon after it was loaded.
+    TooFast,
+    /// The form token is too old.
+    Expired,
+    /// There have been too many submissions from the same IP address recently.
+    RateLimited,
+    /// An event links to the given blocked domain.
+    BlockedDomain(String),
+    /// An event contains the given blocked keyword.
+    BlockedKeyword(String),
+}
+
+impl Display for Rejection {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Honeypot | Self::InvalidToken => {
+                write!(
+                    f,
+                    "Submission rejected. Please reload the form and try again."
+                )
+            }
+            Self::TooFast => write!(
+                f,
+                "The form was submitted too quickly. Please go back and try again."
+            ),
+            Self::Expired => write!(
+                f,
+                "The form has expired. Please reload the form and try again."
+            ),
+            Self::RateLimited => write!(
+                f,
+                "Too many submissions. Please wait for a while and try again."
+            ),
+            Self::BlockedDomain(domain) => write!(f, "Links to {} are not allowed.", domain),
+            Self::BlockedKeyword(keyword) => write!(f, "\"{}\" is not allowed.", keyword),
+        }
+    }
+}
+
+/// A rejected submission, as logged for review.
+#[derive(Serialize)]
+struct RejectedSubmission<'a> {
+    time: DateTime<Utc>,
+    client: IpAddr,
+    reason: String,
+    email: Option<&'a str>,
+    events: &'a [Event],
+}
+
+impl SpamGuard {
+    pub fn new(config: SpamConfig) -> Self {
+        let key = if config.secret.is_empty() {
+            let mut key = vec![0; 32];
+            rand::thread_rng().fill_bytes(&mut key);
+            key
+        } else {
+            config.secret.as_bytes().to_owned()
+        };
+        Self {
+            config,
+            key,
+            recent: Default::default(),
+        }
+    }
+
+    /// Returns a new signed token recording that the form was loaded at the given time.
+    pub fn issue_token(&self, now: DateTime<Utc>) -> String {
+        let timestamp = now.timestamp().to_string();
+        let signature = self.mac(&timestamp).finalize().into_bytes();
+        format!("{}.{}", timestamp, URL_SAFE_NO_PAD.encode(signature))
+    }
+
+    /// Checks a submission from the given client with the given form token, honeypot field value and
+    /// events. If it is accepted, it is counted for rate limiting.
+    pub fn check(
+        &self,
+        client: IpAddr,
+        token: &str,
+        honeypot: &str,
+        events: &[Event],
+        now: DateTime<Utc>,
+    ) -> Result<(), Rejection> {
+        if !honeypot.is_empty() {
+            return Err(Rejection::Honeypot);
+        }
+        self.check_token(token, now)?;
+        self.check_blocklists(events)?;
+
+        let mut recent = self.recent.lock().unwrap();
+        recent.retain(|_, times| {
+            times.retain(|time| (now - *time).num_seconds() < RATE_LIMIT_PERIOD);
+            !times.is_empty()
+        });
+        let times = recent.entry(client).or_default();
+        if self.config.max_submissions_per_hour != 0
+            && times.len() >= self.config.max_submissions_per_hour
+        {
+            return Err(Rejection::RateLimited);
+        }
+        times.push(now);
+        Ok(())
+    }
+
+    /// Logs the given rejected submission for review, and appends it to the rejected log file if
+    /// one is configured.
+    pub fn log_rejection(
+        &self,
+        client: IpAddr,
+        rejection: &Rejection,
+        email: Option<&str>,
+        events: &[Event],
+    ) -> Result<(), Report> {
+        warn!(
+            "Rejected submission of {} events from {}: {:?}",
+            events.len(),
+            client,
+            rejection
+        );
+        if let Some(rejected_log) = &self.config.rejected_log {
+            let mut line = serde_json::to_string(&RejectedSubmission {
+                time: Utc::now(),
+                client,
+                reason: format!("{:?}", rejection),
+                email,
+                events,
+            })?;
+            line.push('\n');
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(rejected_log)
+                .and_then(|mut file| file.write_all(line.as_bytes()))
+                .wrap_err_with(|| format!("Writing to {:?}", rejected_log))?;
+        }
+        Ok(())
+    }
+
+    fn check_token(&self, token: &str, now: DateTime<Utc>) -> Result<(), Rejection> {
+        let (timestamp, signature) = token.split_once('.').ok_or(Rejection::InvalidToken)?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| Rejection::InvalidToken)?;
+        self.mac(timestamp)
+            .verify_slice(&signature)
+            .map_err(|_| Rejection::InvalidToken)?;
+        let issued = timestamp
+            .parse::<i64>()
+            .map_err(|_| Rejection::InvalidToken)?;
+        let age = now.timestamp() - issued;
+        if age < self.config.min_fill_seconds.try_into().unwrap_or(i64::MAX) {
+            Err(Rejection::TooFast)
+        } else if age > MAX_TOKEN_AGE {
+            Err(Rejection::Expired)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_blocklists(&self, events: &[Event]) -> Result<(), Rejection> {
+        for event in events {
+            for link in &event.links {
+                let Some(host) = Url::parse(link)
+                    .ok()
+                    .and_then(|url| url.host_str().map(str::to_lowercase))
+                else {
+                    continue;
+                };
+                if let Some(domain) = self.config.blocked_domains.iter().find(|domain| {
+                    let domain = domain.to_lowercase();
+                    host == domain
+                        || host
+                            .strip_suffix(&domain)
+                            .is_some_and(|prefix| prefix.ends_with('.'))
+                }) {
+                    return Err(Rejection::BlockedDomain(domain.to_owned()));
+                }
+            }
+
+            let text = [
+                Some(&event.name),
+                event.details.as_ref(),
+                event.price.as_ref(),
+                event.organisation.as_ref(),
+            ]
+            .into_iter()
+            .flatten()
+            .chain(&event.links)
+            .chain(&event.bands)
+            .chain(&event.callers)
+            .map(|field| field.to_lowercase())
+            .collect::<Vec<_>>();
+            if let Some(keyword) = self.config.blocked_keywords.iter().find(|keyword| {
+                let keyword = keyword.to_lowercase();
+                text.iter().any(|field| field.contains(&keyword))
+            }) {
+                return Err(Rejection::BlockedKeyword(keyword.to_owned()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the HMAC of the given timestamp, to sign or verify a token.
+    fn mac(&self, timestamp: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC takes keys of any size");
+        mac.update(timestamp.as_bytes());
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{dancestyle::DanceStyle, event::EventTime};
+    use chrono::{NaiveDate, TimeDelta};
+
+    fn test_event() -> Event {
+        Event {
+            name: "Dance".to_string(),
+            details: None,
+            links: vec!["https://example.com/".to_string()],
+            time: EventTime::DateOnly {
+                start_date: NaiveDate::from_ymd_opt(2030, 1, 1).unwrap(),
+                end_date: NaiveDate::from_ymd_opt(2030, 1, 1).unwrap(),
+            },
+            country: "UK".to_string(),
+            state: None,
+            city: "London".to_string(),
+            styles: vec![DanceStyle::Contra],
+            workshop: false,
+            social: true,
+            bands: vec![],
+            callers: vec![],
+            price: None,
+            organisation: None,
+            cancelled: false,
+            source: None,
+        }
+    }
+
+    fn test_guard() -> SpamGuard {
+        SpamGuard::new(SpamConfig {
+            secret: "secret".to_string(),
+            max_submissions_per_hour: 2,
+            min_fill_seconds: 5,
+            blocked_domains: vec!["spam.example".to_string()],
+            blocked_keywords: vec!["Casino".to_string()],
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn hmac() {
+        // Test case 2 from RFC 4231.
+        let mut mac = HmacSha256::new_from_slice(b"Jefe").unwrap();
+        mac.update(b"what do ya want for nothing?");
+        assert_eq!(
+            mac.finalize()
+                .into_bytes()
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<String>(),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn tokens() {
+        let guard = test_guard();
+        let client = IpAddr::from([192, 0, 2, 1]);
+        let events = [test_event()];
+        let loaded = Utc::now();
+        let token = guard.issue_token(loaded);
+
+        assert_eq!(
+            guard.check(client, &token, "", &events, loaded + TimeDelta::seconds(2)),
+            Err(Rejection::TooFast)
+        );
+        assert_eq!(
+            guard.check(client, &token, "", &events, loaded + TimeDelta::days(2)),
+            Err(Rejection::Expired)
+        );
+        assert_eq!(
+            guard.check(client, "", "", &events, loaded + TimeDelta::seconds(10)),
+            Err(Rejection::InvalidToken)
+        );
+        let forged = token.replacen(|c: char| c.is_ascii_digit(), "0", 1);
+        assert_eq!(
+            guard.check(
+                client,
+                &forged,
+                "",
+                &events,
+                loaded + TimeDelta::seconds(10)
+            ),
+            Err(Rejection::InvalidToken)
+        );
+        assert_eq!(
+            guard.check(
+                client,
+                &token,
+                "http://spam.example/",
+                &events,
+                loaded + TimeDelta::seconds(10)
+            ),
+            Err(Rejection::Honeypot)
+        );
+        assert_eq!(
+            guard.check(client, &token, "", &events, loaded + TimeDelta::seconds(10)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn rate_limit() {
+        let guard = test_guard();
+        let client = IpAddr::from([192, 0, 2, 1]);
+        let other_client = IpAddr::from([192, 0, 2, 2]);
+        let events = [test_event()];
+        let loaded = Utc::now();
+        let token = guard.issue_token(loaded);
+        let submitted = loaded + TimeDelta::minutes(1);
+
+        assert_eq!(guard.check(client, &token, "", &events, submitted), Ok(()));
+        assert_eq!(guard.check(client, &token, "", &events, submitted), Ok(()));
+        assert_eq!(
+            guard.check(client, &token, "", &events, submitted),
+            Err(Rejection::RateLimited)
+        );
+        assert_eq!(
+            guard.check(other_client, &token, "", &events, submitted),
+            Ok(())
+        );
+        assert_eq!(
+            guard.check(client, &token, "", &events, submitted + TimeDelta::hours(1)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn blocklists() {
+        let guard = test_guard();
+        let client = IpAddr::from([192, 0, 2, 1]);
+        let loaded = Utc::now();
+        let token = guard.issue_token(loaded);
+        let submitted = loaded + TimeDelta::minutes(1);
+
+        let event = Event {
+            links: vec![
+                "https://example.com/".to_string(),
+                "https://www.Spam.example/dance".to_string(),
+            ],
+            ..test_event()
+        };
+        assert_eq!(
+            guard.check(client, &token, "", &[event], submitted),
+            Err(Rejection::BlockedDomain("spam.example".to_string()))
+        );
+
+        let event = Event {
+            links: vec!["https://notspam.example/".to_string()],
+            details: Some("Dancing then casino night".to_string()),
+            ..test_event()
+        };
+        assert_eq!(
+            guard.check(client, &token, "", &[event], submitted),
+            Err(Rejection::BlockedKeyword("Casino".to_string()))
+        );
+    }
+}
@@ -0,0 +1,347 @@
+// Copyright 2024 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Statistics about events, and simple SVG charts of them.
+
+use crate::model::{
+    event::Event,
+    filters::{weekday_name, WEEKDAYS},
+};
+use chrono::Datelike;
+use serde::Serialize;
+use std::{collections::HashMap, fmt::Write};
+
+/// The number of bands, callers and organisations to include in the top lists.
+const TOP_COUNT: usize = 10;
+
+/// The width of a chart in pixels.
+const CHART_WIDTH: usize = 600;
+/// The width of the labels on the left of a chart in pixels.
+const CHART_LABEL_WIDTH: usize = 160;
+/// The height of each bar of a chart in pixels.
+const CHART_BAR_HEIGHT: usize = 20;
+
+/// Statistics about a set of events.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Stats {
+    /// The total number of events.
+    pub total: usize,
+    /// The proportion of events which include a workshop, between 0 and 1.
+    pub workshop_ratio: f64,
+    /// The proportion of events which include social dancing, between 0 and 1.
+    pub social_ratio: f64,
+    /// Events by dance style, most common first. Events with several styles count for each.
+    pub by_style: Vec<Count>,
+    /// Events by country, most common first.
+    pub by_country: Vec<Count>,
+    /// Events by the year and month in which they start, like "2024-01", in order.
+    pub by_month: Vec<Count>,
+    /// Events by the day of the week on which they start, starting on Monday.
+    pub by_weekday: Vec<Count>,
+    /// Events by the year in which they start, in order, with the change from the previous year.
+    pub by_year: Vec<YearCount>,
+    pub top_bands: Vec<Count>,
+    pub top_callers: Vec<Count>,
+    pub top_organisations: Vec<Count>,
+}
+
+/// The number of events with some property.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct Count {
+    pub name: String,
+    pub count: usize,
+}
+
+/// The number of events starting in a year.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct YearCount {
+    pub year: i32,
+    pub count: usize,
+    /// The percentage change from the previous year, if there were any events then.
+    pub change_percent: Option<f64>,
+}
+
+impl Stats {
+    /// Calculates statistics for the given events.
+    pub fn new(events: &[&Event]) -> Self {
+        let total = events.len();
+        let ratio = |count: usize| {
+            if total == 0 {
+                0.0
+            } else {
+                count as f64 / total as f64
+            }
+        };
+
+        let by_weekday = WEEKDAYS
+            .iter()
+            .map(|weekday| Count {
+                name: weekday_name(*weekday).to_owned(),
+                count: events
+                    .iter()
+                    .filter(|event| event.local_date_range().0.weekday() == *weekday)
+                    .count(),
+            })
+            .collect();
+
+        let mut by_month = count_by(events.iter().map(|event| {
+            let start_date = event.local_date_range().0;
+            format!("{:04}-{:02}", start_date.year(), start_date.month())
+        }));
+        by_month.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut year_counts = HashMap::<i32, usize>::new();
+        for event in events {
+            *year_counts
+                .entry(event.local_date_range().0.year())
+                .or_default() += 1;
+        }
+        let mut years = year_counts.into_iter().collect::<Vec<_>>();
+        years.sort();
+        let mut by_year: Vec<YearCount> = vec![];
+        for (year, count) in years {
+            let change_percent = by_year
+                .last()
+                .filter(|previous| previous.year == year - 1)
+                .map(|previous| (count as f64 / previous.count as f64 - 1.0) * 100.0);
+            by_year.push(YearCount {
+                year,
+                count,
+                change_percent,
+            });
+        }
+
+        Self {
+            total,
+            workshop_ratio: ratio(events.iter().filter(|event| event.workshop).count()),
+            social_ratio: ratio(events.iter().filter(|event| event.social).count()),
+            by_style: most_common(count_by(
+                events
+                    .iter()
+                    .flat_map(|event| event.styles.iter().map(ToString::to_string)),
+            )),
+            by_country: most_common(count_by(
+                events.iter().map(|event| event.country.to_owned()),
+            )),
+            by_month,
+            by_weekday,
+            by_year,
+            top_bands: top(count_by(
+                events.iter().flat_map(|event| event.bands.iter().cloned()),
+            )),
+            top_callers: top(count_by(
+                events
+                    .iter()
+                    .flat_map(|event| event.callers.iter().cloned()),
+            )),
+            top_organisations: top(count_by(
+                events.iter().filter_map(|event| event.organisation.clone()),
+            )),
+        }
+    }
+
+    /// Returns the number of events per year, for charting.
+    pub fn year_counts(&self) -> Vec<Count> {
+        self.by_year
+            .iter()
+            .map(|year| Count {
+                name: year.year.to_string(),
+                count: year.count,
+            })
+            .collect()
+    }
+}
+
+/// Counts how many times each distinct name occurs.
+fn count_by(names: impl Iterator<Item = String>) -> Vec<Count> {
+    let mut counts = HashMap::<String, usize>::new();
+    for name in names {
+        *counts.entry(name).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .map(|(name, count)| Count { name, count })
+        .collect()
+}
+
+/// Sorts the given counts with the highest first, then by name.
+fn most_common(mut counts: Vec<Count>) -> Vec<Count> {
+    counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+    counts
+}
+
+/// Returns the highest few counts.
+fn top(counts: Vec<Count>) -> Vec<Count> {
+    let mut counts = most_common(counts);
+    counts.truncate(TOP_COUNT);
+    counts
+}
+
+/// Renders the given counts as a horizontal bar chart in SVG, with a bar for each in order.
+pub fn bar_chart(counts: &[Count]) -> String {
+    let max = counts.iter().map(|count| count.count).max().unwrap_or(0);
+    // Leave some space to the right of the longest bar for its count.
+    let bar_space = CHART_WIDTH - CHART_LABEL_WIDTH - 40;
+    let height = counts.len() * CHART_BAR_HEIGHT;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" class=\"chart\" width=\"{width}\" height=\"{height}\" viewBox=\"0 0 {width} {height}\">",
+        width = CHART_WIDTH,
+        height = height,
+    );
+    for (i, count) in counts.iter().enumerate() {
+        let y = i * CHART_BAR_HEIGHT;
+        let bar_width = (count.count * bar_space).checked_div(max).unwrap_or(0);
+        write!(
+            svg,
+            "<text x=\"{label_x}\" y=\"{text_y}\" text-anchor=\"end\">{name}</text>\
+            <rect x=\"{bar_x}\" y=\"{bar_y}\" width=\"{bar_width}\" height=\"{bar_height}\" />\
+            <text x=\"{count_x}\" y=\"{text_y}\">{count}</text>",
+            label_x = CHART_LABEL_WIDTH - 5,
+            text_y = y + CHART_BAR_HEIGHT * 3 / 4,
+            name = escape_xml(&count.name),
+            bar_x = CHART_LABEL_WIDTH,
+            bar_y = y + 2,
+            bar_width = bar_width,
+            bar_height = CHART_BAR_HEIGHT - 4,
+            count_x = CHART_LABEL_WIDTH + bar_width + 5,
+            count = count.count,
+        )
+        .unwrap();
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+/// Escapes the given text for use in XML content.
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{dancestyle::DanceStyle, event::EventTime};
+    use chrono::NaiveDate;
+
+    fn test_event(date: NaiveDate, country: &str, workshop: bool) -> Event {
+        Event {
+            name: "Dance".to_string(),
+            details: None,
+            links: vec![],
+            time: EventTime::DateOnly {
+                start_date: date,
+                end_date: date,
+            },
+            country: country.to_string(),
+            state: None,
+            city: "City".to_string(),
+            styles: vec![DanceStyle::Contra],
+            workshop,
+            social: true,
+            bands: vec!["Band".to_string()],
+            callers: vec![],
+            price: None,
+            organisation: None,
+            cancelled: false,
+            source: None,
+        }
+    }
+
+    #[test]
+    fn count_events() {
+        let events = [
+            // A Monday.
+            test_event(NaiveDate::from_ymd_opt(2023, 1, 2).unwrap(), "UK", true),
+            test_event(NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(), "USA", false),
+            test_event(NaiveDate::from_ymd_opt(2024, 2, 3).unwrap(), "USA", false),
+            test_event(NaiveDate::from_ymd_opt(2024, 2, 4).unwrap(), "UK", false),
+        ];
+        let stats = Stats::new(&events.iter().collect::<Vec<_>>());
+
+        assert_eq!(stats.total, 4);
+        assert_eq!(stats.workshop_ratio, 0.25);
+        assert_eq!(stats.social_ratio, 1.0);
+        assert_eq!(
+            stats.by_country,
+            vec![
+                Count {
+                    name: "UK".to_string(),
+                    count: 2
+                },
+                Count {
+                    name: "USA".to_string(),
+                    count: 2
+                },
+            ]
+        );
+        assert_eq!(
+            stats.by_month[2],
+            Count {
+                name: "2024-02".to_string(),
+                count: 2
+            }
+        );
+        assert_eq!(stats.by_weekday.len(), 7);
+        assert_eq!(
+            stats.by_weekday[0],
+            Count {
+                name: "Monday".to_string(),
+                count: 2
+            }
+        );
+        assert_eq!(
+            stats.by_year,
+            vec![
+                YearCount {
+                    year: 2023,
+                    count: 1,
+                    change_percent: None,
+                },
+                YearCount {
+                    year: 2024,
+                    count: 3,
+                    change_percent: Some(200.0),
+                },
+            ]
+        );
+        assert_eq!(
+            stats.top_bands,
+            vec![Count {
+                name: "Band".to_string(),
+                count: 4
+            }]
+        );
+        assert_eq!(stats.top_callers, vec![]);
+    }
+
+    #[test]
+    fn chart_escapes_names() {
+        let svg = bar_chart(&[
+            Count {
+                name: "A & B".to_string(),
+                count: 2,
+            },
+            Count {
+                name: "C".to_string(),
+                count: 1,
+            },
+        ]);
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.contains(">A &amp; B</text>"));
+        assert!(svg.contains("width=\"400\""));
+        assert!(svg.contains("width=\"200\""));
+    }
+}
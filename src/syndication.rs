@@ -0,0 +1,303 @@
+// Copyright 2024 the dancelist authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! RSS and Atom feeds of events.
+
+use crate::{
+    icalendar::event_description,
+    model::{event::Event, history::EventHistory},
+};
+use atom_syndication::{Category, Content, Entry, Feed, FixedDateTime, Link, Person, Text};
+use axum::{
+    body::Body,
+    http::{header, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use rss::{ChannelBuilder, GuidBuilder, ItemBuilder};
+use serde::Deserialize;
+use std::cmp::Reverse;
+
+/// Options for how to order entries in a feed.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+pub struct FeedOptions {
+    #[serde(default)]
+    pub order: FeedOrder,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedOrder {
+    /// Upcoming events first, in order of start time.
+    #[default]
+    Start,
+    /// The most recently added events first.
+    Added,
+    /// By country, state and city, then by start time.
+    Location,
+}
+
+/// Sorts the given events in the given order.
+pub fn sort_events(events: &mut [&Event], history: &EventHistory, order: FeedOrder) {
+    events.sort_by_key(|event| event.time.start_time_sort_key());
+    // The sorts are stable, so events added at the same time or in the same place stay in order of
+    // start time.
+    match order {
+        FeedOrder::Start => {}
+        FeedOrder::Added => events.sort_by_key(|event| Reverse(history.first_seen(event))),
+        FeedOrder::Location => {
+            events.sort_by(|a, b| {
+                (&a.country, &a.state, &a.city).cmp(&(&b.country, &b.state, &b.city))
+            });
+        }
+    }
+}
+
+/// Makes an RSS 2.0 feed of the given events.
+///
+/// `link` should be the URL of the HTML page showing the same events.
+pub fn events_to_rss(events: &[&Event], history: &EventHistory, title: &str, link: &str) -> String {
+    let items = events
+        .iter()
+        .map(|event| {
+            ItemBuilder::default()
+                .title(Some(entry_title(event)))
+                .link(event.main_link().cloned())
+                .description(Some(event_description(event)))
+                .guid(Some(
+                    GuidBuilder::default()
+                        .value(event.uid())
+                        .permalink(false)
+                        .build(),
+                ))
+                .pub_date(history.first_seen(event).map(|time| time.to_rfc2822()))
+                .categories(
+                    event
+                        .styles
+                        .iter()
+                        .map(|style| rss::Category {
+                            name: style.to_string(),
+                            domain: None,
+                        })
+                        .collect::<Vec<_>>(),
+                )
+                .build()
+        })
+        .collect::<Vec<_>>();
+    ChannelBuilder::default()
+        .title(title)
+        .link(link)
+        .description(title)
+        .items(items)
+        .build()
+        .to_string()
+}
+
+/// Makes an Atom feed of the given events.
+///
+/// `link` should be the URL of the HTML page showing the same events, and is also used as the ID
+/// of the feed. `author` is the name of the site, which is given as the author of the feed.
+pub fn events_to_atom(
+    events: &[&Event],
+    history: &EventHistory,
+    title: &str,
+    link: &str,
+    author: &str,
+    now: DateTime<Utc>,
+) -> String {
+    let entries = events
+        .iter()
+        .map(|event| {
+            let updated = history.last_modified(event).unwrap_or(now);
+            let mut entry = Entry {
+                id: format!("urn:uuid:{}", event.uid()),
+                title: Text::plain(entry_title(event)),
+                updated: updated.fixed_offset(),
+                published: history.first_seen(event).map(|time| time.fixed_offset()),
+                summary: Some(Text::plain(event_description(event))),
+                categories: event
+                    .styles
+                    .iter()
+                    .map(|style| Category {
+                        term: style.tag().to_owned(),
+                        label: Some(style.to_string()),
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            };
+            if let Some(main_link) = event.main_link() {
+                entry.links = vec![Link {
+                    href: main_link.to_owned(),
+                    ..Default::default()
+                }];
+            }
+            if let Some(details) = &event.details {
+                entry.content = Some(Content {
+                    value: Some(details.to_owned()),
+                    content_type: Some("text".to_owned()),
+                    ..Default::default()
+                });
+            }
+            entry
+        })
+        .collect::<Vec<_>>();
+    let updated: FixedDateTime = entries
+        .iter()
+        .map(|entry| entry.updated)
+        .max()
+        .unwrap_or_else(|| now.fixed_offset());
+    Feed {
+        id: link.to_owned(),
+        title: Text::plain(title),
+        updated,
+        authors: vec![Person {
+            name: author.to_owned(),
+            ..Default::default()
+        }],
+        links: vec![Link {
+            href: link.to_owned(),
+            rel: "alternate".to_owned(),
+            ..Default::default()
+        }],
+        entries,
+        ..Default::default()
+    }
+    .to_string()
+}
+
+/// Makes a title for a feed entry, including the date and location of the event.
+fn entry_title(event: &Event) -> String {
+    format!(
+        "{}: {} in {}, {}",
+        event.time.start_date().format("%-d %B %Y"),
+        event.name,
+        event.city,
+        event.country
+    )
+}
+
+#[derive(Debug)]
+pub struct Rss(pub String);
+
+impl IntoResponse for Rss {
+    fn into_response(self) -> Response {
+        let mut res = Response::new(Body::from(self.0));
+        res.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/rss+xml"),
+        );
+        res
+    }
+}
+
+#[derive(Debug)]
+pub struct Atom(pub String);
+
+impl IntoResponse for Atom {
+    fn into_response(self) -> Response {
+        let mut res = Response::new(Body::from(self.0));
+        res.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/atom+xml"),
+        );
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{dancestyle::DanceStyle, event::EventTime, events::Events};
+    use chrono::{NaiveDate, TimeZone};
+
+    fn test_event(name: &str, date: NaiveDate) -> Event {
+        Event {
+            name: name.to_string(),
+            details: Some("Details".to_string()),
+            links: vec!["https://example.com/".to_string()],
+            time: EventTime::DateOnly {
+                start_date: date,
+                end_date: date,
+            },
+            country: "Country".to_string(),
+            state: None,
+            city: "City".to_string(),
+            styles: vec![DanceStyle::Contra],
+            workshop: false,
+            social: true,
+            bands: vec![],
+            callers: vec![],
+            price: None,
+            organisation: None,
+            cancelled: false,
+            source: None,
+        }
+    }
+
+    #[test]
+    fn sort_recently_added() {
+        let early = test_event("Early", NaiveDate::from_ymd_opt(2024, 1, 1).unwrap());
+        let late = test_event("Late", NaiveDate::from_ymd_opt(2024, 6, 1).unwrap());
+        let mut history = EventHistory::default();
+        history.record(
+            &Events {
+                events: vec![early.clone()],
+            },
+            Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap(),
+        );
+        history.record(
+            &Events {
+                events: vec![late.clone()],
+            },
+            Utc.with_ymd_and_hms(2023, 6, 1, 0, 0, 0).unwrap(),
+        );
+
+        let mut events = vec![&late, &early];
+        sort_events(&mut events, &history, FeedOrder::Start);
+        assert_eq!(events, vec![&early, &late]);
+        sort_events(&mut events, &history, FeedOrder::Added);
+        assert_eq!(events, vec![&late, &early]);
+    }
+
+    #[test]
+    fn rss_guid() {
+        let event = test_event("Dance", NaiveDate::from_ymd_opt(2024, 1, 1).unwrap());
+        let rss = events_to_rss(
+            &[&event],
+            &EventHistory::default(),
+            "Title",
+            "https://example.com/",
+        );
+        assert!(rss.contains(&format!(
+            "<guid isPermaLink=\"false\">{}</guid>",
+            event.uid()
+        )));
+        assert!(rss.contains("<title>1 January 2024: Dance in City, Country</title>"));
+    }
+
+    #[test]
+    fn atom_author() {
+        let event = test_event("Dance", NaiveDate::from_ymd_opt(2024, 1, 1).unwrap());
+        let atom = events_to_atom(
+            &[&event],
+            &EventHistory::default(),
+            "Title",
+            "https://example.com/",
+            "Site name",
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+        );
+        assert!(atom.contains("<author><name>Site name</name></author>"));
+    }
+}
@@ -91,6 +91,19 @@ pub const DEFAULT_TIMEZONES: [((&str, Option<&str>), Tz); 73] = [
     (("USA", Some("WY")), Tz::US__Mountain),
 ];
 
+/// Returns the default timezone for the given country and state, if known.
+pub fn default_timezone(country: &str, state: Option<&str>) -> Option<Tz> {
+    DEFAULT_TIMEZONES
+        .iter()
+        .find(|((c, s), _)| *c == country && *s == state)
+        .or_else(|| {
+            DEFAULT_TIMEZONES
+                .iter()
+                .find(|((c, s), _)| *c == country && s.is_none())
+        })
+        .map(|(_, timezone)| *timezone)
+}
+
 pub fn to_fixed_offset(date_time: DateTime<Tz>) -> DateTime<FixedOffset> {
     let fixed_offset = date_time.offset().fix();
     date_time.with_timezone(&fixed_offset)